@@ -6,6 +6,7 @@ use magic_pake::{
     ot::apricot,
     ot::chou_orlandi,
     ot::common::*,
+    util::SECURITY_PARAM,
 };
 
 use std::thread;
@@ -42,6 +43,8 @@ fn run_ot(msg: Vec<[Vec<u8>; 2]>, choices: Vec<bool>) {
 fn bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("OT|Messages");
     group.sample_size(10);
+    // Messages are wire labels, run with `--features label128` for 128-bit messages.
+    let name = format!("Apricot, {}-bit messages", SECURITY_PARAM);
 
     // Local
     for i in 3..=20 {
@@ -58,7 +61,7 @@ fn bench(c: &mut Criterion) {
         let choices = vec![false; n];
 
         group.throughput(criterion::Throughput::Elements(n as u64));
-        group.bench_with_input(BenchmarkId::new("Apricot", n), &n, |b, _| {
+        group.bench_with_input(BenchmarkId::new(&name, n), &n, |b, _| {
             b.iter(|| run_ot(enc.clone(), choices.clone()))
         });
     }
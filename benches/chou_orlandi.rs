@@ -3,11 +3,16 @@ use magic_pake::{
     circuit::build_circuit,
     common::raw,
     garble::{self, BinaryEncodingKey},
-    ot::chou_orlandi::*,
+    ot::chou_orlandi,
     ot::common::*,
+    ot::masny_rindal,
 };
 
-fn run_ot(msg: Vec<[Vec<u8>; 2]>, choices: Vec<bool>) {
+fn run_ot<S, R>(sender: S, receiver: R, msg: Vec<[Vec<u8>; 2]>, choices: Vec<bool>)
+where
+    S: ObliviousSender + Send + 'static,
+    R: ObliviousReceiver + Send + 'static,
+{
     let (s1, r1) = raw::new_local_channel();
     let (s2, r2) = raw::new_local_channel();
     let ch1 = (s1, r2);
@@ -19,14 +24,12 @@ fn run_ot(msg: Vec<[Vec<u8>; 2]>, choices: Vec<bool>) {
         .name("Sender".to_string())
         .spawn(move || {
             let msg = Message::from_zipped(&msg);
-            let sender = Sender;
             sender.exchange(&msg, &ch1).unwrap();
         });
 
     let h2 = thread::Builder::new()
         .name("Receiver".to_string())
         .spawn(move || {
-            let receiver = Receiver;
             let _ = receiver.exchange(&choices, &ch2).unwrap();
         });
 
@@ -53,7 +56,24 @@ fn bench(c: &mut Criterion) {
 
         group.throughput(criterion::Throughput::Elements(n as u64));
         group.bench_with_input(BenchmarkId::new("Chou-Orlandi", n), &i, |b, _| {
-            b.iter(|| run_ot(enc.clone(), choices.clone()))
+            b.iter(|| {
+                run_ot(
+                    chou_orlandi::Sender,
+                    chou_orlandi::Receiver,
+                    enc.clone(),
+                    choices.clone(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Masny-Rindal", n), &i, |b, _| {
+            b.iter(|| {
+                run_ot(
+                    masny_rindal::Sender,
+                    masny_rindal::Receiver,
+                    enc.clone(),
+                    choices.clone(),
+                )
+            })
         });
     }
 
@@ -1,6 +1,7 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use magic_pake::common::raw::new_local_channel;
 use magic_pake::fpake::*;
+use magic_pake::util::SECURITY_PARAM;
 use std::thread;
 
 fn fpake(password_size: usize) {
@@ -31,7 +32,9 @@ fn fpake(password_size: usize) {
 }
 
 fn bench_fpake(c: &mut Criterion) {
+    // Run with `--features label128` for 128-bit wire labels.
     let mut group = c.benchmark_group("fPAKE|Password bits");
+    let name = format!("Hamming Distance, {}-bit labels", SECURITY_PARAM);
     group.sample_size(10);
 
     for i in 6..=12 {
@@ -39,7 +42,7 @@ fn bench_fpake(c: &mut Criterion) {
         let bytes = bits / 8;
 
         group.throughput(Throughput::Bytes(bytes as u64));
-        let id = BenchmarkId::new("Hamming Distance", bits);
+        let id = BenchmarkId::new(&name, bits);
         group.bench_with_input(id, &bits, |b, _| b.iter(|| fpake(bytes)));
     }
 
@@ -2,24 +2,28 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 use magic_pake::circuit::build_circuit;
 use magic_pake::garble::{encode, evaluate, garble};
+use magic_pake::util::SECURITY_PARAM;
 
 fn bench_garble_eval(c: &mut Criterion) {
     let mut group = c.benchmark_group("Garbled Circuits|Password bits");
     group.sample_size(100);
+    // Run with `--features label128` for 128-bit wire labels.
+    let garble_name = format!("Garble, {}-bit labels", SECURITY_PARAM);
+    let evaluate_name = format!("Evaluate, {}-bit labels", SECURITY_PARAM);
 
     for i in 1..=16 {
         let bits = 1 << i;
 
         let circuit = build_circuit(bits, 8);
         group.throughput(criterion::Throughput::Elements(bits as u64));
-        group.bench_with_input(BenchmarkId::new("Garble", bits), &bits, |b, _| {
+        group.bench_with_input(BenchmarkId::new(&garble_name, bits), &bits, |b, _| {
             b.iter(|| garble(&circuit))
         });
 
         let (gc, e, _) = garble(&circuit);
         let x = encode(&e, &vec![1; 2 * bits]);
         group.throughput(criterion::Throughput::Elements(bits as u64));
-        group.bench_with_input(BenchmarkId::new("Evaluate", bits), &bits, |b, _| {
+        group.bench_with_input(BenchmarkId::new(&evaluate_name, bits), &bits, |b, _| {
             b.iter(|| evaluate(&gc, &x))
         });
     }
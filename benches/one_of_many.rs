@@ -2,11 +2,7 @@ use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use magic_pake::common::raw;
 use magic_pake::ot::one_of_many::*;
 
-fn log2(x: u32) -> u32 {
-    ((std::mem::size_of::<u32>() * 8) as u32 - (x - 1).leading_zeros()) as u32
-}
-
-fn one_of_many_chou_orlandi(n: u32, domain: u32, messages: Vec<Vec<u8>>) {
+fn one_of_many_chou_orlandi(n: u32, messages: Vec<Vec<u8>>) {
     let choice = n / 2;
 
     let (s1, r1) = raw::new_local_channel();
@@ -21,7 +17,7 @@ fn one_of_many_chou_orlandi(n: u32, domain: u32, messages: Vec<Vec<u8>>) {
             let sender = ManyOTSender {
                 interal_sender: Box::new(magic_pake::ot::chou_orlandi::Sender),
             };
-            sender.exchange(&messages, domain, &ch1).unwrap();
+            sender.exchange(&messages, &ch1).unwrap();
         });
 
     let h2 = thread::Builder::new()
@@ -30,7 +26,7 @@ fn one_of_many_chou_orlandi(n: u32, domain: u32, messages: Vec<Vec<u8>>) {
             let receiver = ManyOTReceiver {
                 internal_receiver: Box::new(magic_pake::ot::chou_orlandi::Receiver),
             };
-            receiver.exchange(choice, domain, &ch2).unwrap()
+            receiver.exchange(choice, n, &ch2).unwrap()
         });
 
     h1.unwrap().join().unwrap();
@@ -43,7 +39,6 @@ fn bench_1_of_n_ot(c: &mut Criterion) {
 
     for i in 2..=22u32 {
         let n = 1 << i;
-        let domain = log2(n);
 
         let mut messages = Vec::with_capacity(n as usize);
         for _ in 0..n {
@@ -53,7 +48,7 @@ fn bench_1_of_n_ot(c: &mut Criterion) {
         group.throughput(criterion::Throughput::Elements(n as u64));
         group.bench_with_input(BenchmarkId::new("Chou-Orlandi Based", n), &n, |b, _| {
             b.iter(|| {
-                one_of_many_chou_orlandi(n, domain, messages.clone());
+                one_of_many_chou_orlandi(n, messages.clone());
             })
         });
     }
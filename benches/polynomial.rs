@@ -3,7 +3,7 @@ use rand::Rng;
 use rand_chacha::ChaChaRng;
 use rand_core::SeedableRng;
 use magic_pake::ot::bitmatrix::BitVector;
-use magic_pake::ot::polynomial::{Polynomial, polynomial_mul_acc_generic};
+use magic_pake::ot::polynomial::{Polynomial, gf128_mul_acc, polynomial_mul_acc_generic};
 
 
 fn bench(c: &mut Criterion) {
@@ -24,23 +24,31 @@ fn bench(c: &mut Criterion) {
         });
     });
 
+    group.bench_function(BenchmarkId::new("Dispatched Implementation", 1), |bench| {
+        bench.iter(|| {
+            let mut c1 = BitVector::from_bytes(&[0x00; 16]);
+            gf128_mul_acc(&mut c1, &a, &b);
+            let _ = Polynomial::from(c1);
+        });
+    });
+
     #[cfg(target_arch = "x86_64")]
-    {
+    if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1") {
         group.bench_function(BenchmarkId::new("x86 Implementation", 1), |bench| {
             bench.iter(|| {
                 let mut c1 = BitVector::from_bytes(&[0x00; 16]);
-                magic_pake::ot::polynomial::polynomial_mul_acc_x86(&mut c1, &a, &b);
+                unsafe { magic_pake::ot::polynomial::polynomial_mul_acc_x86(&mut c1, &a, &b) };
                 let _ = Polynomial::from(c1);
             });
         });
     }
 
     #[cfg(target_arch = "aarch64")]
-    {
-        group.bench_function(BenchmarkId::new("x86 Implementation", 1), |bench| {
+    if std::arch::is_aarch64_feature_detected!("aes") {
+        group.bench_function(BenchmarkId::new("arm64 Implementation", 1), |bench| {
             bench.iter(|| {
                 let mut c1 = BitVector::from_bytes(&[0x00; 16]);
-                magic_pake::ot::polynomial::polynomial_mul_acc_arm64(&mut c1, &a, &b);
+                unsafe { magic_pake::ot::polynomial::polynomial_mul_acc_arm64(&mut c1, &a, &b) };
                 let _ = Polynomial::from(c1);
             });
         });
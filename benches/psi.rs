@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use magic_pake::common::raw;
+use magic_pake::ot::oprf;
+use magic_pake::psi;
+use std::thread;
+
+fn items(n: u32, offset: u32) -> Vec<[u8; 4]> {
+    (offset..offset + n).map(|x| x.to_be_bytes()).collect()
+}
+
+fn run_oprf(inputs: Vec<[u8; 4]>) {
+    let (ch1, ch2) = raw::local_channel_pair();
+    let h1 = thread::spawn(move || oprf::Sender::default().exchange(&ch1).unwrap());
+    let h2 = thread::spawn(move || oprf::Receiver::default().exchange(&inputs, &ch2).unwrap());
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+fn run_psi(sender: Vec<[u8; 4]>, receiver: Vec<[u8; 4]>) {
+    let (ch1, ch2) = raw::local_channel_pair();
+    let h1 = thread::spawn(move || psi::Sender::default().exchange(&sender, &ch1).unwrap());
+    let h2 = thread::spawn(move || psi::Receiver::default().exchange(&receiver, &ch2).unwrap());
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+fn bench_oprf(c: &mut Criterion) {
+    let mut group = c.benchmark_group("OPRF|Inputs");
+    group.sample_size(10);
+
+    for i in 10..=20u32 {
+        let n = 1 << i;
+        let inputs = items(n, 0);
+        group.throughput(criterion::Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::new("KKRT", n), &n, |b, _| {
+            b.iter(|| run_oprf(inputs.clone()))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_psi(c: &mut Criterion) {
+    let mut group = c.benchmark_group("PSI|Set size");
+    group.sample_size(10);
+
+    for i in 10..=20u32 {
+        let n = 1 << i;
+        // Half of the items are shared.
+        let sender = items(n, 0);
+        let receiver = items(n, n / 2);
+        group.throughput(criterion::Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::new("KKRT", n), &n, |b, _| {
+            b.iter(|| run_psi(sender.clone(), receiver.clone()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_oprf, bench_psi);
+criterion_main!(benches);
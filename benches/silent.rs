@@ -0,0 +1,82 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use magic_pake::common::raw;
+use magic_pake::ot::common::*;
+use magic_pake::ot::{apricot, chou_orlandi, silent};
+use std::thread;
+
+fn run_ot<S, R>(sender: fn() -> S, receiver: fn() -> R, msg: Vec<[[u8; 16]; 2]>, choices: Vec<bool>)
+where
+    S: ObliviousSender + 'static,
+    R: ObliviousReceiver + 'static,
+{
+    let (ch1, ch2) = raw::local_channel_pair();
+    let h1 = thread::spawn(move || {
+        let msg = Message::from_zipped(&msg);
+        sender().exchange(&msg, &ch1).unwrap();
+    });
+    let h2 = thread::spawn(move || receiver().exchange(&choices, &ch2).unwrap());
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+fn apricot_sender() -> apricot::Sender {
+    apricot::Sender {
+        bootstrap: Box::new(chou_orlandi::Receiver),
+    }
+}
+
+fn apricot_receiver() -> apricot::Receiver {
+    apricot::Receiver {
+        bootstrap: Box::new(chou_orlandi::Sender),
+    }
+}
+
+fn run_correlated(n: usize) {
+    let (ch1, ch2) = raw::local_channel_pair();
+    let h1 = thread::spawn(move || silent::Sender::default().correlated(n, &ch1).unwrap());
+    let h2 = thread::spawn(move || silent::Receiver::default().correlated(n, &ch2).unwrap());
+    h1.join().unwrap();
+    h2.join().unwrap();
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("OT extension|OTs");
+    group.sample_size(10);
+
+    // A single fPAKE login needs a few thousand OTs, the rest are large batches.
+    for i in [11, 20, 21, 22, 23] {
+        let n = 1 << i;
+        let msg = vec![[[0u8; 16], [1u8; 16]]; n];
+        let choices = vec![false; n];
+
+        group.throughput(criterion::Throughput::Elements(n as u64));
+        group.bench_with_input(BenchmarkId::new("Apricot", n), &n, |b, _| {
+            b.iter(|| {
+                run_ot(
+                    apricot_sender,
+                    apricot_receiver,
+                    msg.clone(),
+                    choices.clone(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Silent", n), &n, |b, _| {
+            b.iter(|| {
+                run_ot(
+                    silent::Sender::default,
+                    silent::Receiver::default,
+                    msg.clone(),
+                    choices.clone(),
+                )
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("Silent correlated", n), &n, |b, &n| {
+            b.iter(|| run_correlated(n))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
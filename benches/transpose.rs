@@ -0,0 +1,41 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use magic_pake::ot::bitmatrix::{BitMatrix, BitVector};
+use magic_pake::ot::transpose::{transpose, transpose_portable};
+use rand::Rng;
+use rand_chacha::ChaChaRng;
+use rand_core::SeedableRng;
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Transpose");
+    group.sample_size(10);
+
+    let mut rng = ChaChaRng::from_seed([0; 32]);
+    // The shape of an apricot matrix: 128 rows, one column per OT.
+    for log_cols in [10, 14, 18] {
+        let rows = 128;
+        let cols = 1 << log_cols;
+        let source: Vec<u8> = (0..rows * cols / 8).map(|_| rng.gen()).collect();
+        let mut target = vec![0u8; source.len()];
+
+        group.bench_function(BenchmarkId::new("SIMD", cols), |bench| {
+            bench.iter(|| transpose(&source, &mut target, rows, cols));
+        });
+
+        group.bench_function(BenchmarkId::new("Portable", cols), |bench| {
+            bench.iter(|| transpose_portable(&source, &mut target, rows, cols));
+        });
+
+        let matrix: BitMatrix = source
+            .chunks_exact(cols / 8)
+            .map(BitVector::from_bytes)
+            .collect();
+        group.bench_function(BenchmarkId::new("BitMatrix", cols), |bench| {
+            bench.iter(|| matrix.transpose());
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+// Bit matrices are exchanged during OT extension.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use magic_pake::ot::bitmatrix::BitMatrix;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(matrix) = bincode::deserialize::<BitMatrix>(data) {
+        let bytes = bincode::serialize(&matrix).unwrap();
+        assert!(bincode::deserialize::<BitMatrix>(&bytes).is_ok());
+    }
+});
@@ -0,0 +1,13 @@
+// Garbled circuits are received from the other party, deserializing must never panic.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use magic_pake::circuit::verify_circuit;
+use magic_pake::garble::GarbledCircuit;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(gc) = bincode::deserialize::<GarbledCircuit>(data) {
+        let _ = verify_circuit(&gc.circuit);
+        let bytes = bincode::serialize(&gc).unwrap();
+        assert!(bincode::deserialize::<GarbledCircuit>(&bytes).is_ok());
+    }
+});
@@ -0,0 +1,11 @@
+// Public keys of the Chou-Orlandi OT are received from the other party.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use magic_pake::ot::chou_orlandi::Public;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(public) = bincode::deserialize::<Public>(data) {
+        let bytes = bincode::serialize(&public).unwrap();
+        assert!(bincode::deserialize::<Public>(&bytes).is_ok());
+    }
+});
@@ -4,6 +4,9 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 // Tools for building circuits.
 
+pub mod dsl;
+pub mod export;
+
 /// A `Circuit` is a collection of gates, with a set of inputs and outputs.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Circuit {
@@ -24,7 +27,7 @@ pub struct Gate {
     pub kind: GateKind,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum GateKind {
     Add,
     Mul(u16),
@@ -45,9 +48,18 @@ impl Gate {
             GateKind::And => 2,
         }
     }
+
+    /// Number of ciphertexts the garbler has to send for this gate.
+    pub const fn ciphertexts(&self) -> usize {
+        match self.kind {
+            GateKind::Add | GateKind::Mul(_) => 0,
+            GateKind::Proj(_) => self.domain as usize,
+            GateKind::And => 2,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ProjKind {
     Map(u16),
     Less(u16),
@@ -156,49 +168,62 @@ impl Circuit {
             wires[i] = *input;
         }
         for gate in &self.gates {
-            let inputs = gate.inputs.iter().map(|&i| wires[i]);
-            let output: u16 = match gate.kind {
-                GateKind::Add => inputs.sum::<u16>() % gate.domain,
-                GateKind::Mul(m) => inputs.map(|x| x * m).next().unwrap(),
-                GateKind::Proj(ref p) => inputs.map(|x| p.project(x)).next().unwrap(),
-                GateKind::And => inputs.map(|x| x & 1).next().unwrap(),
-            };
+            // Computed in u32 so neither sums nor products overflow before being reduced.
+            let domain = gate.domain as u32;
+            let mut inputs = gate.inputs.iter().map(|&i| wires[i] as u32);
+            let output = match gate.kind {
+                GateKind::Add => inputs.sum::<u32>() % domain,
+                GateKind::Mul(m) => inputs.next().unwrap() * m as u32 % domain,
+                GateKind::Proj(ref p) => p.project(inputs.next().unwrap() as u16) as u32,
+                GateKind::And => inputs.product::<u32>() & 1,
+            } as u16;
             wires[gate.output] = output;
         }
         wires
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum CircuitError {
     BadOutputCount,
     BadInputCount,
     BadWireCount(usize, usize),
-    BadDomain,
+    BadDomain(usize),
+    BadArity(usize),
+    UndefinedWire(usize),
+    BadGateOrder(usize),
 }
 impl Error for CircuitError {}
 impl fmt::Display for CircuitError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            CircuitError::BadInputCount => {
-                write!(f, "Error, circuit input wires are used as output.")
+            Self::BadInputCount => {
+                write!(f, "Number of input domains does not match the inputs")
             }
-            CircuitError::BadOutputCount => write!(f, "Bad output count"),
-            CircuitError::BadWireCount(a, b) => {
+            Self::BadOutputCount => write!(f, "Bad output count"),
+            Self::BadWireCount(a, b) => {
                 write!(f, "Bad wire count, actual {a}, but {b} defined")
             }
-            CircuitError::BadDomain => write!(f, "Bad domain"),
+            Self::BadDomain(w) => write!(f, "Bad domain for wire {w}"),
+            Self::BadArity(w) => {
+                write!(f, "Gate {w} has the wrong number of inputs for its kind")
+            }
+            Self::UndefinedWire(w) => {
+                write!(f, "Wire {w} is read before it is assigned")
+            }
+            Self::BadGateOrder(w) => {
+                write!(f, "Gate output {w} is not the next free wire")
+            }
         }
     }
 }
 
 pub fn verify_circuit(circuit: &Circuit) -> Result<(), CircuitError> {
     // assert!(circuit.input_domains.len() == circuit.num_inputs, "Input domain amount, doesn't match amount of inputs");
-    let num_wires = circuit
-        .gates
-        .iter()
-        .flat_map(|g| &g.inputs)
-        .chain(circuit.gates.iter().map(|g| &g.output))
+    // Every input is a wire, even when it is never read.
+    let num_wires = (0..circuit.num_inputs)
+        .chain(circuit.gates.iter().flat_map(|g| g.inputs.iter().copied()))
+        .chain(circuit.gates.iter().map(|g| g.output))
         .unique()
         .count();
     if num_wires != circuit.num_wires {
@@ -214,6 +239,46 @@ pub fn verify_circuit(circuit: &Circuit) -> Result<(), CircuitError> {
     if !ok {
         return Err(CircuitError::BadOutputCount);
     }
+    if circuit.input_domains.len() != circuit.num_inputs {
+        return Err(CircuitError::BadInputCount);
+    }
+    // Every wire carries a value of domain at least 2.
+    let mut domains = Vec::with_capacity(circuit.num_wires);
+    for (w, &domain) in circuit.input_domains.iter().enumerate() {
+        if domain < 2 {
+            return Err(CircuitError::BadDomain(w));
+        }
+        domains.push(domain);
+    }
+    // Gates must be topologically sorted, each assigning the next wire.
+    for (i, gate) in circuit.gates.iter().enumerate() {
+        let next = circuit.num_inputs + i;
+        if let Some(&w) = gate.inputs.iter().find(|&&w| w >= next) {
+            return Err(CircuitError::UndefinedWire(w));
+        }
+        if gate.output != next {
+            return Err(CircuitError::BadGateOrder(gate.output));
+        }
+        let arity_ok = match gate.kind {
+            GateKind::Add => !gate.inputs.is_empty(),
+            GateKind::Mul(_) | GateKind::Proj(_) => gate.inputs.len() == 1,
+            GateKind::And => gate.inputs.len() == 2,
+        };
+        if !arity_ok {
+            return Err(CircuitError::BadArity(gate.output));
+        }
+        // Gates compute on at least bits, and their inputs have to be in the domain of the gate.
+        let bad_domain = gate.domain < 2
+            || (gate.kind == GateKind::And && gate.domain != 2)
+            || gate.output_domain() < 2;
+        if bad_domain {
+            return Err(CircuitError::BadDomain(gate.output));
+        }
+        if let Some(&w) = gate.inputs.iter().find(|&&w| domains[w] != gate.domain) {
+            return Err(CircuitError::BadDomain(w));
+        }
+        domains.push(gate.output_domain());
+    }
     Ok(())
 }
 
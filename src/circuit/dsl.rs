@@ -0,0 +1,1218 @@
+// A small language for describing circuits.
+//
+// ```text
+// let n = 8;
+// input x: bit[n] from garbler;
+// input y: bit[n] from evaluator;
+// output sum i in 0..n { proj(x[i] xor y[i], n + 1) } < 3;
+// ```
+//
+// Inputs are typed, either `bit` or `mod m`, optionally as arrays, and belong to a party.
+// Expressions are built from `+`, `xor`, `and`, multiplication by a constant `*`,
+// `proj(e, m)` (reduce into domain `m`), `table(e, m, [v0, v1, ...])` (map value `x` of `e` to
+// `vx` in domain `m`), comparison `e < t` and `sum i in a..b { e }`.
+// Integer expressions are evaluated while compiling, and can be used as array indices,
+// domains, constants and bounds. Compilation shares common subexpressions, merges nested
+// additions, removes identity gates and drops anything that does not reach an output.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::*;
+
+/// Position in the source, 1-indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl Error for CompileError {}
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+type Result<T> = std::result::Result<T, CompileError>;
+
+/// An input declared in the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input {
+    pub name: String,
+    pub party: String,
+    pub domain: u16,
+    /// The circuit input wires holding this input, one per array element.
+    pub wires: Range<usize>,
+}
+
+/// A compiled program.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub circuit: Circuit,
+    pub inputs: Vec<Input>,
+}
+
+impl Program {
+    /// Input wires belonging to `party`, in declaration order.
+    pub fn party_wires(&self, party: &str) -> Vec<usize> {
+        self.inputs
+            .iter()
+            .filter(|i| i.party == party)
+            .flat_map(|i| i.wires.clone())
+            .collect()
+    }
+}
+
+/// Compile `source` into a circuit.
+pub fn compile(source: &str) -> Result<Program> {
+    let tokens = lex(source)?;
+    let program = Parser { tokens, pos: 0 }.program()?;
+    Compiler::default().compile(&program)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Lexer
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Ident(String),
+    Int(i64),
+    Punct(&'static str),
+    Eof,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(s) => write!(f, "`{s}`"),
+            Self::Int(i) => write!(f, "`{i}`"),
+            Self::Punct(p) => write!(f, "`{p}`"),
+            Self::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+const PUNCTS: [&str; 14] = [
+    "..", ":", ";", ",", "[", "]", "(", ")", "{", "}", "+", "-", "*", "<",
+];
+
+fn lex(source: &str) -> Result<Vec<(Tok, Span)>> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    while i < chars.len() {
+        let span = Span { line, column };
+        let c = chars[i];
+        let start = i;
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text
+                .parse()
+                .map_err(|_| CompileError::new(span, "integer literal too large"))?;
+            tokens.push((Tok::Int(value), span));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Tok::Ident(chars[start..i].iter().collect()), span));
+        } else if c == '=' {
+            i += 1;
+            tokens.push((Tok::Punct("="), span));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = PUNCTS
+                .iter()
+                .find(|p| rest.starts_with(*p))
+                .ok_or_else(|| CompileError::new(span, format!("unexpected character `{c}`")))?;
+            i += punct.len();
+            tokens.push((Tok::Punct(punct), span));
+        }
+        column += i - start;
+    }
+    tokens.push((Tok::Eof, Span { line, column }));
+    Ok(tokens)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Parser
+
+#[derive(Debug, Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Xor,
+    And,
+    Less,
+}
+
+#[derive(Debug, Clone)]
+enum ExprKind {
+    Int(i64),
+    Var(String),
+    Index(Box<Expr>, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Proj(Box<Expr>, Box<Expr>),
+    Table(Box<Expr>, Box<Expr>, Vec<Expr>),
+    Sum {
+        var: String,
+        from: Box<Expr>,
+        to: Box<Expr>,
+        body: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Expr {
+    kind: ExprKind,
+    span: Span,
+}
+
+#[derive(Debug, Clone)]
+enum Stmt {
+    Input {
+        name: String,
+        domain: Expr,
+        len: Option<Expr>,
+        party: String,
+        span: Span,
+    },
+    Let {
+        name: String,
+        expr: Expr,
+    },
+    Output(Expr),
+}
+
+const KEYWORDS: [&str; 11] = [
+    "input", "from", "let", "output", "sum", "in", "xor", "and", "proj", "table", "mod",
+];
+
+struct Parser {
+    tokens: Vec<(Tok, Span)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Tok, Span) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Tok::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn is(&self, word: &str) -> bool {
+        match self.peek() {
+            Tok::Ident(s) => s == word,
+            Tok::Punct(p) => *p == word,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.is(word);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, word: &str) -> Result<Span> {
+        let span = self.span();
+        if self.eat(word) {
+            Ok(span)
+        } else {
+            Err(CompileError::new(
+                span,
+                format!("expected `{word}`, found {}", self.peek()),
+            ))
+        }
+    }
+
+    fn ident(&mut self) -> Result<(String, Span)> {
+        match self.next() {
+            (Tok::Ident(s), span) if !KEYWORDS.contains(&s.as_str()) => Ok((s, span)),
+            (tok, span) => Err(CompileError::new(
+                span,
+                format!("expected identifier, found {tok}"),
+            )),
+        }
+    }
+
+    fn program(mut self) -> Result<Vec<Stmt>> {
+        let mut stmts = Vec::new();
+        while *self.peek() != Tok::Eof {
+            stmts.push(self.stmt()?);
+        }
+        Ok(stmts)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt> {
+        let span = self.span();
+        let stmt = if self.eat("input") {
+            let (name, _) = self.ident()?;
+            self.expect(":")?;
+            let domain = if self.eat("mod") {
+                self.atom()?
+            } else {
+                let span = self.expect("bit")?;
+                Expr {
+                    kind: ExprKind::Int(2),
+                    span,
+                }
+            };
+            let len = if self.eat("[") {
+                let len = self.expr()?;
+                self.expect("]")?;
+                Some(len)
+            } else {
+                None
+            };
+            self.expect("from")?;
+            let (party, _) = self.ident()?;
+            Stmt::Input {
+                name,
+                domain,
+                len,
+                party,
+                span,
+            }
+        } else if self.eat("let") {
+            let (name, _) = self.ident()?;
+            self.expect("=")?;
+            let expr = self.expr()?;
+            Stmt::Let { name, expr }
+        } else if self.eat("output") {
+            Stmt::Output(self.expr()?)
+        } else {
+            return Err(CompileError::new(
+                span,
+                format!("expected `input`, `let` or `output`, found {}", self.peek()),
+            ));
+        };
+        self.expect(";")?;
+        Ok(stmt)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+        let span = self.span();
+        if self.eat("<") {
+            let right = self.additive()?;
+            return Ok(binary(BinOp::Less, left, right, span));
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let span = self.span();
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else if self.eat("xor") {
+                BinOp::Xor
+            } else {
+                return Ok(left);
+            };
+            let right = self.multiplicative()?;
+            left = binary(op, left, right, span);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.postfix()?;
+        loop {
+            let span = self.span();
+            let op = if self.eat("*") {
+                BinOp::Mul
+            } else if self.eat("and") {
+                BinOp::And
+            } else {
+                return Ok(left);
+            };
+            let right = self.postfix()?;
+            left = binary(op, left, right, span);
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        while self.is("[") {
+            self.expect("[")?;
+            let index = self.expr()?;
+            self.expect("]")?;
+            expr = Expr {
+                span: expr.span,
+                kind: ExprKind::Index(Box::new(expr), Box::new(index)),
+            };
+        }
+        Ok(expr)
+    }
+
+    /// A literal, a variable or a parenthesized expression.
+    fn atom(&mut self) -> Result<Expr> {
+        let span = self.span();
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        if let Tok::Int(i) = *self.peek() {
+            self.pos += 1;
+            return Ok(Expr {
+                kind: ExprKind::Int(i),
+                span,
+            });
+        }
+        let (name, span) = self.ident()?;
+        Ok(Expr {
+            kind: ExprKind::Var(name),
+            span,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let span = self.span();
+        if self.eat("proj") {
+            self.expect("(")?;
+            let expr = self.expr()?;
+            self.expect(",")?;
+            let range = self.expr()?;
+            self.expect(")")?;
+            return Ok(Expr {
+                kind: ExprKind::Proj(Box::new(expr), Box::new(range)),
+                span,
+            });
+        }
+        if self.eat("table") {
+            self.expect("(")?;
+            let expr = self.expr()?;
+            self.expect(",")?;
+            let range = self.expr()?;
+            self.expect(",")?;
+            self.expect("[")?;
+            let mut values = vec![self.expr()?];
+            while self.eat(",") {
+                values.push(self.expr()?);
+            }
+            self.expect("]")?;
+            self.expect(")")?;
+            return Ok(Expr {
+                kind: ExprKind::Table(Box::new(expr), Box::new(range), values),
+                span,
+            });
+        }
+        if self.eat("sum") {
+            let (var, _) = self.ident()?;
+            self.expect("in")?;
+            let from = self.additive()?;
+            self.expect("..")?;
+            let to = self.additive()?;
+            self.expect("{")?;
+            let body = self.expr()?;
+            self.expect("}")?;
+            return Ok(Expr {
+                kind: ExprKind::Sum {
+                    var,
+                    from: Box::new(from),
+                    to: Box::new(to),
+                    body: Box::new(body),
+                },
+                span,
+            });
+        }
+        self.atom()
+    }
+}
+
+fn binary(op: BinOp, left: Expr, right: Expr, span: Span) -> Expr {
+    Expr {
+        kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+        span,
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Compiler
+
+type NodeId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Input(usize),
+    Gate {
+        kind: GateKind,
+        inputs: Vec<NodeId>,
+        domain: u16,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(i64),
+    Wire(NodeId),
+    Array(Vec<NodeId>),
+}
+
+/// The wire in `value`, which was evaluated from the expression at `span`.
+fn as_wire(value: Value, span: Span) -> Result<NodeId> {
+    match value {
+        Value::Wire(w) => Ok(w),
+        Value::Int(_) => Err(CompileError::new(span, "expected a wire, found an integer")),
+        Value::Array(_) => Err(CompileError::new(span, "expected a wire, found an array")),
+    }
+}
+
+#[derive(Default)]
+struct Compiler {
+    nodes: Vec<Node>,
+    /// Output domain of every node.
+    domains: Vec<u16>,
+    cache: HashMap<Node, NodeId>,
+    scope: HashMap<String, Value>,
+    inputs: Vec<Input>,
+    input_domains: Vec<u16>,
+    outputs: Vec<NodeId>,
+}
+
+impl Compiler {
+    fn compile(mut self, program: &[Stmt]) -> Result<Program> {
+        for stmt in program {
+            match stmt {
+                Stmt::Input {
+                    name,
+                    domain,
+                    len,
+                    party,
+                    span,
+                } => {
+                    if self.inputs.iter().any(|i| i.name == *name) {
+                        return Err(CompileError::new(
+                            *span,
+                            format!("input `{name}` is already declared"),
+                        ));
+                    }
+                    let domain = self.domain(domain)?;
+                    let value = match len {
+                        Some(len) => {
+                            let n = self.int(len)?;
+                            if n < 1 {
+                                return Err(CompileError::new(len.span, "array must not be empty"));
+                            }
+                            Value::Array((0..n).map(|_| self.input(domain)).collect())
+                        }
+                        None => Value::Wire(self.input(domain)),
+                    };
+                    let end = self.input_domains.len();
+                    let start = match &value {
+                        Value::Array(a) => end - a.len(),
+                        _ => end - 1,
+                    };
+                    self.inputs.push(Input {
+                        name: name.clone(),
+                        party: party.clone(),
+                        domain,
+                        wires: start..end,
+                    });
+                    self.scope.insert(name.clone(), value);
+                }
+                Stmt::Let { name, expr } => {
+                    let value = self.eval(expr)?;
+                    self.scope.insert(name.clone(), value);
+                }
+                Stmt::Output(expr) => {
+                    let node = self.wire(expr)?;
+                    self.outputs.push(node);
+                }
+            }
+        }
+        if self.outputs.is_empty() {
+            return Err(CompileError::new(
+                Span { line: 1, column: 1 },
+                "program has no outputs",
+            ));
+        }
+        let circuit = self.emit();
+        debug_assert!(verify_circuit(&circuit).is_ok());
+        Ok(Program {
+            circuit,
+            inputs: self.inputs,
+        })
+    }
+
+    fn input(&mut self, domain: u16) -> NodeId {
+        let wire = self.input_domains.len();
+        self.input_domains.push(domain);
+        self.nodes.push(Node::Input(wire));
+        self.domains.push(domain);
+        self.nodes.len() - 1
+    }
+
+    fn gate(&mut self, kind: GateKind, mut inputs: Vec<NodeId>, domain: u16) -> NodeId {
+        if matches!(kind, GateKind::Add | GateKind::And) {
+            inputs.sort_unstable();
+        }
+        let node = Node::Gate {
+            kind,
+            inputs,
+            domain,
+        };
+        if let Some(&id) = self.cache.get(&node) {
+            return id;
+        }
+        let output_domain = Gate {
+            output: 0,
+            domain,
+            inputs: vec![],
+            kind,
+        }
+        .output_domain();
+        self.nodes.push(node.clone());
+        self.domains.push(output_domain);
+        let id = self.nodes.len() - 1;
+        self.cache.insert(node, id);
+        id
+    }
+
+    fn int(&mut self, expr: &Expr) -> Result<i64> {
+        match self.eval(expr)? {
+            Value::Int(i) => Ok(i),
+            _ => Err(CompileError::new(expr.span, "expected an integer")),
+        }
+    }
+
+    fn small(&mut self, expr: &Expr, what: &str) -> Result<u16> {
+        let i = self.int(expr)?;
+        u16::try_from(i)
+            .map_err(|_| CompileError::new(expr.span, format!("{what} {i} out of range")))
+    }
+
+    fn domain(&mut self, expr: &Expr) -> Result<u16> {
+        let m = self.small(expr, "domain")?;
+        if !(2..u16::MAX).contains(&m) {
+            return Err(CompileError::new(
+                expr.span,
+                format!("domain {m} out of range"),
+            ));
+        }
+        Ok(m)
+    }
+
+    fn wire(&mut self, expr: &Expr) -> Result<NodeId> {
+        let value = self.eval(expr)?;
+        as_wire(value, expr.span)
+    }
+
+    /// The bit in `value`, which was evaluated from the expression at `span`.
+    fn bit(&self, value: Value, span: Span) -> Result<NodeId> {
+        let w = as_wire(value, span)?;
+        if self.domains[w] != 2 {
+            return Err(CompileError::new(
+                span,
+                format!("expected a bit, found mod {}", self.domains[w]),
+            ));
+        }
+        Ok(w)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Int(i) => Ok(Value::Int(*i)),
+            ExprKind::Var(name) => self
+                .scope
+                .get(name)
+                .cloned()
+                .ok_or_else(|| CompileError::new(span, format!("unknown name `{name}`"))),
+            ExprKind::Index(base, index) => {
+                let array = match self.eval(base)? {
+                    Value::Array(a) => a,
+                    _ => return Err(CompileError::new(base.span, "only arrays can be indexed")),
+                };
+                let i = self.int(index)?;
+                usize::try_from(i)
+                    .ok()
+                    .and_then(|i| array.get(i))
+                    .map(|&w| Value::Wire(w))
+                    .ok_or_else(|| {
+                        CompileError::new(
+                            index.span,
+                            format!("index {i} out of bounds for length {}", array.len()),
+                        )
+                    })
+            }
+            ExprKind::Binary(op, left, right) => self.binary(*op, left, right, span),
+            ExprKind::Proj(expr, range) => {
+                let w = self.wire(expr)?;
+                let m = self.domain(range)?;
+                if m == self.domains[w] {
+                    return Ok(Value::Wire(w));
+                }
+                let domain = self.domains[w];
+                Ok(Value::Wire(self.gate(
+                    GateKind::Proj(ProjKind::Map(m)),
+                    vec![w],
+                    domain,
+                )))
+            }
+            ExprKind::Table(expr, range, values) => {
+                let w = self.wire(expr)?;
+                let m = self.domain(range)?;
+                let domain = self.domains[w];
+                if values.len() != domain as usize {
+                    return Err(CompileError::new(
+                        span,
+                        format!(
+                            "table over mod {domain} needs {domain} values, found {}",
+                            values.len()
+                        ),
+                    ));
+                }
+                let mut table = Vec::with_capacity(values.len());
+                for value in values {
+                    let v = self.int(value)?;
+                    if !(0..m as i64).contains(&v) {
+                        return Err(CompileError::new(
+                            value.span,
+                            format!("table value {v} out of range for mod {m}"),
+                        ));
+                    }
+                    table.push(v as u16);
+                }
+                Ok(Value::Wire(self.table(w, m, &table)))
+            }
+            ExprKind::Sum {
+                var,
+                from,
+                to,
+                body,
+            } => {
+                let (from, to) = (self.int(from)?, self.int(to)?);
+                if from >= to {
+                    return Err(CompileError::new(
+                        span,
+                        format!("empty sum over {from}..{to}"),
+                    ));
+                }
+                let shadowed = self.scope.remove(var);
+                let mut terms = Vec::new();
+                for i in from..to {
+                    self.scope.insert(var.clone(), Value::Int(i));
+                    terms.push((self.wire(body)?, body.span));
+                }
+                self.scope.remove(var);
+                if let Some(value) = shadowed {
+                    self.scope.insert(var.clone(), value);
+                }
+                let domain = self.domains[terms[0].0];
+                if let Some((w, span)) = terms.iter().find(|(w, _)| self.domains[*w] != domain) {
+                    return Err(CompileError::new(
+                        *span,
+                        format!(
+                            "terms of the sum must share a domain, found mod {} and mod {}",
+                            domain, self.domains[*w]
+                        ),
+                    ));
+                }
+                let terms = terms.into_iter().map(|(w, _)| w).collect();
+                Ok(Value::Wire(self.add(terms, domain)))
+            }
+        }
+    }
+
+    fn add(&mut self, terms: Vec<NodeId>, domain: u16) -> NodeId {
+        if terms.len() == 1 {
+            return terms[0];
+        }
+        self.gate(GateKind::Add, terms, domain)
+    }
+
+    /// `table[x]` in domain `m` for the wire `x` of domain `table.len()`, built from the
+    /// comparisons `[x < v]` as `table[k - 1] * [x < k] + sum (table[v - 1] - table[v]) * [x < v]`
+    /// over `v` in `1..k`, where `[x < k]` is always one.
+    fn table(&mut self, x: NodeId, m: u16, table: &[u16]) -> NodeId {
+        let (k, domain) = (table.len(), self.domains[x]);
+        let mut terms = Vec::new();
+        for v in 1..=k {
+            let c = if v == k {
+                table[k - 1]
+            } else {
+                ((table[v - 1] as u32 + m as u32 - table[v] as u32) % m as u32) as u16
+            };
+            // Keep the last term of an all zero table, so there is a wire.
+            if c == 0 && !(v == k && terms.is_empty()) {
+                continue;
+            }
+            let less = self.gate(GateKind::Proj(ProjKind::Less(v as u16)), vec![x], domain);
+            let lifted = if m == 2 {
+                less
+            } else {
+                self.gate(GateKind::Proj(ProjKind::Map(m)), vec![less], 2)
+            };
+            terms.push(if c == 1 {
+                lifted
+            } else {
+                self.gate(GateKind::Mul(c), vec![lifted], m)
+            });
+        }
+        self.add(terms, m)
+    }
+
+    fn binary(&mut self, op: BinOp, left: &Expr, right: &Expr, span: Span) -> Result<Value> {
+        let l = self.eval(left)?;
+        let r = self.eval(right)?;
+        let overflow = || CompileError::new(span, "integer overflow");
+        match (op, l, r) {
+            (BinOp::Add, Value::Int(a), Value::Int(b)) => {
+                a.checked_add(b).map(Value::Int).ok_or_else(overflow)
+            }
+            (BinOp::Sub, Value::Int(a), Value::Int(b)) => {
+                a.checked_sub(b).map(Value::Int).ok_or_else(overflow)
+            }
+            (BinOp::Mul, Value::Int(a), Value::Int(b)) => {
+                a.checked_mul(b).map(Value::Int).ok_or_else(overflow)
+            }
+            (BinOp::Less, Value::Int(a), Value::Int(b)) => Ok(Value::Int((a < b) as i64)),
+            (BinOp::Add, Value::Wire(a), Value::Wire(b)) => {
+                let domain = self.domains[a];
+                if self.domains[b] != domain {
+                    return Err(CompileError::new(
+                        span,
+                        format!(
+                            "cannot add mod {} and mod {}, use `proj` to change domain",
+                            domain, self.domains[b]
+                        ),
+                    ));
+                }
+                Ok(Value::Wire(self.add(vec![a, b], domain)))
+            }
+            (BinOp::Xor, l, r) => {
+                let (a, b) = (self.bit(l, left.span)?, self.bit(r, right.span)?);
+                Ok(Value::Wire(self.add(vec![a, b], 2)))
+            }
+            (BinOp::And, l, r) => {
+                let (a, b) = (self.bit(l, left.span)?, self.bit(r, right.span)?);
+                Ok(Value::Wire(self.gate(GateKind::And, vec![a, b], 2)))
+            }
+            (BinOp::Mul, Value::Wire(w), Value::Int(c))
+            | (BinOp::Mul, Value::Int(c), Value::Wire(w)) => {
+                let domain = self.domains[w];
+                let c = c.rem_euclid(domain as i64) as u16;
+                if c == 1 {
+                    return Ok(Value::Wire(w));
+                }
+                Ok(Value::Wire(self.gate(GateKind::Mul(c), vec![w], domain)))
+            }
+            (BinOp::Less, Value::Wire(w), Value::Int(t)) => {
+                let t = u16::try_from(t).map_err(|_| {
+                    CompileError::new(right.span, format!("threshold {t} out of range"))
+                })?;
+                let domain = self.domains[w];
+                Ok(Value::Wire(self.gate(
+                    GateKind::Proj(ProjKind::Less(t)),
+                    vec![w],
+                    domain,
+                )))
+            }
+            (BinOp::Mul, Value::Wire(_), Value::Wire(_)) => Err(CompileError::new(
+                span,
+                "wires can only be multiplied by constants, use `and` for bits",
+            )),
+            (BinOp::Less, _, _) => Err(CompileError::new(
+                span,
+                "comparisons must be between a wire and a constant",
+            )),
+            (BinOp::Sub, _, _) => Err(CompileError::new(span, "`-` is only defined on integers")),
+            _ => Err(CompileError::new(
+                span,
+                "invalid operands, constants can only be used with `*` and `<`",
+            )),
+        }
+    }
+
+    /// Lower the graph to a circuit, with the outputs as the last wires.
+    fn emit(&self) -> Circuit {
+        let num_inputs = self.input_domains.len();
+
+        // Count users of every node reachable from the outputs.
+        let mut users = vec![0usize; self.nodes.len()];
+        let mut seen = vec![false; self.nodes.len()];
+        let mut stack = self.outputs.clone();
+        while let Some(id) = stack.pop() {
+            if std::mem::replace(&mut seen[id], true) {
+                continue;
+            }
+            if let Node::Gate { inputs, .. } = &self.nodes[id] {
+                for &i in inputs {
+                    users[i] += 1;
+                    stack.push(i);
+                }
+            }
+        }
+
+        // Merge additions only feeding into another addition of the same domain.
+        let is_output = |id: NodeId| self.outputs.contains(&id);
+        let mergeable = |id: NodeId, domain: u16| {
+            matches!(&self.nodes[id], Node::Gate { kind: GateKind::Add, domain: d, .. } if *d == domain)
+                && users[id] == 1
+                && !is_output(id)
+        };
+        let inputs_of = |id: NodeId| -> Vec<NodeId> {
+            let (kind, inputs, domain) = match &self.nodes[id] {
+                Node::Gate {
+                    kind,
+                    inputs,
+                    domain,
+                } => (kind, inputs, *domain),
+                Node::Input(_) => return vec![],
+            };
+            if *kind != GateKind::Add {
+                return inputs.clone();
+            }
+            let mut merged = Vec::new();
+            let mut stack: Vec<NodeId> = inputs.iter().rev().copied().collect();
+            while let Some(i) = stack.pop() {
+                match &self.nodes[i] {
+                    Node::Gate { inputs, .. } if mergeable(i, domain) => {
+                        stack.extend(inputs.iter().rev());
+                    }
+                    _ => merged.push(i),
+                }
+            }
+            merged
+        };
+
+        // Topological order of the gates, leaving outputs nothing depends on for last.
+        let inputs: Vec<Vec<NodeId>> = (0..self.nodes.len()).map(inputs_of).collect();
+        let mut has_users = vec![false; self.nodes.len()];
+        let mut order = Vec::new();
+        let mut visited = vec![false; self.nodes.len()];
+        for &out in &self.outputs {
+            self.visit(out, &inputs, &mut visited, &mut order, &mut has_users);
+        }
+        let mut deferred: Vec<NodeId> = Vec::new();
+        for &out in &self.outputs {
+            if matches!(self.nodes[out], Node::Gate { .. })
+                && !has_users[out]
+                && !deferred.contains(&out)
+            {
+                deferred.push(out);
+            }
+        }
+
+        let mut wires: HashMap<NodeId, usize> = HashMap::new();
+        for (id, node) in self.nodes.iter().enumerate() {
+            if let Node::Input(w) = node {
+                wires.insert(id, *w);
+            }
+        }
+        let mut gates = Vec::new();
+        let push = |id: NodeId, wires: &mut HashMap<NodeId, usize>, gates: &mut Vec<Gate>| {
+            let (kind, domain) = match &self.nodes[id] {
+                Node::Gate { kind, domain, .. } => (*kind, *domain),
+                Node::Input(_) => unreachable!(),
+            };
+            let output = num_inputs + gates.len();
+            gates.push(Gate {
+                output,
+                domain,
+                inputs: inputs[id].iter().map(|i| wires[i]).collect(),
+                kind,
+            });
+            wires.insert(id, output);
+        };
+        for &id in order.iter().filter(|id| !deferred.contains(id)) {
+            push(id, &mut wires, &mut gates);
+        }
+        for &out in &self.outputs {
+            if deferred.contains(&out) && !wires.contains_key(&out) {
+                push(out, &mut wires, &mut gates);
+            } else {
+                // Copy the value onto a fresh output wire.
+                let output = num_inputs + gates.len();
+                gates.push(Gate {
+                    output,
+                    domain: self.domains[out],
+                    inputs: vec![wires[&out]],
+                    kind: GateKind::Add,
+                });
+            }
+        }
+
+        Circuit {
+            num_wires: num_inputs + gates.len(),
+            num_inputs,
+            num_outputs: self.outputs.len(),
+            gates,
+            input_domains: self.input_domains.clone(),
+        }
+    }
+
+    fn visit(
+        &self,
+        id: NodeId,
+        inputs: &[Vec<NodeId>],
+        visited: &mut [bool],
+        order: &mut Vec<NodeId>,
+        has_users: &mut [bool],
+    ) {
+        if visited[id] || matches!(self.nodes[id], Node::Input(_)) {
+            return;
+        }
+        visited[id] = true;
+        for &i in &inputs[id] {
+            has_users[i] = true;
+            self.visit(i, inputs, visited, order, has_users);
+        }
+        order.push(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::garble::*;
+
+    const HAMMING: &str = "
+        // Hamming distance below a threshold.
+        let n = 8;
+        input x: bit[n] from garbler;
+        input y: bit[n] from evaluator;
+        output sum i in 0..n { proj(x[i] xor y[i], n + 1) } < 3;
+    ";
+
+    fn garble_encode_eval_decode(c: &Circuit, x: &[u16]) -> Vec<u16> {
+        let (gc, e, d) = garble(c);
+        let x = encode(&e, x);
+        let z = evaluate(&gc, &x);
+        decode(&d, &z).unwrap()
+    }
+
+    #[test]
+    fn test_hamming() {
+        let program = compile(HAMMING).unwrap();
+        let circuit = &program.circuit;
+        verify_circuit(circuit).unwrap();
+        assert_eq!(circuit.gates.len(), build_circuit(8, 2).gates.len());
+        assert_eq!(program.party_wires("garbler"), (0..8).collect::<Vec<_>>());
+        assert_eq!(
+            program.party_wires("evaluator"),
+            (8..16).collect::<Vec<_>>()
+        );
+
+        let mut x = vec![0u16; 16];
+        assert_eq!(garble_encode_eval_decode(circuit, &x), [1]);
+        x[0] = 1;
+        x[9] = 1;
+        assert_eq!(garble_encode_eval_decode(circuit, &x), [1]);
+        x[2] = 1;
+        assert_eq!(garble_encode_eval_decode(circuit, &x), [0]);
+    }
+
+    #[test]
+    fn test_optimizations() {
+        let program = compile(
+            "
+            input a: mod 7 from garbler;
+            input b: mod 7 from evaluator;
+            input unused: bit from evaluator;
+            let s = a + b;
+            let t = (a + b) * 1 + proj(b, 7);
+            output s + a;
+            output t;
+        ",
+        )
+        .unwrap();
+        let circuit = &program.circuit;
+        // `a + b` is shared, so only the gates for the two outputs remain.
+        assert_eq!(circuit.gates.len(), 3);
+        assert_eq!(circuit.num_wires, 6);
+        let x = [3, 5, 0];
+        assert_eq!(
+            garble_encode_eval_decode(circuit, &x),
+            [(3 + 5 + 3) % 7, (3 + 5 + 5) % 7]
+        );
+    }
+
+    #[test]
+    fn test_merged_sum() {
+        let program = compile(
+            "
+            input a: mod 5[4] from garbler;
+            output a[0] + a[1] + a[2] + a[3] * 2;
+        ",
+        )
+        .unwrap();
+        let gates = &program.circuit.gates;
+        assert_eq!(gates.len(), 2);
+        assert_eq!(gates[1].inputs.len(), 4);
+        let x = [1, 2, 3, 4];
+        assert_eq!(
+            garble_encode_eval_decode(&program.circuit, &x),
+            [(1 + 2 + 3 + 8) % 5]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let program = compile(
+            "
+            input a: mod 3 from garbler;
+            input b: bit from evaluator;
+            output table(a, 5, [4, 0, 2]);
+            output table(b, 2, [1, 0]);
+            output table(a, 2, [0, 0, 0]);
+        ",
+        )
+        .unwrap();
+        let circuit = &program.circuit;
+        verify_circuit(circuit).unwrap();
+        for a in 0..3 {
+            for b in 0..2 {
+                let expected = [[4, 0, 2][a as usize], 1 - b, 0];
+                assert_eq!(garble_encode_eval_decode(circuit, &[a, b]), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_chain() {
+        // Every operand is evaluated once, so this does not take 2^n steps.
+        let n = 64;
+        let terms: Vec<String> = (0..n).map(|i| format!("x[{i}]")).collect();
+        let source = format!(
+            "input x: bit[{n}] from garbler;\noutput {};\noutput {};",
+            terms.join(" xor "),
+            terms.join(" and ")
+        );
+        let program = compile(&source).unwrap();
+        verify_circuit(&program.circuit).unwrap();
+        let mut x = vec![1u16; n];
+        assert_eq!(garble_encode_eval_decode(&program.circuit, &x), [0, 1]);
+        x[5] = 0;
+        assert_eq!(garble_encode_eval_decode(&program.circuit, &x), [1, 0]);
+    }
+
+    #[test]
+    fn test_outputs_in_use() {
+        let program = compile(
+            "
+            input a: bit[2] from garbler;
+            let c = a[0] and a[1];
+            output c;
+            output c xor a[0];
+            output a[1];
+        ",
+        )
+        .unwrap();
+        let circuit = &program.circuit;
+        verify_circuit(circuit).unwrap();
+        assert_eq!(circuit.num_outputs, 3);
+        for x in [[0, 0], [0, 1], [1, 0], [1, 1]] {
+            let expected = [x[0] & x[1], (x[0] & x[1]) ^ x[0], x[1]];
+            assert_eq!(garble_encode_eval_decode(circuit, &x), expected);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("input x: bit from a;\noutput y;", 2, 8, "unknown name `y`"),
+            (
+                "input x: bit[2] from a;\noutput x[2];",
+                2,
+                10,
+                "index 2 out of bounds",
+            ),
+            (
+                "input x: mod 3 from a;\ninput y: bit from a;\noutput x + y;",
+                3,
+                10,
+                "cannot add",
+            ),
+            (
+                "input x: bit from a;\noutput x * x;",
+                2,
+                10,
+                "only be multiplied",
+            ),
+            ("input x: bit from a\noutput x;", 2, 1, "expected `;`"),
+            (
+                "input x: mod 5 from a;\noutput x and x;",
+                2,
+                8,
+                "expected a bit",
+            ),
+            (
+                "input x: mod 3 from a;\noutput table(x, 2, [0, 1]);",
+                2,
+                8,
+                "needs 3 values",
+            ),
+            (
+                "input x: bit from a;\noutput table(x, 2, [0, 2]);",
+                2,
+                24,
+                "out of range",
+            ),
+            ("input x: mod 1 from a;", 1, 14, "domain 1 out of range"),
+            ("input x: bit from a;\noutput 3;", 2, 8, "found an integer"),
+            (
+                "input x: bit from a;\noutput x $ x;",
+                2,
+                10,
+                "unexpected character",
+            ),
+        ];
+        for (source, line, column, message) in cases {
+            let err = compile(source).unwrap_err();
+            assert_eq!(err.span, Span { line, column }, "{source}: {err}");
+            assert!(err.message.contains(message), "{source}: {err}");
+        }
+    }
+}
@@ -0,0 +1,372 @@
+// Exporters for inspecting circuits, Graphviz DOT and a stable JSON schema.
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// Version of the JSON schema written by `to_json`.
+pub const JSON_VERSION: u32 = 1;
+
+/// JSON representation of a `Circuit`.
+///
+/// Unlike the serde representation of `Circuit` itself this is independent of the internal
+/// layout, and is versioned so it can be authored outside of Rust.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonCircuit {
+    pub version: u32,
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+    pub num_wires: usize,
+    pub input_domains: Vec<u16>,
+    pub gates: Vec<JsonGate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum JsonGate {
+    Add {
+        inputs: Vec<usize>,
+        output: usize,
+        domain: u16,
+    },
+    Mul {
+        constant: u16,
+        input: usize,
+        output: usize,
+        domain: u16,
+    },
+    Proj {
+        range: u16,
+        input: usize,
+        output: usize,
+        domain: u16,
+    },
+    Less {
+        threshold: u16,
+        input: usize,
+        output: usize,
+        domain: u16,
+    },
+    And {
+        inputs: [usize; 2],
+        output: usize,
+    },
+}
+
+impl From<&Gate> for JsonGate {
+    fn from(gate: &Gate) -> Self {
+        let (output, domain) = (gate.output, gate.domain);
+        match gate.kind {
+            GateKind::Add => Self::Add {
+                inputs: gate.inputs.clone(),
+                output,
+                domain,
+            },
+            GateKind::Mul(constant) => Self::Mul {
+                constant,
+                input: gate.inputs[0],
+                output,
+                domain,
+            },
+            GateKind::Proj(ProjKind::Map(range)) => Self::Proj {
+                range,
+                input: gate.inputs[0],
+                output,
+                domain,
+            },
+            GateKind::Proj(ProjKind::Less(threshold)) => Self::Less {
+                threshold,
+                input: gate.inputs[0],
+                output,
+                domain,
+            },
+            GateKind::And => Self::And {
+                inputs: [gate.inputs[0], gate.inputs[1]],
+                output,
+            },
+        }
+    }
+}
+
+impl From<JsonGate> for Gate {
+    fn from(gate: JsonGate) -> Self {
+        let (kind, inputs, output, domain) = match gate {
+            JsonGate::Add {
+                inputs,
+                output,
+                domain,
+            } => (GateKind::Add, inputs, output, domain),
+            JsonGate::Mul {
+                constant,
+                input,
+                output,
+                domain,
+            } => (GateKind::Mul(constant), vec![input], output, domain),
+            JsonGate::Proj {
+                range,
+                input,
+                output,
+                domain,
+            } => (
+                GateKind::Proj(ProjKind::Map(range)),
+                vec![input],
+                output,
+                domain,
+            ),
+            JsonGate::Less {
+                threshold,
+                input,
+                output,
+                domain,
+            } => (
+                GateKind::Proj(ProjKind::Less(threshold)),
+                vec![input],
+                output,
+                domain,
+            ),
+            JsonGate::And { inputs, output } => (GateKind::And, inputs.to_vec(), output, 2),
+        };
+        Self {
+            output,
+            domain,
+            inputs,
+            kind,
+        }
+    }
+}
+
+impl From<&Circuit> for JsonCircuit {
+    fn from(circuit: &Circuit) -> Self {
+        Self {
+            version: JSON_VERSION,
+            num_inputs: circuit.num_inputs,
+            num_outputs: circuit.num_outputs,
+            num_wires: circuit.num_wires,
+            input_domains: circuit.input_domains.clone(),
+            gates: circuit.gates.iter().map(JsonGate::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    Version(u32),
+    Circuit(CircuitError),
+}
+
+impl Error for ImportError {}
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Malformed circuit: {e}"),
+            Self::Version(v) => {
+                write!(
+                    f,
+                    "Unsupported circuit version {v}, expected {JSON_VERSION}"
+                )
+            }
+            Self::Circuit(e) => write!(f, "Invalid circuit: {e}"),
+        }
+    }
+}
+
+impl TryFrom<JsonCircuit> for Circuit {
+    type Error = ImportError;
+
+    fn try_from(json: JsonCircuit) -> Result<Self, ImportError> {
+        if json.version != JSON_VERSION {
+            return Err(ImportError::Version(json.version));
+        }
+        let circuit = Self {
+            num_wires: json.num_wires,
+            num_inputs: json.num_inputs,
+            num_outputs: json.num_outputs,
+            gates: json.gates.into_iter().map(Gate::from).collect(),
+            input_domains: json.input_domains,
+        };
+        verify_circuit(&circuit).map_err(ImportError::Circuit)?;
+        Ok(circuit)
+    }
+}
+
+impl Circuit {
+    /// Render the circuit as JSON following the `JsonCircuit` schema.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&JsonCircuit::from(self)).expect("circuit is serializable")
+    }
+
+    /// Read a circuit written in the `JsonCircuit` schema, the circuit is verified.
+    pub fn from_json(json: &str) -> Result<Self, ImportError> {
+        let json: JsonCircuit = serde_json::from_str(json).map_err(ImportError::Json)?;
+        Self::try_from(json)
+    }
+
+    /// Domain of the value carried by every wire, for the wires that are assigned.
+    fn wire_domains(&self) -> HashMap<usize, u16> {
+        let inputs = self.input_domains.iter().copied().enumerate();
+        let gates = self.gates.iter().map(|g| (g.output, g.output_domain()));
+        inputs.chain(gates).collect()
+    }
+
+    /// Render the circuit as a Graphviz DOT graph.
+    ///
+    /// Inputs are boxes, gates are coloured by kind and edges are labelled with the domain of
+    /// the wire they carry.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // Writing to a `String` never fails.
+        let _ = self.write_dot(&mut dot);
+        dot
+    }
+
+    fn write_dot(&self, f: &mut impl Write) -> fmt::Result {
+        let domains = self.wire_domains();
+        writeln!(f, "digraph circuit {{")?;
+        writeln!(f, "  rankdir=LR;")?;
+        writeln!(f, "  node [style=filled];")?;
+        for i in 0..self.num_inputs {
+            writeln!(f, "  w{i} [label=\"in {i}\", shape=box, fillcolor=white];")?;
+        }
+        for gate in &self.gates {
+            let (label, color) = match gate.kind {
+                GateKind::Add => (format!("+ mod {}", gate.domain), "lightblue"),
+                GateKind::Mul(c) => (format!("* {c} mod {}", gate.domain), "palegreen"),
+                GateKind::Proj(ProjKind::Map(m)) => (format!("mod {m}"), "orange"),
+                GateKind::Proj(ProjKind::Less(t)) => (format!("< {t}"), "salmon"),
+                GateKind::And => ("and".to_string(), "gold"),
+            };
+            let w = gate.output;
+            writeln!(f, "  w{w} [label=\"{label}\", fillcolor={color}];")?;
+            for input in &gate.inputs {
+                match domains.get(input) {
+                    Some(d) => writeln!(f, "  w{input} -> w{w} [label=\"{d}\"];")?,
+                    None => writeln!(f, "  w{input} -> w{w};")?,
+                }
+            }
+        }
+        for (i, w) in (self.num_wires - self.num_outputs..self.num_wires).enumerate() {
+            writeln!(
+                f,
+                "  out{i} [label=\"out {i}\", shape=doublecircle, fillcolor=white];"
+            )?;
+            match domains.get(&w) {
+                Some(d) => writeln!(f, "  w{w} -> out{i} [label=\"{d}\"];")?,
+                None => writeln!(f, "  w{w} -> out{i};")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_roundtrip() {
+        let circuit = build_circuit(8, 2);
+        let json = circuit.to_json();
+        let imported = Circuit::from_json(&json).unwrap();
+        assert_eq!(imported.to_json(), json);
+        for x in [[0u16; 16], [1u16; 16]] {
+            assert_eq!(imported.eval(&x), circuit.eval(&x));
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let json = r#"{
+            "version": 1,
+            "num_inputs": 2,
+            "num_outputs": 1,
+            "num_wires": 4,
+            "input_domains": [2, 2],
+            "gates": [
+                {"kind": "and", "inputs": [0, 1], "output": 2},
+                {"kind": "proj", "range": 3, "input": 2, "output": 3, "domain": 2}
+            ]
+        }"#;
+        let circuit = Circuit::from_json(json).unwrap();
+        assert_eq!(circuit.gates[0].kind, GateKind::And);
+        assert_eq!(circuit.gates[1].kind, GateKind::Proj(ProjKind::Map(3)));
+    }
+
+    #[test]
+    fn test_json_rejects_bad_circuits() {
+        let json = r#"{
+            "version": 1,
+            "num_inputs": 1,
+            "num_outputs": 1,
+            "num_wires": 2,
+            "input_domains": [2],
+            "gates": [{"kind": "add", "inputs": [0, 3], "output": 1, "domain": 2}]
+        }"#;
+        assert!(matches!(
+            Circuit::from_json(json),
+            Err(ImportError::Circuit(_))
+        ));
+        let json = json.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            Circuit::from_json(&json),
+            Err(ImportError::Version(2))
+        ));
+        assert!(matches!(
+            Circuit::from_json("{}"),
+            Err(ImportError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_rejects_bad_domains() {
+        let circuit = |domains: &str, gate: &str| {
+            format!(
+                r#"{{"version": 1, "num_inputs": 2, "num_outputs": 1, "num_wires": 3,
+                    "input_domains": {domains}, "gates": [{gate}]}}"#
+            )
+        };
+        let add = r#"{"kind": "add", "inputs": [0, 1], "output": 2, "domain": 3}"#;
+        assert!(Circuit::from_json(&circuit("[3, 3]", add)).is_ok());
+        let cases = [
+            ("[3, 1]", add, CircuitError::BadDomain(1)),
+            ("[0, 3]", add, CircuitError::BadDomain(0)),
+            ("[3, 2]", add, CircuitError::BadDomain(1)),
+            ("[3]", add, CircuitError::BadInputCount),
+            (
+                "[3, 3]",
+                r#"{"kind": "add", "inputs": [], "output": 2, "domain": 3}"#,
+                CircuitError::BadArity(2),
+            ),
+            (
+                "[2, 2]",
+                r#"{"kind": "proj", "range": 0, "input": 0, "output": 2, "domain": 2}"#,
+                CircuitError::BadDomain(2),
+            ),
+            (
+                "[3, 3]",
+                r#"{"kind": "and", "inputs": [0, 1], "output": 2}"#,
+                CircuitError::BadDomain(0),
+            ),
+        ];
+        for (domains, gate, expected) in cases {
+            match Circuit::from_json(&circuit(domains, gate)) {
+                Err(ImportError::Circuit(e)) => assert_eq!(e, expected, "{domains} {gate}"),
+                other => panic!("{domains} {gate}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = build_circuit(2, 0).to_dot();
+        assert!(dot.starts_with("digraph circuit {"));
+        assert!(dot.contains("w8 [label=\"+ mod 3\", fillcolor=lightblue];"));
+        assert!(dot.contains("w9 [label=\"< 1\", fillcolor=salmon];"));
+        assert!(dot.contains("w0 -> w4 [label=\"2\"];"));
+        assert!(dot.contains("w8 -> w9 [label=\"3\"];"));
+        assert!(dot.contains("w9 -> out0 [label=\"2\"];"));
+    }
+}
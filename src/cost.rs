@@ -0,0 +1,313 @@
+// Cost model for circuits and the protocols built on top of them.
+//
+// Everything here is computed from the shape of the circuit alone, nothing is garbled or sent.
+// Byte counts are the sizes of the payloads handed to `ChannelSender::send`, i.e. without any
+// framing or authentication added by the channel. Rounds are counted as one-way message flights.
+use crate::circuit::*;
+use crate::util::{log2, LENGTH};
+use crate::wires::Wire;
+
+/// Computational security parameter of apricot (number of base OTs).
+const K: usize = 128;
+/// Statistical security parameter of apricot.
+const S: usize = 128;
+
+/// Bincode size of a `usize`, used for lengths of `Vec`s and `HashMap`s as well as keys.
+const USIZE: usize = std::mem::size_of::<u64>();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GateCost {
+    pub count: usize,
+    pub ciphertexts: usize,
+}
+
+impl GateCost {
+    const fn add(&mut self, gate: &Gate) {
+        self.count += 1;
+        self.ciphertexts += gate.ciphertexts();
+    }
+}
+
+/// Cost of garbling a `Circuit`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CircuitCost {
+    pub add: GateCost,
+    pub mul: GateCost,
+    pub proj: GateCost,
+    pub less: GateCost,
+    pub and: GateCost,
+    /// Size of the serialized `GarbledCircuit`.
+    pub garbled_bytes: usize,
+}
+
+impl CircuitCost {
+    pub fn new(circuit: &Circuit) -> Self {
+        let mut cost = Self::default();
+
+        // The garbled circuit contains the circuit itself and two maps of ciphertexts.
+        let mut bytes = bincode::serialized_size(circuit).unwrap() as usize + 2 * USIZE;
+        for gate in &circuit.gates {
+            match gate.kind {
+                GateKind::Add => cost.add.add(gate),
+                GateKind::Mul(_) => cost.mul.add(gate),
+                GateKind::Proj(proj) => {
+                    match proj {
+                        ProjKind::Map(_) => cost.proj.add(gate),
+                        ProjKind::Less(_) => cost.less.add(gate),
+                    }
+                    bytes += 2 * USIZE + gate.ciphertexts() * Wire::serialized_size(proj.domain());
+                }
+                GateKind::And => {
+                    cost.and.add(gate);
+                    bytes += USIZE + gate.ciphertexts() * Wire::serialized_size(2);
+                }
+            }
+        }
+        cost.garbled_bytes = bytes;
+        cost
+    }
+
+    /// Gate costs by name, in a fixed order.
+    pub const fn gates(&self) -> [(&'static str, GateCost); 5] {
+        [
+            ("Add", self.add),
+            ("Mul", self.mul),
+            ("Proj", self.proj),
+            ("Less", self.less),
+            ("And", self.and),
+        ]
+    }
+
+    pub fn ciphertexts(&self) -> usize {
+        self.gates().iter().map(|(_, g)| g.ciphertexts).sum()
+    }
+}
+
+/// Cost for one of the parties in a protocol.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartyCost {
+    /// Number of (extended) 1-of-2 OTs in which the party is the receiver.
+    pub ots: usize,
+    /// Number of base OTs in which the party is the receiver.
+    pub base_ots: usize,
+    /// Number of bytes sent by the party.
+    pub bytes_sent: usize,
+}
+
+impl std::ops::Add for PartyCost {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            ots: self.ots + other.ots,
+            base_ots: self.base_ots + other.base_ots,
+            bytes_sent: self.bytes_sent + other.bytes_sent,
+        }
+    }
+}
+
+/// Estimated cost of running a two-party protocol.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProtocolCost {
+    pub rounds: usize,
+    pub parties: [PartyCost; 2],
+}
+
+impl ProtocolCost {
+    pub const fn total_bytes(&self) -> usize {
+        self.parties[0].bytes_sent + self.parties[1].bytes_sent
+    }
+
+    /// Run `other` after `self`, keeping the order of the parties.
+    fn then(self, other: Self) -> Self {
+        Self {
+            rounds: self.rounds + other.rounds,
+            parties: [
+                self.parties[0] + other.parties[0],
+                self.parties[1] + other.parties[1],
+            ],
+        }
+    }
+
+    /// Swap the roles of the two parties.
+    const fn swap(self) -> Self {
+        let [a, b] = self.parties;
+        Self {
+            rounds: self.rounds,
+            parties: [b, a],
+        }
+    }
+}
+
+/// Size of the `TransactionProperties` exchanged at the start of every OT.
+const fn properties(protocol: &str) -> usize {
+    2 * USIZE + protocol.len()
+}
+
+/// Chou-Orlandi OT of `n` messages of `size` bytes, parties are (sender, receiver).
+pub const fn chou_orlandi(n: usize, size: usize) -> ProtocolCost {
+    // All public points are sent in one message.
+    let points = USIZE + n * 32;
+    let sender = PartyCost {
+        ots: 0,
+        base_ots: 0,
+        bytes_sent: properties("Chou-Orlandi") + points + USIZE + n * 2 * (USIZE + size),
+    };
+    let receiver = PartyCost {
+        ots: 0,
+        base_ots: n,
+        bytes_sent: properties("Chou-Orlandi") + points,
+    };
+    ProtocolCost {
+        rounds: 4,
+        parties: [sender, receiver],
+    }
+}
+
+/// Apricot OT extension of `n` messages of `size` bytes, parties are (sender, receiver).
+pub fn apricot(n: usize, size: usize) -> ProtocolCost {
+    let l = n.div_ceil(8) * 8 + K + S;
+    let coinflip = 32;
+    // Variant tag and a single length.
+    let lengths = 4 + 8;
+    let sender = PartyCost {
+        ots: 0,
+        base_ots: 0,
+        bytes_sent: properties("Apricot") + coinflip + lengths + n * 2 * size,
+    };
+    let receiver = PartyCost {
+        ots: n,
+        base_ots: 0,
+        bytes_sent: properties("Apricot") + 2 * coinflip + l / 8 * K + 2 * K / 8,
+    };
+    let extension = ProtocolCost {
+        rounds: 5,
+        parties: [sender, receiver],
+    };
+
+    // The roles are reversed for the base OTs.
+    let base = chou_orlandi(K, 32).swap();
+    extension.then(base)
+}
+
+/// A single `HalfKey` for a password of `bits` bits, parties are (garbler, evaluator).
+pub fn halfkey(bits: usize, threshold: u16) -> ProtocolCost {
+    let circuit = CircuitCost::new(&build_circuit(bits, threshold));
+    let mut cost = apricot(bits, LENGTH);
+
+    // The garbled circuit and the garbler's encoded password are sent after the OT.
+    cost.parties[0].bytes_sent += circuit.garbled_bytes + USIZE + bits * Wire::serialized_size(2);
+    cost
+}
+
+/// Full fPAKE, both parties garbling once. The first party garbles first.
+pub fn fpake(bits: usize, threshold: u16) -> ProtocolCost {
+    let first = halfkey(bits, threshold);
+    first.then(first.swap())
+}
+
+/// One-of-many fPAKE using `mfpake_many` and `mfpake_single`, parties are (server, client).
+pub fn mfpake(bits: usize, threshold: u16, passwords: usize) -> ProtocolCost {
+    let bytes = bits / 8;
+    let domain = log2(passwords) as usize;
+
+    // 1-to-n OT of the masked passwords, the number of passwords is agreed on first and `y` is
+    // sent before the base OTs.
+    let mut many = chou_orlandi(domain, LENGTH);
+    many.rounds += 2;
+    many.parties[0].bytes_sent += properties("1-to-n OT") + (1 << domain) * bytes;
+    many.parties[1].bytes_sent += properties("1-to-n OT");
+
+    many.then(fpake(bits, threshold))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::fpake::HalfKey;
+    use crate::garble::garble;
+    use crate::many_fpake::{mfpake_many, mfpake_single};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct CountingSender(Box<dyn ChannelSender>, Arc<AtomicUsize>);
+
+    impl ChannelSender for CountingSender {
+        fn send(&self, data: &[u8]) -> Result<()> {
+            self.1.fetch_add(data.len(), Ordering::SeqCst);
+            self.0.send(data)
+        }
+    }
+
+    fn counting_channel_pair() -> (Channel, Channel, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let ((s1, r1), (s2, r2)) = raw::local_channel_pair();
+        let c1 = Arc::new(AtomicUsize::new(0));
+        let c2 = Arc::new(AtomicUsize::new(0));
+        let ch1: Channel = (Box::new(CountingSender(s1, c1.clone())), r1);
+        let ch2: Channel = (Box::new(CountingSender(s2, c2.clone())), r2);
+        (ch1, ch2, c1, c2)
+    }
+
+    #[test]
+    fn test_garbled_size() {
+        let circuit = build_circuit(64, 4);
+        let cost = CircuitCost::new(&circuit);
+        let (gc, _, _) = garble(&circuit);
+        let actual = bincode::serialize(&gc).unwrap().len();
+        assert_eq!(cost.garbled_bytes, actual);
+        assert_eq!(cost.add.count, 65);
+        assert_eq!(cost.proj.count, 64);
+        assert_eq!(cost.less.count, 1);
+        assert_eq!(cost.ciphertexts(), 64 * 2 + 65);
+    }
+
+    #[test]
+    fn test_and_garbled_size() {
+        let circuit = CircuitBuilder::new(2)
+            .add_gate(Gate {
+                output: 2,
+                domain: 2,
+                inputs: vec![0, 1],
+                kind: GateKind::And,
+            })
+            .build();
+        let cost = CircuitCost::new(&circuit);
+        let (gc, _, _) = garble(&circuit);
+        assert_eq!(cost.garbled_bytes, bincode::serialize(&gc).unwrap().len());
+        assert_eq!(cost.and.ciphertexts, 2);
+    }
+
+    #[test]
+    fn test_halfkey_bytes() {
+        let password = b"password";
+        let (ch1, ch2, c1, c2) = counting_channel_pair();
+        let h1 = thread::spawn(move || HalfKey::garbler(password, 0, &ch1).unwrap());
+        let h2 = thread::spawn(move || HalfKey::evaluator(password, &ch2).unwrap());
+        h1.join().unwrap();
+        h2.join().unwrap();
+
+        let cost = halfkey(password.len() * 8, 0);
+        assert_eq!(cost.parties[0].bytes_sent, c1.load(Ordering::SeqCst));
+        assert_eq!(cost.parties[1].bytes_sent, c2.load(Ordering::SeqCst));
+        assert_eq!(cost.parties[0].base_ots, 128);
+        assert_eq!(cost.parties[1].ots, 64);
+    }
+
+    #[test]
+    fn test_mfpake_bytes() {
+        let passwords = vec![vec![0u8; 8], vec![1u8; 8], vec![2u8; 8], vec![3u8; 8]];
+        let password = passwords[1].clone();
+        let (ch1, ch2, c1, c2) = counting_channel_pair();
+        let h1 = thread::spawn(move || mfpake_many(&passwords, 0, &ch1).unwrap());
+        let h2 = thread::spawn(move || mfpake_single(&password, 1, 4, 0, &ch2).unwrap());
+        h1.join().unwrap();
+        h2.join().unwrap();
+
+        let cost = mfpake(64, 0, 4);
+        assert_eq!(cost.parties[0].bytes_sent, c1.load(Ordering::SeqCst));
+        assert_eq!(cost.parties[1].bytes_sent, c2.load(Ordering::SeqCst));
+        assert_eq!(cost.parties[1].base_ots, 128 + 2);
+    }
+}
@@ -9,6 +9,7 @@ use crate::ot::common::Message as MessagePair;
 use crate::ot::common::*;
 use crate::util::*;
 use crate::wires::*;
+use rayon::prelude::*;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Key(pub(crate) WireBytes);
@@ -24,6 +25,21 @@ pub struct HalfKey(pub WireBytes);
 
 impl HalfKey {
     pub fn garbler(password: &[u8], threshold: u16, ch: &Channel) -> Result<Self> {
+        let ot = apricot::Sender {
+            bootstrap: Box::new(chou_orlandi::Receiver),
+        };
+        Self::garbler_with(password, threshold, &ot, ch)
+    }
+
+    /// Like [`HalfKey::garbler`], sending the evaluator's labels with `ot`, e.g. a batch of an
+    /// [`apricot::OtExtensionSession`] shared by several logins, or a
+    /// [`crate::ot::beaver::SenderPool`] precomputed before the login.
+    pub fn garbler_with(
+        password: &[u8],
+        threshold: u16,
+        ot: &dyn ObliviousSender,
+        ch: &Channel,
+    ) -> Result<Self> {
         instrument::begin("Garbler", E_PROT_COLOR);
 
         let password = u8_vec_to_bool_vec(password);
@@ -42,9 +58,6 @@ impl HalfKey {
             .collect();
 
         let msg = MessagePair::from_zipped(&e_theirs);
-        let ot = apricot::Sender {
-            bootstrap: Box::new(chou_orlandi::Receiver),
-        };
         ot.exchange(&msg, ch)?;
         let (s, _) = ch;
 
@@ -61,19 +74,22 @@ impl HalfKey {
     }
 
     pub fn evaluator(password: &[u8], ch: &Channel) -> Result<Self> {
-        instrument::begin("Evaluator", E_PROT_COLOR);
-
-        let password = u8_vec_to_bool_vec(password);
         let ot = apricot::Receiver {
             bootstrap: Box::new(chou_orlandi::Sender),
         };
-        let enc_password = ot.exchange(&password, ch)?;
+        Self::evaluator_with(password, &ot, ch)
+    }
+
+    /// Like [`HalfKey::evaluator`], receiving our labels with `ot`. Needs one OT per password bit.
+    pub fn evaluator_with(password: &[u8], ot: &dyn ObliviousReceiver, ch: &Channel) -> Result<Self> {
+        instrument::begin("Evaluator", E_PROT_COLOR);
+
+        let enc_password = ot.exchange_bits(password, password.len() * 8, ch)?;
         let (_, r) = ch;
 
         let enc_password: Vec<Wire> = enc_password
             .iter()
-            .map(|b| to_array(b))
-            .map(|b: [u8; 32]| Wire::from_array(b, Domain::Binary))
+            .map(|b| Wire::from_array(to_array(b), Domain::Binary))
             .collect();
 
         let our_password = enc_password;
@@ -89,13 +105,108 @@ impl HalfKey {
         let output = evaluate(&gc, &input);
 
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (gc.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
         )))
     }
 
+    /// Garbler side of one fPAKE per password, sharing a single OT and message round.
+    pub fn garbler_batch(passwords: &[Vec<u8>], threshold: u16, ch: &Channel) -> Result<Vec<Self>> {
+        instrument::begin("Batch Garbler", E_PROT_COLOR);
+
+        let garbled: Vec<_> = passwords
+            .par_iter()
+            .map(|password| {
+                let password = u8_vec_to_bool_vec(password);
+                let n = password.len();
+                let circuit = build_circuit(n, threshold);
+                let (gc, e, d) = garble(&circuit);
+                let e = BinaryEncodingKey::from(e).zipped();
+                let enc_password = BinaryEncodingKey::unzipped(&e[..n]).encode(&password);
+                (gc, e[n..].to_vec(), enc_password, d.hashes[0][1])
+            })
+            .collect();
+
+        let e_theirs: Vec<_> = garbled
+            .iter()
+            .flat_map(|(_, e, _, _)| e.iter().map(|[w0, w1]| [w0.as_bytes(), w1.as_bytes()]))
+            .collect();
+        if !e_theirs.is_empty() {
+            let msg = MessagePair::from_zipped(&e_theirs);
+            let ot = apricot::Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            ot.exchange(&msg, ch)?;
+        }
+        let (s, _) = ch;
+
+        let (gcs, enc_passwords): (Vec<_>, Vec<_>) = garbled
+            .iter()
+            .map(|(gc, _, enc_password, _)| (gc, enc_password))
+            .unzip();
+        s.send(&bincode::serialize(&gcs)?)?;
+        s.send(&bincode::serialize(&enc_passwords)?)?;
+
+        instrument::end();
+        Ok(garbled.into_iter().map(|(_, _, _, d)| Self(d)).collect())
+    }
+
+    /// Evaluator side of one fPAKE per password, see [`HalfKey::garbler_batch`].
+    pub fn evaluator_batch(passwords: &[Vec<u8>], ch: &Channel) -> Result<Vec<Self>> {
+        instrument::begin("Batch Evaluator", E_PROT_COLOR);
+
+        let choices = passwords.concat();
+        let enc_passwords = if choices.is_empty() {
+            Payload::default()
+        } else {
+            let ot = apricot::Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            };
+            ot.exchange_bits(&choices, choices.len() * 8, ch)?
+        };
+        let (_, r) = ch;
+
+        let gcs: Vec<GarbledCircuit> = bincode::deserialize(&r.recv()?)?;
+        let their_passwords: Vec<Vec<Wire>> = bincode::deserialize(&r.recv()?)?;
+        if gcs.len() != passwords.len() || their_passwords.len() != passwords.len() {
+            return Err(Box::new(OTError::BadMessage("fPAKE batch")));
+        }
+
+        let mut offset = 0;
+        let mut inputs = Vec::with_capacity(passwords.len());
+        for (password, their_password) in passwords.iter().zip(their_passwords) {
+            let n = password.len() * 8;
+            let mut input = their_password;
+            input.extend(
+                (offset..offset + n)
+                    .map(|i| Wire::from_array(to_array(&enc_passwords[i]), Domain::Binary)),
+            );
+            offset += n;
+            inputs.push(input);
+        }
+        if gcs.iter().zip(&inputs).any(|(gc, x)| gc.circuit.num_inputs != x.len()) {
+            return Err(Box::new(OTError::BadMessage("fPAKE batch")));
+        }
+
+        let keys = gcs
+            .par_iter()
+            .zip(inputs.par_iter())
+            .map(|(gc, input)| {
+                let output = evaluate(gc, input);
+                Self(hash_label!(
+                    (gc.circuit.num_wires - 1).to_be_bytes(),
+                    1u16.to_be_bytes(),
+                    &output[0]
+                ))
+            })
+            .collect();
+
+        instrument::end();
+        Ok(keys)
+    }
+
     pub fn combine(self, other: Self) -> Key {
         Key(xor(self.0, other.0))
     }
@@ -137,6 +248,98 @@ mod tests {
         assert_eq!(k1, k2);
     }
 
+    #[test]
+    fn test_fpake_session() {
+        use std::thread;
+
+        let password = b"password";
+        let threshold = 0;
+
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let session = apricot::OtExtensionSession::new(true, &ch1).unwrap();
+            (0..2)
+                .map(|_| {
+                    let k1 = HalfKey::garbler_with(password, threshold, &session.sender(), &ch1);
+                    let k2 = HalfKey::evaluator_with(password, &session.receiver(), &ch1);
+                    k1.unwrap().combine(k2.unwrap())
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let h2 = thread::spawn(move || {
+            let session = apricot::OtExtensionSession::new(false, &ch2).unwrap();
+            (0..2)
+                .map(|_| {
+                    let k2 = HalfKey::evaluator_with(password, &session.receiver(), &ch2);
+                    let k1 = HalfKey::garbler_with(password, threshold, &session.sender(), &ch2);
+                    k1.unwrap().combine(k2.unwrap())
+                })
+                .collect::<Vec<_>>()
+        });
+
+        let k1 = h1.join().unwrap();
+        let k2 = h2.join().unwrap();
+        assert_eq!(k1, k2);
+        assert_ne!(k1[0], k1[1]);
+    }
+
+    #[test]
+    fn test_fpake_precomputed() {
+        use crate::ot::beaver;
+        use std::thread;
+
+        let password = b"password";
+        let bits = 8 * password.len();
+
+        // Offline, both directions.
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let (sender, receiver) = (beaver::SenderPool::default(), beaver::ReceiverPool::default());
+            let ot = apricot::Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            sender.precompute(&ot, 2 * bits, &ch1).unwrap();
+            let ot = apricot::Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            };
+            receiver.precompute(&ot, 2 * bits, &ch1).unwrap();
+            (sender, receiver)
+        });
+        let (sender, receiver) = (beaver::SenderPool::default(), beaver::ReceiverPool::default());
+        let ot = apricot::Receiver {
+            bootstrap: Box::new(chou_orlandi::Sender),
+        };
+        receiver.precompute(&ot, 2 * bits, &ch2).unwrap();
+        let ot = apricot::Sender {
+            bootstrap: Box::new(chou_orlandi::Receiver),
+        };
+        sender.precompute(&ot, 2 * bits, &ch2).unwrap();
+        let pools = (h1.join().unwrap(), (sender, receiver));
+
+        // Two logins online.
+        let (ch1, ch2) = raw::local_channel_pair();
+        let ((s1, r1), (s2, r2)) = pools;
+        let h1 = thread::spawn(move || {
+            (0..2)
+                .map(|_| {
+                    let k1 = HalfKey::garbler_with(password, 1, &s1, &ch1).unwrap();
+                    let k2 = HalfKey::evaluator_with(password, &r1, &ch1).unwrap();
+                    k1.combine(k2)
+                })
+                .collect::<Vec<_>>()
+        });
+        let keys: Vec<_> = (0..2)
+            .map(|_| {
+                let k2 = HalfKey::evaluator_with(password, &r2, &ch2).unwrap();
+                let k1 = HalfKey::garbler_with(password, 1, &s2, &ch2).unwrap();
+                k1.combine(k2)
+            })
+            .collect();
+        assert_eq!(h1.join().unwrap(), keys);
+        assert_eq!(r2.available(), 0);
+    }
+
     fn garble_encode_eval_decode(c: &Circuit, x: &[u16]) -> Vec<u16> {
         let (gc, e, d) = garble(c);
         let x = encode(&e, x);
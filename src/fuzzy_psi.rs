@@ -0,0 +1,278 @@
+// Fuzzy private set intersection: find every pair of items, one from each party, within a
+// Hamming distance threshold.
+//
+// Uses the masking trick from `mfpake_many`, but with a fresh mask per item since every item is
+// compared against the whole other set. The sender sends x_i ^ r_i, and the receiver computes
+// z_ij = x_i ^ r_i ^ y_j, after which d(x_i, y_j) = d(r_i, z_ij). Every pair then runs an fPAKE
+// on (r_i, z_ij), all batched into a single apricot OT and message round in each direction.
+// The resulting per-pair keys agree only for matching pairs, and non-matching pairs reveal
+// nothing to either party unless the keys are later compared with `PairKeys::matches`.
+use std::error::Error;
+use std::fmt;
+
+use crate::common::*;
+use crate::fpake::{HalfKey, Key};
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::common::OTError;
+use crate::util::*;
+
+#[derive(Debug)]
+pub enum FuzzyPsiError {
+    /// Items have different lengths, on either side.
+    ItemLength,
+}
+
+impl Error for FuzzyPsiError {}
+
+impl fmt::Display for FuzzyPsiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ItemLength => write!(f, "All items must have the same length"),
+        }
+    }
+}
+
+/// One key for each pair of a sender item `i` and receiver item `j`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairKeys {
+    keys: Vec<Key>,
+    columns: usize,
+}
+
+impl PairKeys {
+    /// Number of sender items.
+    pub fn rows(&self) -> usize {
+        self.keys.len().checked_div(self.columns).unwrap_or(0)
+    }
+
+    /// Number of receiver items.
+    pub const fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> &Key {
+        assert!(j < self.columns);
+        &self.keys[i * self.columns + j]
+    }
+
+    /// Reveal the matching pairs `(i, j)` to both parties by exchanging key confirmations.
+    pub fn matches(&self, ch: &Channel) -> Result<Vec<(usize, usize)>> {
+        instrument::begin("Key confirmation", E_PROT_COLOR);
+        let (s, r) = ch;
+        let confirmations: Vec<[u8; 32]> = self.keys.iter().map(|k| hash!(k.as_bytes())).collect();
+        s.send(&bincode::serialize(&confirmations)?)?;
+        let theirs: Vec<[u8; 32]> = bincode::deserialize(&r.recv()?)?;
+        if theirs.len() != confirmations.len() {
+            return Err(Box::new(OTError::BadMessage("key confirmations")));
+        }
+        let matches = confirmations
+            .iter()
+            .zip(&theirs)
+            .enumerate()
+            .filter(|(_, (a, b))| a == b)
+            .map(|(k, _)| (k / self.columns, k % self.columns))
+            .collect();
+        instrument::end();
+        Ok(matches)
+    }
+}
+
+fn item_length(items: &[Vec<u8>]) -> std::result::Result<usize, FuzzyPsiError> {
+    let length = items.first().map_or(0, Vec::len);
+    if items.iter().any(|x| x.len() != length) {
+        return Err(FuzzyPsiError::ItemLength);
+    }
+    Ok(length)
+}
+
+/// Sender side, holding items x_i. Garbles first.
+///
+/// Runs an fPAKE for every pair, so the receiver's set size is refused with
+/// `OTError::BadMessage` if it exceeds `max_columns`.
+pub fn sender(
+    items: &[Vec<u8>],
+    threshold: u16,
+    max_columns: usize,
+    ch: &Channel,
+) -> Result<PairKeys> {
+    instrument::begin("Fuzzy PSI Sender", E_FUNC_COLOR);
+    let (s, r) = ch;
+    let length = item_length(items)?;
+
+    instrument::begin("Receive set size", E_RECV_COLOR);
+    let columns: u64 = bincode::deserialize(&r.recv()?)?;
+    instrument::end();
+    let columns = usize::try_from(columns)
+        .ok()
+        .filter(|&c| c <= max_columns)
+        .filter(|&c| {
+            c.checked_mul(items.len())
+                .and_then(|n| n.checked_mul(length))
+                .is_some()
+        })
+        .ok_or(OTError::BadMessage("fuzzy PSI set size"))?;
+
+    instrument::begin("Mask items", E_COMP_COLOR);
+    let masks: Vec<Vec<u8>> = items
+        .iter()
+        .map(|_| {
+            let mut mask = vec![0u8; length];
+            random_bytes(&mut mask);
+            mask
+        })
+        .collect();
+    let masked: Vec<Vec<u8>> = items
+        .iter()
+        .zip(&masks)
+        .map(|(x, mask)| {
+            let mut masked = x.clone();
+            xor_bytes_inplace(&mut masked, mask);
+            masked
+        })
+        .collect();
+    instrument::end();
+
+    instrument::begin("Send masked items", E_SEND_COLOR);
+    s.send(&bincode::serialize(&masked)?)?;
+    instrument::end();
+
+    let passwords: Vec<Vec<u8>> = masks
+        .iter()
+        .flat_map(|mask| std::iter::repeat_n(mask.clone(), columns))
+        .collect();
+
+    instrument::begin("fPAKE with masks", E_PROT_COLOR);
+    let k1 = HalfKey::garbler_batch(&passwords, threshold, ch)?;
+    let k2 = HalfKey::evaluator_batch(&passwords, ch)?;
+    instrument::end();
+
+    instrument::end();
+    Ok(PairKeys {
+        keys: k1.into_iter().zip(k2).map(|(a, b)| a.combine(b)).collect(),
+        columns,
+    })
+}
+
+/// Receiver side, holding items y_j. Evaluates first.
+pub fn receiver(items: &[Vec<u8>], threshold: u16, ch: &Channel) -> Result<PairKeys> {
+    instrument::begin("Fuzzy PSI Receiver", E_FUNC_COLOR);
+    let (s, r) = ch;
+    let length = item_length(items)?;
+    let columns = items.len();
+
+    instrument::begin("Send set size", E_SEND_COLOR);
+    s.send(&bincode::serialize(&(columns as u64))?)?;
+    instrument::end();
+
+    instrument::begin("Receive masked items", E_RECV_COLOR);
+    let masked: Vec<Vec<u8>> = bincode::deserialize(&r.recv()?)?;
+    instrument::end();
+    if !masked.is_empty() && !items.is_empty() && item_length(&masked)? != length {
+        return Err(Box::new(FuzzyPsiError::ItemLength));
+    }
+
+    instrument::begin("Mask items", E_COMP_COLOR);
+    let passwords: Vec<Vec<u8>> = masked
+        .iter()
+        .flat_map(|x| {
+            items.iter().map(move |y| {
+                let mut z = x.clone();
+                xor_bytes_inplace(&mut z, y);
+                z
+            })
+        })
+        .collect();
+    instrument::end();
+
+    instrument::begin("fPAKE with masks", E_PROT_COLOR);
+    let k1 = HalfKey::evaluator_batch(&passwords, ch)?;
+    let k2 = HalfKey::garbler_batch(&passwords, threshold, ch)?;
+    instrument::end();
+
+    instrument::end();
+    Ok(PairKeys {
+        keys: k1.into_iter().zip(k2).map(|(a, b)| a.combine(b)).collect(),
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    type Matches = Vec<(usize, usize)>;
+
+    const MAX_COLUMNS: usize = 16;
+
+    fn run(xs: Vec<Vec<u8>>, ys: Vec<Vec<u8>>, threshold: u16) -> (Matches, Matches) {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let keys = sender(&xs, threshold, MAX_COLUMNS, &ch1).unwrap();
+            keys.matches(&ch1).unwrap()
+        });
+        let h2 = thread::spawn(move || {
+            let keys = receiver(&ys, threshold, &ch2).unwrap();
+            keys.matches(&ch2).unwrap()
+        });
+        (h1.join().unwrap(), h2.join().unwrap())
+    }
+
+    #[test]
+    fn test_fuzzy_psi() {
+        let xs = vec![vec![0u8; 4], vec![0xffu8; 4], vec![0x0fu8; 4]];
+        let ys = vec![
+            vec![0x01, 0x00, 0x00, 0x01], // distance 2 from xs[0]
+            vec![0xf0; 4],                // distance 16 from xs[0] and xs[1], 32 from xs[2]
+            vec![0xff, 0xff, 0xff, 0x7f], // distance 1 from xs[1]
+            vec![0xaa; 4],                // distance 16 from everything
+        ];
+        let (a, b) = run(xs, ys, 2);
+        assert_eq!(a, vec![(0, 0), (1, 2)]);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_fuzzy_psi_keys() {
+        let xs = vec![vec![1u8, 2, 3], vec![4u8, 5, 6]];
+        let ys = vec![vec![4u8, 5, 7], vec![9u8, 9, 9]];
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || sender(&xs, 1, MAX_COLUMNS, &ch1).unwrap());
+        let h2 = thread::spawn(move || receiver(&ys, 1, &ch2).unwrap());
+        let k1 = h1.join().unwrap();
+        let k2 = h2.join().unwrap();
+        assert_eq!((k1.rows(), k1.columns()), (2, 2));
+        assert_eq!(k1.get(1, 0), k2.get(1, 0));
+        assert_ne!(k1.get(0, 0), k2.get(0, 0));
+        assert_ne!(k1.get(0, 1), k2.get(0, 1));
+        assert_ne!(k1.get(1, 1), k2.get(1, 1));
+    }
+
+    #[test]
+    fn test_fuzzy_psi_empty() {
+        let (a, b) = run(vec![], vec![vec![1, 2]], 0);
+        assert!(a.is_empty() && b.is_empty());
+        let (a, b) = run(vec![vec![1, 2]], vec![], 0);
+        assert!(a.is_empty() && b.is_empty());
+    }
+
+    #[test]
+    fn test_item_length() {
+        let (ch1, _ch2) = raw::local_channel_pair();
+        assert!(sender(&[vec![1], vec![1, 2]], 0, MAX_COLUMNS, &ch1).is_err());
+    }
+
+    #[test]
+    fn test_set_size_limit() {
+        let (ch1, (s2, _r2)) = raw::local_channel_pair();
+        for columns in [MAX_COLUMNS as u64 + 1, u64::MAX] {
+            s2.send(&bincode::serialize(&columns).unwrap()).unwrap();
+            let err = sender(&[vec![1, 2]], 0, MAX_COLUMNS, &ch1).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<OTError>(),
+                Some(OTError::BadMessage(_))
+            ));
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::mem::{transmute, MaybeUninit};
+use subtle::ConstantTimeEq;
 
 use crate::circuit::*;
 use crate::util::*;
@@ -40,6 +41,22 @@ impl EncodingKey {
 
         z
     }
+
+    /// Domain of the input wire `i`.
+    pub fn domain(&self, i: usize) -> u16 {
+        self.wires[i].domain()
+    }
+
+    /// Label of the input wire `i` encoding `x`.
+    pub fn encode_wire(&self, i: usize, x: u16) -> Wire {
+        let wire = &self.wires[i];
+        wire + &(&self.delta[&wire.domain()] * x)
+    }
+
+    /// All labels of the input wire `i`, the `k`th label encoding `k`.
+    pub fn labels(&self, i: usize) -> Vec<Wire> {
+        (0..self.domain(i)).map(|k| self.encode_wire(i, k)).collect()
+    }
 }
 
 impl From<EncodingKey> for BinaryEncodingKey {
@@ -95,9 +112,11 @@ impl BinaryEncodingKey {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DecodingKey {
     pub(crate) hashes: Vec<Vec<WireBytes>>,
+    /// Color of the zero label of each output, used for point-and-permute decoding.
+    pub(crate) colors: Vec<u16>,
     pub(crate) offset: usize,
 }
 
@@ -114,15 +133,18 @@ impl fmt::Display for DecodeError {
 
 impl DecodingKey {
     pub fn decode(&self, z: &[Wire]) -> Result<Vec<u16>, DecodeError> {
+        if z.len() != self.hashes.len() {
+            return Err(DecodeError {});
+        }
         let mut y = Vec::with_capacity(z.len());
         for (i, z) in z.iter().enumerate() {
             let output = self.offset + i;
             let hashes = &self.hashes[i];
 
             let mut success = false;
-            for k in 0..z.domain() {
+            for k in 0..z.domain().min(hashes.len() as u16) {
                 let hash = hash(output, k, z);
-                if hash == hashes[k as usize] {
+                if bool::from(hash.ct_eq(&hashes[k as usize])) {
                     y.push(k);
                     success = true;
                     break;
@@ -136,6 +158,52 @@ impl DecodingKey {
 
         Ok(y)
     }
+
+    /// Decode using point-and-permute.
+    ///
+    /// The color of an output label determines its value, so only a single hash is computed per
+    /// output to check that the label is valid.
+    pub fn decode_permuted(&self, z: &[Wire]) -> Result<Vec<u16>, DecodeError> {
+        if z.len() != self.hashes.len() {
+            return Err(DecodeError {});
+        }
+        z.iter()
+            .enumerate()
+            .map(|(i, z)| self.decode_output(i, z))
+            .collect()
+    }
+
+    /// Decode the label `z` of the `i`th output using point-and-permute.
+    ///
+    /// The key may come from the other party, so it is checked to hold a color in the domain for
+    /// every output instead of trusting its shape.
+    pub fn decode_output(&self, i: usize, z: &Wire) -> Result<u16, DecodeError> {
+        let hashes = self.hashes.get(i).ok_or(DecodeError {})?;
+        let zero = *self.colors.get(i).ok_or(DecodeError {})?;
+        let domain = hashes.len() as u32;
+        if domain == 0 || zero as u32 >= domain || z.color() as u32 >= domain {
+            return Err(DecodeError {});
+        }
+        let color = z.color() as u32 + domain - zero as u32;
+        let k = (color % domain) as u16;
+        let hash = hash(self.offset + i, k, z);
+        if !bool::from(hash.ct_eq(&hashes[k as usize])) {
+            return Err(DecodeError {});
+        }
+        Ok(k)
+    }
+
+    /// Keep only the decoding information for the given `outputs`.
+    pub fn restrict(&self, outputs: &[usize]) -> Self {
+        let mut key = self.clone();
+        for (i, hashes) in key.hashes.iter_mut().enumerate() {
+            if !outputs.contains(&i) {
+                hashes.clear();
+                key.colors[i] = 0;
+            }
+        }
+        key
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -168,6 +236,10 @@ pub fn garble(circuit: &Circuit) -> (GarbledCircuit, EncodingKey, DecodingKey) {
             }
         }
     }
+    // Inputs no gate reads from still have to be encodable.
+    for &domain in &circuit.input_domains {
+        delta.entry(domain).or_insert_with(|| Wire::delta(domain));
+    }
 
     // 2. Create wires for each of the inputs
     let mut wires = Vec::with_capacity(circuit.num_wires);
@@ -186,6 +258,7 @@ pub fn garble(circuit: &Circuit) -> (GarbledCircuit, EncodingKey, DecodingKey) {
     let mut f = HashMap::new();
     let mut f_halfgate = HashMap::new();
     let mut d = Vec::with_capacity(circuit.num_outputs);
+    let mut colors = Vec::with_capacity(circuit.num_outputs);
     let mut j0: usize = 0;
     let mut j1: usize = 0;
     for gate in &circuit.gates {
@@ -233,19 +306,19 @@ pub fn garble(circuit: &Circuit) -> (GarbledCircuit, EncodingKey, DecodingKey) {
                 j1 += 1;
 
                 // first half gate
-                let t_g = &Wire::from_array(hash!(w_a, j0.to_be_bytes()), Domain::Binary)
-                    + &Wire::from_array(hash!(w_a + delta, j0.to_be_bytes()), Domain::Binary);
+                let t_g = &Wire::from_array(hash_label!(w_a, j0.to_be_bytes()), Domain::Binary)
+                    + &Wire::from_array(hash_label!(w_a + delta, j0.to_be_bytes()), Domain::Binary);
                 let t_g = if p_b { &t_g + delta } else { t_g };
 
-                let w_g = Wire::from_array(hash!(w_a, j0.to_be_bytes()), Domain::Binary);
+                let w_g = Wire::from_array(hash_label!(w_a, j0.to_be_bytes()), Domain::Binary);
                 let w_g = if p_a { &w_g + &t_g } else { w_g };
 
                 // second half gate
-                let t_e = &Wire::from_array(hash!(w_b, j1.to_be_bytes()), Domain::Binary)
-                    + &Wire::from_array(hash!(w_b + delta, j1.to_be_bytes()), Domain::Binary);
+                let t_e = &Wire::from_array(hash_label!(w_b, j1.to_be_bytes()), Domain::Binary)
+                    + &Wire::from_array(hash_label!(w_b + delta, j1.to_be_bytes()), Domain::Binary);
                 let t_e = &t_e + w_a;
 
-                let w_e = Wire::from_array(hash!(w_b, j1.to_be_bytes()), Domain::Binary);
+                let w_e = Wire::from_array(hash_label!(w_b, j1.to_be_bytes()), Domain::Binary);
                 let w_e = if p_b { &w_e + &(&t_e + w_a) } else { w_e };
 
                 f_halfgate.insert(gate.output, (t_g, t_e));
@@ -257,7 +330,7 @@ pub fn garble(circuit: &Circuit) -> (GarbledCircuit, EncodingKey, DecodingKey) {
         // 5. Decoding information for outputs
         if gate.output >= outputs_start_at {
             let output_domain = gate.output_domain();
-            let mut values = vec![[0u8; 32]; output_domain as usize];
+            let mut values = vec![[0u8; LENGTH]; output_domain as usize];
             for x in 0..output_domain {
                 let hash = hash(
                     gate.output,
@@ -268,11 +341,13 @@ pub fn garble(circuit: &Circuit) -> (GarbledCircuit, EncodingKey, DecodingKey) {
             }
 
             d.push(values);
+            colors.push(wires[gate.output].color());
         }
     }
 
     let decode_key = DecodingKey {
         hashes: d,
+        colors,
         offset: outputs_start_at,
     };
 
@@ -334,10 +409,10 @@ pub fn evaluate(circuit: &GarbledCircuit, x: &[Wire]) -> Vec<Wire> {
                 j1 += 1;
                 let (t_g, t_e) = &f_halfgate[&gate.output];
 
-                let w_g = Wire::from_array(hash!(w_a, j0.to_be_bytes()), Domain::Binary);
+                let w_g = Wire::from_array(hash_label!(w_a, j0.to_be_bytes()), Domain::Binary);
                 let w_g = if s_a { &w_g + t_g } else { w_g };
 
-                let w_e = Wire::from_array(hash!(w_b, j1.to_be_bytes()), Domain::Binary);
+                let w_e = Wire::from_array(hash_label!(w_b, j1.to_be_bytes()), Domain::Binary);
 
                 let w_e = if s_b { &w_e + &(t_e + w_a) } else { w_e };
 
@@ -369,28 +444,7 @@ pub fn encode(e: &EncodingKey, x: &[u16]) -> Vec<Wire> {
 }
 
 pub fn decode(d: &DecodingKey, z: &[Wire]) -> Result<Vec<u16>, DecodeError> {
-    let mut y = vec![0; d.hashes.len()];
-    for i in 0..z.len() {
-        let output = d.offset + i;
-        let hashes = &d.hashes[i];
-
-        let mut success = false;
-        for k in 0..z[i].domain() {
-            let hash = hash(output, k, &z[i]);
-            println!("hash: {:?}", hash);
-            if hash == hashes[k as usize] {
-                y[i as usize] = k;
-                success = true;
-                break;
-            }
-        }
-
-        if !success {
-            return Err(DecodeError {});
-        }
-    }
-
-    Ok(y)
+    d.decode(z)
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -702,4 +756,168 @@ mod tests {
             assert_eq!(zero, &bin_enc.0[i], "encoding bad!");
         }
     }
+
+    #[test]
+    fn decode_permuted() {
+        // Outputs in several domains: mod 7 sum, mod 5 projection and a threshold bit.
+        let circuit = Circuit {
+            gates: vec![
+                Gate {
+                    kind: GateKind::Add,
+                    inputs: vec![0, 1],
+                    output: 2,
+                    domain: 7,
+                },
+                Gate {
+                    kind: GateKind::Proj(ProjKind::Map(5)),
+                    inputs: vec![2],
+                    output: 3,
+                    domain: 7,
+                },
+                Gate {
+                    kind: GateKind::Proj(ProjKind::Less(4)),
+                    inputs: vec![2],
+                    output: 4,
+                    domain: 7,
+                },
+            ],
+            num_inputs: 2,
+            num_outputs: 2,
+            num_wires: 5,
+            input_domains: vec![7, 7],
+        };
+        for x in 0..7 {
+            for y in 0..7 {
+                let (gc, e, d) = garble(&circuit);
+                let z = evaluate(&gc, &encode(&e, &[x, y]));
+                let expected = vec![((x + y) % 7) % 5, ((x + y) % 7 < 4) as u16];
+                assert_eq!(d.decode_permuted(&z).unwrap(), expected);
+                assert_eq!(d.decode(&z).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_permuted_rejects_bad_labels() {
+        let circuit = make_me_the_threshold();
+        let (gc, e, d) = garble(&circuit);
+        let z = evaluate(&gc, &encode(&e, &[0; 8]));
+        assert!(d.decode_permuted(&z).is_ok());
+        assert!(d.decode_permuted(&[Wire::new(2)]).is_err());
+        assert!(d.decode_permuted(&[]).is_err());
+        assert!(d.decode(&[]).is_err());
+
+        let hidden = d.restrict(&[]);
+        assert!(hidden.decode_output(0, &z[0]).is_err());
+        assert_eq!(d.restrict(&[0]).decode_output(0, &z[0]).unwrap(), 1);
+    }
+
+    #[test]
+    fn decode_rejects_malformed_keys() {
+        let circuit = make_me_the_threshold();
+        let (gc, e, d) = garble(&circuit);
+        let z = evaluate(&gc, &encode(&e, &[0; 8]));
+        let tampered = |colors: Vec<u16>| DecodingKey {
+            hashes: d.hashes.clone(),
+            colors,
+            offset: d.offset,
+        };
+        assert!(tampered(vec![]).decode_permuted(&z).is_err());
+        assert!(tampered(vec![7]).decode_permuted(&z).is_err());
+        assert!(tampered(d.colors.clone()).decode_permuted(&z).is_ok());
+
+        let truncated = DecodingKey {
+            hashes: vec![d.hashes[0][..1].to_vec()],
+            ..d.clone()
+        };
+        assert!(truncated.decode(&z).is_err());
+    }
+
+    mod differential {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+
+        const DOMAINS: [u16; 8] = [2, 3, 5, 8, 16, 100, 256, 1000];
+
+        /// Build a valid circuit from raw genes, each gate reading wires defined before it.
+        fn build(
+            inputs: &[(usize, u16)],
+            genes: &[[u16; 3]],
+            outputs: usize,
+        ) -> (Circuit, Vec<u16>) {
+            let input_domains: Vec<u16> = inputs.iter().map(|&(d, _)| DOMAINS[d]).collect();
+            let x = inputs.iter().map(|&(d, x)| x % DOMAINS[d]).collect();
+            let mut domains = input_domains.clone();
+            let mut gates = Vec::with_capacity(genes.len());
+            for g in genes {
+                let a = g[1] as usize % domains.len();
+                let domain = domains[a];
+                let same: Vec<usize> = (0..domains.len())
+                    .filter(|&w| domains[w] == domain)
+                    .collect();
+                let bits: Vec<usize> = (0..domains.len()).filter(|&w| domains[w] == 2).collect();
+                let pick = |wires: &[usize]| wires[g[2] as usize % wires.len()];
+                let (kind, inputs, domain) = match g[0] % 5 {
+                    0 => (GateKind::Add, vec![a, pick(&same)], domain),
+                    1 => (GateKind::Mul(g[2] % domain), vec![a], domain),
+                    2 => (
+                        GateKind::Proj(ProjKind::Map(DOMAINS[g[2] as usize % DOMAINS.len()])),
+                        vec![a],
+                        domain,
+                    ),
+                    4 if !bits.is_empty() => (
+                        GateKind::And,
+                        vec![bits[g[1] as usize % bits.len()], pick(&bits)],
+                        2,
+                    ),
+                    _ => (
+                        GateKind::Proj(ProjKind::Less(g[2] % (domain + 1))),
+                        vec![a],
+                        domain,
+                    ),
+                };
+                let gate = Gate {
+                    output: domains.len(),
+                    domain,
+                    inputs,
+                    kind,
+                };
+                domains.push(gate.output_domain());
+                gates.push(gate);
+            }
+            let circuit = Circuit {
+                num_wires: domains.len(),
+                num_inputs: inputs.len(),
+                num_outputs: 1 + outputs % gates.len(),
+                gates,
+                input_domains,
+            };
+            (circuit, x)
+        }
+
+        fn arb_circuit() -> impl Strategy<Value = (Circuit, Vec<u16>)> {
+            (
+                vec((0..DOMAINS.len(), any::<u16>()), 1..6),
+                vec(any::<[u16; 3]>(), 1..24),
+                any::<usize>(),
+            )
+                .prop_map(|(inputs, genes, outputs)| build(&inputs, &genes, outputs))
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(64))]
+
+            #[test]
+            fn garbled_matches_plaintext((circuit, x) in arb_circuit()) {
+                verify_circuit(&circuit).unwrap();
+                let expected = circuit.eval(&x)[circuit.num_wires - circuit.num_outputs..].to_vec();
+
+                let (gc, e, d) = garble(&circuit);
+                let z = evaluate(&gc, &encode(&e, &x));
+                prop_assert_eq!(&decode(&d, &z).unwrap(), &expected);
+                prop_assert_eq!(&d.decode_permuted(&z).unwrap(), &expected);
+            }
+        }
+    }
 }
@@ -16,7 +16,7 @@ use crate::util::*;
 use crate::wires::*;
 
 #[inline]
-fn payload_to_encoding(payload: Payload, bit_count: usize) -> Vec<Wire> {
+fn payload_to_encoding(payload: &Payload, bit_count: usize) -> Vec<Wire> {
     let mut encoding = Vec::with_capacity(bit_count);
     for i in 0..bit_count {
         let encoded_bytes = &payload[i];
@@ -109,7 +109,6 @@ impl OneOfManyKey {
 
         // 4. Encode all passwords
         instrument::begin("Encode passwords", E_COMP_COLOR);
-        let domain = log2(passwords.len());
         let mut server_encodings: Vec<Vec<u8>> = Vec::with_capacity(passwords.len());
         let encoding_key: Vec<_> = encoding[password_bits..]
             .to_vec()
@@ -137,7 +136,7 @@ impl OneOfManyKey {
         let server_password_ot = ManyOTSender {
             interal_sender: Box::new(OTSender),
         };
-        server_password_ot.exchange(&server_encodings, domain, channel)?;
+        server_password_ot.exchange(&server_encodings, channel)?;
         instrument::end();
 
         //
@@ -185,8 +184,7 @@ impl OneOfManyKey {
         let many_receiver = ManyOTReceiver {
             internal_receiver: Box::new(OTReceiver),
         };
-        let domain = log2(number_of_password);
-        let server_password_ot = many_receiver.exchange(index, domain, channel)?;
+        let server_password_ot = many_receiver.exchange(index, number_of_password, channel)?;
         instrument::end();
 
         instrument::begin("Build encodings", E_COMP_COLOR);
@@ -208,7 +206,7 @@ impl OneOfManyKey {
         instrument::end();
 
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (garbled_circuit.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
@@ -288,7 +286,6 @@ impl OneOfManyKey {
         // 4. Mask all passwords
         instrument::begin("Mask passwords", E_COMP_COLOR);
         let password_count = passwords.len();
-        let domain = log2(password_count);
         let mut masked_passwords = vec![vec![0u8; password_bytes]; password_count];
         for i in 0..password_count {
             xor_bytes_inplace(&mut masked_passwords[i], &mask);
@@ -301,7 +298,7 @@ impl OneOfManyKey {
         let masked_passwords_ot = ManyOTSender {
             interal_sender: Box::new(OTSender),
         };
-        masked_passwords_ot.exchange(&masked_passwords, domain, channel)?;
+        masked_passwords_ot.exchange(&masked_passwords, channel)?;
         instrument::end();
 
         // 6. OT For encoding of masked server password
@@ -373,8 +370,7 @@ impl OneOfManyKey {
         let many_receiver = ManyOTReceiver {
             internal_receiver: Box::new(OTReceiver),
         };
-        let domain = log2(number_of_password);
-        let masked_password = many_receiver.exchange(index, domain, channel)?;
+        let masked_password = many_receiver.exchange(index, number_of_password, channel)?;
         instrument::end();
 
         // 5. Encode masked password
@@ -400,10 +396,10 @@ impl OneOfManyKey {
         // 7. Build encodings
         instrument::begin("Building encodings", E_COMP_COLOR);
         let encoded_row_length = masked_encoding[0].len();
-        let client_encoding = payload_to_encoding(client_encoding, password_bits);
+        let client_encoding = payload_to_encoding(&client_encoding, password_bits);
         let mask_encoding =
             bytes_to_encoding(&encoded_mask_bytes, password_bits, encoded_row_length);
-        let server_encoding = payload_to_encoding(masked_encoding, password_bits);
+        let server_encoding = payload_to_encoding(&masked_encoding, password_bits);
         instrument::end();
 
         // 6. Evaluate the circuit
@@ -413,7 +409,7 @@ impl OneOfManyKey {
         instrument::end();
 
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (gc.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
@@ -597,7 +593,7 @@ impl OneOfManyKey {
         instrument::end();
 
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (garbled_circuit.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
@@ -674,8 +670,7 @@ impl OneOfManyKey {
         let many_receiver = ManyOTReceiver {
             internal_receiver: Box::new(OTReceiver),
         };
-        let domain = log2(number_of_passwords);
-        let masked_password = many_receiver.exchange(index, domain, channel)?;
+        let masked_password = many_receiver.exchange(index, number_of_passwords, channel)?;
         instrument::end();
 
         instrument::begin("Encode masked password", E_COMP_COLOR);
@@ -753,8 +748,7 @@ impl OneOfManyKey {
         let many_sender = ManyOTSender {
             interal_sender: Box::new(OTSender),
         };
-        let domain = log2(passwords.len());
-        many_sender.exchange(masked_passwords.as_slice(), domain, channel)?;
+        many_sender.exchange(masked_passwords.as_slice(), channel)?;
         instrument::end();
 
         instrument::begin("R: Encoded masked password", E_RECV_COLOR);
@@ -767,7 +761,7 @@ impl OneOfManyKey {
 
         instrument::begin("Build encodings", E_COMP_COLOR);
         let encoded_row_length = encoded_mask[0].len();
-        let mask_encoding = payload_to_encoding(encoded_mask, password_bits);
+        let mask_encoding = payload_to_encoding(&encoded_mask, password_bits);
         let server_encoding = bytes_to_encoding(&server_encoded, password_bits, encoded_row_length);
         instrument::end();
 
@@ -779,7 +773,7 @@ impl OneOfManyKey {
 
         instrument::end();
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (garbled_circuit.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
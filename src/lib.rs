@@ -11,11 +11,15 @@ extern crate core;
 
 pub mod circuit;
 pub mod common;
+pub mod cost;
 pub mod fpake;
+pub mod fuzzy_psi;
 pub mod garble;
 mod instrument;
 pub mod legacy_fpake;
 pub mod many_fpake;
 pub mod ot;
+pub mod psi;
+pub mod two_party;
 pub mod util;
 mod wires;
@@ -1,13 +1,18 @@
 // main.rs for vanilla fPAKE.
-use clap::Parser;
-use magic_pake::common::Result;
+use clap::{Parser, Subcommand};
+use magic_pake::circuit::build_circuit;
 use magic_pake::common::auth::{connect_channel, ChannelServer};
+use magic_pake::common::Result;
+use magic_pake::cost::{self, CircuitCost, ProtocolCost};
 use magic_pake::fpake::HalfKey;
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Address of where to connect.
     #[clap(short, long, default_value = "localhost:4321")]
     address: String,
@@ -17,17 +22,83 @@ struct Args {
     server: bool,
 
     /// Password as bitstring
-    #[clap(short, long)]
-    password: String,
+    #[clap(short, long, required = true)]
+    password: Option<String>,
 
     /// Threadshold used.
     #[clap(short, long, default_value_t = 0)]
     threshold: u16,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the estimated cost of the protocols without running them.
+    Cost {
+        /// Size of the password in bits.
+        #[clap(short, long, default_value_t = 2048)]
+        bits: usize,
+
+        /// Threshold used.
+        #[clap(short, long, default_value_t = 0)]
+        threshold: u16,
+
+        /// Number of passwords held by the server in one-of-many fPAKE.
+        #[clap(short, long, default_value_t = 1024)]
+        passwords: usize,
+    },
+}
+
+fn print_cost(bits: usize, threshold: u16, passwords: usize) {
+    let circuit = CircuitCost::new(&build_circuit(bits, threshold));
+    println!("Circuit: {} bits, threshold {}", bits, threshold);
+    println!("{:<8}{:>12}{:>14}", "Gate", "Count", "Ciphertexts");
+    for (name, gate) in circuit.gates() {
+        println!("{:<8}{:>12}{:>14}", name, gate.count, gate.ciphertexts);
+    }
+    println!("Garbled circuit: {} bytes", circuit.garbled_bytes);
+    println!();
+
+    let protocols: [(&str, ProtocolCost); 3] = [
+        ("HalfKey", cost::halfkey(bits, threshold)),
+        ("fPAKE", cost::fpake(bits, threshold)),
+        ("mfPAKE", cost::mfpake(bits, threshold, passwords)),
+    ];
+    println!(
+        "{:<8}{:>8}{:>12}{:>12}{:>12}{:>12}{:>14}",
+        "", "Rounds", "OTs 1/2", "Base 1/2", "Sent 1", "Sent 2", "Total bytes"
+    );
+    for (name, p) in protocols {
+        let [p1, p2] = p.parties;
+        println!(
+            "{:<8}{:>8}{:>12}{:>12}{:>12}{:>12}{:>14}",
+            name,
+            p.rounds,
+            format!("{}/{}", p1.ots, p2.ots),
+            format!("{}/{}", p1.base_ots, p2.base_ots),
+            p1.bytes_sent,
+            p2.bytes_sent,
+            p.total_bytes()
+        );
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
-    let pw = args.password.as_bytes();
+    if let Some(Command::Cost {
+        bits,
+        threshold,
+        passwords,
+    }) = args.command
+    {
+        print_cost(bits, threshold, passwords);
+        return Ok(());
+    }
+
+    let pw = args
+        .password
+        .as_deref()
+        .expect("password is required")
+        .as_bytes();
     let key = if args.server {
         let mut server = ChannelServer::bind(&args.address)?;
         println!("Listening on {}...", &args.address);
@@ -48,5 +119,3 @@ fn main() -> Result<()> {
     println!("Derived Key: {:?}", key);
     Ok(())
 }
-
-
@@ -14,7 +14,7 @@ use crate::util::*;
 use crate::wires::*;
 
 #[inline]
-fn payload_to_encoding(payload: Payload, bit_count: usize) -> Vec<Wire> {
+fn payload_to_encoding(payload: &Payload, bit_count: usize) -> Vec<Wire> {
     let mut encoding = Vec::with_capacity(bit_count);
     for i in 0..bit_count {
         let encoded_bytes = &payload[i];
@@ -24,6 +24,17 @@ fn payload_to_encoding(payload: Payload, bit_count: usize) -> Vec<Wire> {
     return encoding;
 }
 
+/// The label pairs of `encoding` back to back, as read by [`MessagePair::from_flat`].
+#[inline]
+fn encoding_to_bytes(encoding: &[[Wire; 2]]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(encoding.len() * 2 * LENGTH);
+    for [w0, w1] in encoding {
+        bytes.extend_from_slice(w0.as_ref());
+        bytes.extend_from_slice(w1.as_ref());
+    }
+    bytes
+}
+
 #[inline]
 fn bytes_to_encoding(bytes: &[u8], bit_count: usize, encoded_size: usize) -> Vec<Wire> {
     let mut encoding = Vec::with_capacity(bit_count);
@@ -89,14 +100,8 @@ impl OneOfManyKey {
 
         // 2. OT for encoding of client password
         instrument::begin("Encode client password", E_COMP_COLOR);
-        let mut client_password_encoding = Vec::with_capacity(password_bits);
-        for i in 0..password_bits {
-            client_password_encoding.push([
-                encoding[i][0].as_bytes().to_vec(),
-                encoding[i][1].as_bytes().to_vec(),
-            ])
-        }
-        let client_encoding_message = MessagePair::from_zipped(client_password_encoding.as_slice());
+        let client_password_encoding = encoding_to_bytes(&encoding[..password_bits]);
+        let client_encoding_message = MessagePair::from_flat(&client_password_encoding, LENGTH);
         let client_encoding_ot = Sender {
             bootstrap: Box::new(chou_orlandi::Receiver),
         };
@@ -134,7 +139,6 @@ impl OneOfManyKey {
         // 4. Mask all passwords
         instrument::begin("Mask passwords", E_COMP_COLOR);
         let password_count = passwords.len();
-        let domain = log2(password_count);
         let mut masked_passwords = vec![vec![0u8; password_bytes]; password_count];
         for i in 0..password_count {
             xor_bytes_inplace(&mut masked_passwords[i], &mask);
@@ -147,19 +151,14 @@ impl OneOfManyKey {
         let masked_passwords_ot = ManyOTSender {
             interal_sender: Box::new(chou_orlandi::Sender),
         };
-        masked_passwords_ot.exchange(&masked_passwords, domain, channel)?;
+        masked_passwords_ot.exchange(&masked_passwords, channel)?;
         instrument::end();
 
         // 6. OT For encoding of masked server password
         instrument::begin("Encode masked password", E_COMP_COLOR);
-        let mut masked_password_encoding = Vec::with_capacity(password_bits);
-        for i in 0..password_bits {
-            masked_password_encoding.push([
-                encoding[password_bits * 2 + i][0].as_bytes().to_vec(),
-                encoding[password_bits * 2 + i][1].as_bytes().to_vec(),
-            ])
-        }
-        let masked_encoding_message = MessagePair::from_zipped(masked_password_encoding.as_slice());
+        let masked_password_encoding =
+            encoding_to_bytes(&encoding[password_bits * 2..password_bits * 3]);
+        let masked_encoding_message = MessagePair::from_flat(&masked_password_encoding, LENGTH);
         let masked_encoding_ot = Sender {
             bootstrap: Box::new(chou_orlandi::Receiver),
         };
@@ -196,18 +195,10 @@ impl OneOfManyKey {
 
         // 2. OT Encoding of client password
         instrument::begin("OT: Client password", E_PROT_COLOR);
-        let mut password_choices = Vec::with_capacity(password_bits);
-        for i in 0..password_bytes {
-            for j in 0..8 {
-                let bit = ((password[i] >> j) & 1) == 1;
-                password_choices.push(bit)
-            }
-        }
-
         let password_receiver = Receiver {
             bootstrap: Box::new(chou_orlandi::Sender),
         };
-        let client_encoding = password_receiver.exchange(&password_choices, channel)?;
+        let client_encoding = password_receiver.exchange_bits(password, password_bits, channel)?;
         instrument::end();
 
         // 3. Receive encoded mask
@@ -220,24 +211,16 @@ impl OneOfManyKey {
         let many_receiver = ManyOTReceiver {
             internal_receiver: Box::new(chou_orlandi::Receiver),
         };
-        let domain = log2(number_of_password);
-        let masked_password = many_receiver.exchange(index, domain, channel)?;
+        let masked_password = many_receiver.exchange(index, number_of_password, channel)?;
         instrument::end();
 
         // 5. Encode masked password
         instrument::begin("OT: Encoded masked password", E_PROT_COLOR);
-        let mut masked_choices = Vec::with_capacity(password_bits);
-        for i in 0..password_bytes {
-            for j in 0..8 {
-                let bit = ((masked_password[i] >> j) & 1) == 1;
-                masked_choices.push(bit)
-            }
-        }
-
         let password_receiver = Receiver {
             bootstrap: Box::new(chou_orlandi::Sender),
         };
-        let masked_encoding = password_receiver.exchange(&masked_choices, channel)?;
+        let masked_encoding =
+            password_receiver.exchange_bits(&masked_password, password_bits, channel)?;
         instrument::end();
 
         //
@@ -247,10 +230,10 @@ impl OneOfManyKey {
         // 7. Build encodings
         instrument::begin("Building encodings", E_COMP_COLOR);
         let encoded_row_length = masked_encoding[0].len();
-        let client_encoding = payload_to_encoding(client_encoding, password_bits);
+        let client_encoding = payload_to_encoding(&client_encoding, password_bits);
         let mask_encoding =
             bytes_to_encoding(&encoded_mask_bytes, password_bits, encoded_row_length);
-        let server_encoding = payload_to_encoding(masked_encoding, password_bits);
+        let server_encoding = payload_to_encoding(&masked_encoding, password_bits);
         instrument::end();
 
         // 6. Evaluate the circuit
@@ -260,7 +243,7 @@ impl OneOfManyKey {
         instrument::end();
 
         instrument::end();
-        let hash = hash!(
+        let hash = hash_label!(
             (gc.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
@@ -316,18 +299,11 @@ impl OneOfManyKey {
 
         // 2. OT Encoding of the mask
         instrument::begin("Encode mask", E_COMP_COLOR);
-        let mut mask_encoding = Vec::with_capacity(password_bits);
-        for i in 0..password_bits {
-            let bit_encoding = [
-                encoding[password_bits + i][0].as_bytes().to_vec(),
-                encoding[password_bits + i][1].as_bytes().to_vec(),
-            ];
-            mask_encoding.push(bit_encoding);
-        }
+        let mask_encoding = encoding_to_bytes(&encoding[password_bits..password_bits * 2]);
         instrument::end();
 
         instrument::begin("OT: Encoded Mask", E_PROT_COLOR);
-        let message = MessagePair::from_zipped(mask_encoding.as_slice());
+        let message = MessagePair::from_flat(&mask_encoding, LENGTH);
         let ot = Sender {
             bootstrap: Box::new(chou_orlandi::Receiver),
         };
@@ -377,18 +353,10 @@ impl OneOfManyKey {
 
         // 3. Get encoding of mask
         instrument::begin("OT: Encoded Mask", E_PROT_COLOR);
-        let mut mask_choices = vec![false; password_bits];
-        for i in 0..password_bytes {
-            let byte = mask[i];
-            for b in 0..8 {
-                let choice = ((byte >> b) & 1) == 1;
-                mask_choices[i * 8 + b] = choice;
-            }
-        }
         let encoded_mask_ot = Receiver {
             bootstrap: Box::new(chou_orlandi::Sender),
         };
-        let encoded_mask = encoded_mask_ot.exchange(mask_choices.as_slice(), channel)?;
+        let encoded_mask = encoded_mask_ot.exchange_bits(mask, password_bits, channel)?;
         instrument::end();
 
         instrument::begin("R: Encoded masked password", E_RECV_COLOR);
@@ -401,7 +369,7 @@ impl OneOfManyKey {
 
         instrument::begin("Build encodings", E_COMP_COLOR);
         let encoded_row_length = encoded_mask[0].len();
-        let mask_encoding = payload_to_encoding(encoded_mask, password_bits);
+        let mask_encoding = payload_to_encoding(&encoded_mask, password_bits);
         let server_encoding = bytes_to_encoding(&server_encoded, password_bits, encoded_row_length);
         instrument::end();
 
@@ -413,7 +381,7 @@ impl OneOfManyKey {
 
         instrument::end();
         instrument::end();
-        Ok(Self(hash!(
+        Ok(Self(hash_label!(
             (garbled_circuit.circuit.num_wires - 1).to_be_bytes(),
             1u16.to_be_bytes(),
             &output[0]
@@ -447,8 +415,7 @@ pub fn mfpake_single(
     let many_receiver = ManyOTReceiver {
         internal_receiver: Box::new(chou_orlandi::Receiver),
     };
-    let domain = log2(number_of_passwords);
-    let mut masked_password = many_receiver.exchange(index, domain, channel)?;
+    let mut masked_password = many_receiver.exchange(index, number_of_passwords, channel)?;
     instrument::end();
 
     // 2. Double mask the password and use it for fPAKE
@@ -495,8 +462,7 @@ pub fn mfpake_many(passwords: &[Vec<u8>], threshold: u16, channel: &Channel) ->
     let many_sender = ManyOTSender {
         interal_sender: Box::new(chou_orlandi::Sender),
     };
-    let domain = log2(passwords.len());
-    many_sender.exchange(masked_passwords.as_slice(), domain, channel)?;
+    many_sender.exchange(masked_passwords.as_slice(), channel)?;
     instrument::end();
 
     // 3. fPAKE with our "random" input  with the client
@@ -1,20 +1,31 @@
 use crate::common::*;
 use crate::instrument;
 use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::bitmatrix::BitMatrix;
+use crate::ot::chou_orlandi;
 use crate::ot::coinflip::coinflip_receiver;
 use crate::ot::coinflip::coinflip_sender;
 use crate::ot::common::*;
+use crate::ot::polynomial::gf128_mul_acc_bytes;
 use crate::util::*;
 use rand::{Rng, RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
-
-#[cfg(target_arch = "x86_64")]
-use std::arch::x86_64::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 const K: usize = 128;
 const S: usize = 128;
 const K_BYTES: usize = K / 8;
 
+/// A row of the correlated OT matrices.
+pub type Block = [u8; K_BYTES];
+
+/// Number of OTs actually extended for `n`, the bit matrices need a multiple of 8.
+const fn padded(n: usize) -> usize {
+    n.div_ceil(8) * 8
+}
+
 pub struct Sender {
     pub bootstrap: Box<dyn ObliviousReceiver>,
 }
@@ -26,710 +37,663 @@ pub struct Receiver {
 // -------------------------------------------------------------------------------------------------
 // Sender
 
-impl ObliviousSender for Sender {
-    fn exchange(&self, msg: &Message, channel: &Channel) -> Result<()> {
-        instrument::begin("Apricot Sender", E_FUNC_COLOR);
-
-        debug_assert!(msg.len() % 8 == 0, "Number of messages must be a multiple of 8!");
+impl Sender {
+    #[inline]
+    pub fn new(bootstrap: Box<dyn ObliviousReceiver>) -> Self {
+        Self { bootstrap }
+    }
 
+    /// Correlated OT with a chosen global `delta`, returning `q_j` for each of the `count` OTs.
+    ///
+    /// The receiver learns `t_j = q_j ^ x_j * delta` for its choice bits `x_j`. Bit `i` of
+    /// `delta` is bit `i % 8` of byte `i / 8`, and the same for `q_j` and `t_j`.
+    pub fn correlated(&self, delta: &Block, count: usize, channel: &Channel) -> Result<Vec<Block>> {
+        instrument::begin("Apricot COT Sender", E_FUNC_COLOR);
         let transaction_properties = TransactionProperties {
-            msg_size: msg.len(),
-            protocol: "Apricot".to_string(),
+            msg_size: count,
+            protocol: "Apricot COT".to_string(),
         };
         validate_properties(&transaction_properties, channel)?;
 
-        let l = msg.len() + K + S;
-        const K_BYTES: usize = K / 8;
+        let q = self.cote(delta, padded(count), channel)?;
+        let q = q
+            .rows()
+            .take(count)
+            .map(|row| row.try_into().unwrap())
+            .collect();
+        instrument::end();
+        Ok(q)
+    }
 
-        let (matrix_w, matrix_h) = (K_BYTES, l);
-        let (matrix_t_w, matrix_t_h) = (matrix_h / 8, matrix_w * 8);
-        let matrix_size = matrix_w * matrix_h;
+    /// Random OT, returning the pair of random messages `(r0, r1)` for each of the `count` OTs.
+    pub fn random(&self, count: usize, channel: &Channel) -> Result<Vec<[[u8; 32]; 2]>> {
+        let mut delta = [0u8; K_BYTES];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut delta);
+        let q = self.correlated(&delta, count, channel)?;
+        Ok(q.par_iter()
+            .enumerate()
+            .map(|(j, q)| {
+                let mut q1 = *q;
+                xor_bytes_inplace(&mut q1, &delta);
+                [hash!(j.to_be_bytes(), q), hash!(j.to_be_bytes(), q1)]
+            })
+            .collect())
+    }
 
+    /// COTe with fresh base OTs, see [`cote_sender`].
+    fn cote(&self, delta: &Block, count: usize, channel: &Channel) -> Result<BitMatrix> {
         instrument::begin("Chi Coinflip Receiver", E_PROT_COLOR);
         let seed = coinflip_receiver::<32>(channel)?;
         instrument::end();
 
-        // -- COTe
-        instrument::begin("COTe", E_PROT_COLOR);
-
-        let mut random = ChaCha20Rng::from_entropy();
-        let (s, r) = channel;
-
-        let msg_size = msg.0[0][0].len();
-        s.send(&(msg_size as u16).to_be_bytes())?;
-
-        // Generate random delta
-        instrument::begin("Generate delta", E_COMP_COLOR);
-        let mut delta = [0u8; K_BYTES];
-        let delta = delta.as_mut_slice();
-        random.fill_bytes(delta);
-        let delta_choices = unsafe { unpack_bits_to_vec(delta) };
-        instrument::end();
-
         // do OT.
         instrument::begin("Bootstrap", E_COMP_COLOR);
-        let payloads = self.bootstrap.exchange(&delta_choices, channel)?;
+        let seeds = base_sender(self.bootstrap.as_ref(), delta, channel)?;
         instrument::end();
 
-        instrument::begin("Compute t", E_COMP_COLOR);
-        let mut t = vec![0u8; matrix_t_w * matrix_t_h];
-        for row_idx in 0..matrix_t_h {
-            let row = unsafe { vector_row_mut(&mut t, row_idx, matrix_t_w) };
-            fill_random_bytes_from_seed(&payloads[row_idx], row);
-        }
-        instrument::end();
+        cote_sender(delta, &seeds, 0, |_| seed, count, channel)
+    }
+}
 
-        instrument::begin("Allocate q, q^T", E_COMP_COLOR);
-        let mut q = vec![0u8; matrix_t_w * matrix_t_h];
-        let mut q_transposed = vec![0u8; matrix_w * matrix_h];
-        instrument::end();
+/// Base OTs of the extension sender, returning the seeds chosen by the bits of `delta`.
+fn base_sender(
+    bootstrap: &dyn ObliviousReceiver,
+    delta: &Block,
+    channel: &Channel,
+) -> Result<Vec<[u8; 32]>> {
+    let payloads = bootstrap.exchange_bits(delta, K, channel)?;
+    payloads
+        .iter()
+        .map(|p| {
+            p.try_into()
+                .map_err(|_| OTError::BadMessage("Apricot base OT seed").into())
+        })
+        .collect()
+}
 
-        instrument::begin("Generate Chi", E_COMP_COLOR);
-        let mut chi = vec![0u8; matrix_w * matrix_h];
-        fill_random_bytes_from_seed_array(&seed, &mut chi);
-        instrument::end();
+/// COTe on the base OT `seeds` followed by the correlation check, returning the
+/// `count + K + S` rows of q. The rows of t are taken from stream `tweak` of the seeds, and chi is
+/// seeded by `chi_seed(u)`.
+fn cote_sender(
+    delta: &Block,
+    seeds: &[[u8; 32]],
+    tweak: u64,
+    chi_seed: impl FnOnce(&[u8]) -> [u8; 32],
+    count: usize,
+    channel: &Channel,
+) -> Result<BitMatrix> {
+    let l = count + K + S;
+
+    // -- COTe
+    instrument::begin("COTe", E_PROT_COLOR);
+    let (_, r) = channel;
+
+    instrument::begin("Compute t", E_COMP_COLOR);
+    let mut q = BitMatrix::zeros(K, l);
+    q.par_rows_mut()
+        .zip(seeds.par_iter())
+        .for_each(|(row, seed)| fill_random_bytes_from_seed_stream(seed, tweak, row));
+    instrument::end();
+
+    instrument::begin("Receive u", E_RECV_COLOR);
+    let u: Vec<u8> = r.recv()?;
+    instrument::end();
+    if u.len() != K * l / 8 {
+        return Err(Box::new(OTError::BadMessage("Apricot u matrix")));
+    }
+    let u = BitMatrix::from_vec(u, K, l);
+
+    instrument::begin("Generate Chi", E_COMP_COLOR);
+    let chi = random_matrix(&chi_seed(u.as_bytes()), l);
+    instrument::end();
+
+    instrument::begin("Compute q", E_COMP_COLOR);
+    q.par_rows_mut()
+        .zip(u.par_rows())
+        .enumerate()
+        .filter(|(i, _)| (delta[i / 8] >> (i % 8)) & 1 == 1)
+        .for_each(|(_, (q, u))| xor_bytes_inplace(q, u));
+    instrument::end();
+
+    instrument::begin("Transpose q", E_COMP_COLOR);
+    let q = q.transpose();
+    instrument::end();
+    instrument::end();
+
+    // Correlation Check
+    instrument::begin("Correlation check", E_PROT_COLOR);
+    instrument::begin("Compute q_sum", E_COMP_COLOR);
+    let mut q_sum = [0u8; K_BYTES];
+    for (q_row, chi_row) in q.rows().zip(&chi) {
+        gf128_mul_acc_bytes(&mut q_sum, q_row, chi_row);
+    }
+    instrument::end();
+
+    instrument::begin("Receive x_sum, t_sum", E_RECV_COLOR);
+    let x_sum: Vec<u8> = r.recv()?;
+    let t_sum: Vec<u8> = r.recv()?;
+    instrument::end();
+    if x_sum.len() != K_BYTES || t_sum.len() != K_BYTES {
+        return Err(Box::new(OTError::BadMessage("Apricot correlation check")));
+    }
 
-        instrument::begin("Receive u", E_RECV_COLOR);
-        let u: Vec<u8> = r.recv()?;
-        instrument::end();
+    instrument::begin("Compare correlation sums", E_COMP_COLOR);
+    gf128_mul_acc_bytes(&mut q_sum, &x_sum, delta);
 
-        instrument::begin("Compute q", E_COMP_COLOR);
-        for row_idx in 0..matrix_t_h {
-            let row = unsafe { vector_row_mut(&mut q, row_idx, matrix_t_w) };
-            let d = (delta[row_idx / 8] >> (row_idx % 8)) & 1;
-            if d == 1 {
-                let u_row = unsafe { vector_row(&u, row_idx, matrix_t_w) };
-                xor_inplace(row, u_row);
-            }
+    if t_sum != q_sum {
+        return Err(Box::new(OTError::PolychromaticInput()));
+    }
+    instrument::end();
+    instrument::end();
 
-            let t_row = unsafe { vector_row(&t, row_idx, matrix_t_w) };
-            xor_inplace(row, t_row);
-        }
-        instrument::end();
+    Ok(q)
+}
 
-        instrument::begin("Transpose q", E_COMP_COLOR);
-        transpose_matrix(&q, &mut q_transposed, matrix_t_h, matrix_t_w, matrix_w);
-        instrument::end();
-        instrument::end();
+impl ObliviousSender for Sender {
+    fn exchange(&self, msg: &Message, channel: &Channel) -> Result<()> {
+        instrument::begin("Apricot Sender", E_FUNC_COLOR);
 
-        // -- ROTe
-        instrument::begin("ROTe", E_PROT_COLOR);
+        let transaction_properties = TransactionProperties {
+            msg_size: msg.len(),
+            protocol: "Apricot".to_string(),
+        };
+        validate_properties(&transaction_properties, channel)?;
 
-        // Correlation Check
-        instrument::begin("Compute q_sum", E_COMP_COLOR);
-        debug_assert_eq!(matrix_size, chi.len());
-        let mut q_sum = vec![0u8; matrix_w];
-        for row_idx in 0..matrix_h {
-            let q_row = unsafe { vector_row(&q_transposed, row_idx, matrix_w) };
-            let chi_row = unsafe { vector_row(&chi, row_idx, matrix_w) };
+        let mut random = ChaCha20Rng::from_entropy();
 
-            polynomial_mul_acc(q_sum.as_mut_slice(), q_row, chi_row);
-        }
+        // Generate random delta
+        instrument::begin("Generate delta", E_COMP_COLOR);
+        let mut delta = [0u8; K_BYTES];
+        random.fill_bytes(&mut delta);
         instrument::end();
 
-        instrument::begin("Receive x_sum, t_sum", E_RECV_COLOR);
-        let x_sum: Vec<u8> = r.recv()?;
-        let t_sum: Vec<u8> = r.recv()?;
+        let q = self.cote(&delta, padded(msg.len()), channel)?;
+        send_messages(msg, &q, &delta, channel)?;
+
         instrument::end();
+        Ok(())
+    }
+}
 
-        instrument::begin("Compare correlation sums", E_COMP_COLOR);
-        debug_assert_eq!(matrix_w, x_sum.len());
-        debug_assert_eq!(matrix_w, t_sum.len());
-        polynomial_mul_acc(q_sum.as_mut_slice(), x_sum.as_slice(), delta);
+/// Message lengths, sent ahead of the ciphertexts. Any two messages may differ in length, but the
+/// common case of a single length is sent as one number.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum Lengths {
+    Uniform(u64),
+    Mixed(Vec<[u64; 2]>),
+}
 
-        if !eq(t_sum.as_slice(), q_sum.as_slice()) {
-            return Err(Box::new(OTError::PolychromaticInput()));
+impl Lengths {
+    fn of(msg: &Message) -> Self {
+        let first = msg.0.first().map_or(0, |[m0, _]| m0.len());
+        if msg
+            .0
+            .iter()
+            .all(|[m0, m1]| m0.len() == first && m1.len() == first)
+        {
+            Self::Uniform(first as u64)
+        } else {
+            Self::Mixed(
+                msg.0
+                    .iter()
+                    .map(|[m0, m1]| [m0.len() as u64, m1.len() as u64])
+                    .collect(),
+            )
         }
-        instrument::end();
+    }
 
-        // Randomize
-        instrument::begin("Randomize", E_COMP_COLOR);
-        let msg_count = msg.len();
-        let msg_pair_size = msg_size * 2;
-        let mut d = vec![0u8; msg_count * msg_pair_size];
-
-        let thread_count = pick_suitable_thread_count(msg_count);
-        let rows_in_chunk = msg_count / thread_count;
-        let bytes_in_chunk = rows_in_chunk * msg_pair_size;
-        rayon::scope(|s| {
-            let q_transposed = &q_transposed;
-            let delta = &delta;
-
-            let d_chunks = d.chunks_mut(bytes_in_chunk);
-            for (chunk_idx, chunk) in d_chunks.enumerate() {
-                let handle = s.spawn(move |_| {
-                    instrument::begin("Randomize - worker", E_COMP_COLOR);
-
-                    let mut q_buffer = vec![0u8; matrix_w];
-                    let q_buffer = q_buffer.as_mut_slice();
-
-                    let rows_in_this_chunk = chunk.len() / msg_pair_size;
-                    for i in 0..rows_in_this_chunk {
-                        let row_idx = chunk_idx * rows_in_chunk + i;
-
-                        let q_row = unsafe { vector_row(q_transposed, row_idx, matrix_w) };
-                        let v0 = hash!(row_idx.to_be_bytes(), q_row);
-
-                        let d0_idx = i * msg_pair_size;
-                        let d1_idx = d0_idx + msg_size;
-
-                        let m0 = msg.0[row_idx][0];
-                        let mut chacha = ChaCha20Rng::from_seed(v0);
-                        let plain = unsafe { vector_slice_mut(chunk, d0_idx, msg_size) };
-                        chacha.fill_bytes(plain);
-                        xor_inplace(plain, m0);
-
-                        zero_inplace(q_buffer);
-                        xor(q_buffer, q_row, delta);
-                        let v1 = hash!(row_idx.to_be_bytes(), &q_buffer);
-
-                        let m1 = msg.0[row_idx][1];
-                        let mut chacha = ChaCha20Rng::from_seed(v1);
-                        let plain = unsafe { vector_slice_mut(chunk, d1_idx, msg_size) };
-                        chacha.fill_bytes(plain);
-                        xor_inplace(plain, m1);
-                    }
+    /// Offset and lengths of each of the `n` ciphertext pairs, if they add up to `total` bytes.
+    fn offsets(&self, n: usize, total: usize) -> Option<Vec<(usize, [usize; 2])>> {
+        let lengths: Vec<[u64; 2]> = match self {
+            Self::Uniform(len) => vec![[*len, *len]; n],
+            Self::Mixed(lengths) if lengths.len() == n => lengths.clone(),
+            Self::Mixed(_) => return None,
+        };
+        let mut offset = 0usize;
+        let mut offsets = Vec::with_capacity(n);
+        for [l0, l1] in lengths {
+            let (l0, l1) = (usize::try_from(l0).ok()?, usize::try_from(l1).ok()?);
+            offsets.push((offset, [l0, l1]));
+            offset = offset.checked_add(l0)?.checked_add(l1)?;
+        }
+        (offset == total).then_some(offsets)
+    }
+}
 
-                    instrument::end();
-                    ()
-                });
-            }
-        });
-        /*
-        for row_idx in 0..msg.len() {
-            let q_row = unsafe { vector_row(&q_transposed, row_idx, matrix_w) };
+/// Mask every message pair with the random OTs from the rows of `q`, and send them.
+fn send_messages(msg: &Message, q: &BitMatrix, delta: &Block, channel: &Channel) -> Result<()> {
+    let (s, _) = channel;
+
+    // -- ROTe
+    instrument::begin("ROTe", E_PROT_COLOR);
+
+    // Randomize
+    instrument::begin("Randomize", E_COMP_COLOR);
+    let mut d = vec![0u8; msg.0.iter().map(|[m0, m1]| m0.len() + m1.len()).sum()];
+    let mut rows = Vec::with_capacity(msg.len());
+    let mut rest = d.as_mut_slice();
+    for [m0, m1] in &msg.0 {
+        let (row, tail) = rest.split_at_mut(m0.len() + m1.len());
+        rows.push(row.split_at_mut(m0.len()));
+        rest = tail;
+    }
+    rows.into_par_iter()
+        .zip(msg.0.par_iter())
+        .enumerate()
+        .for_each(|(row_idx, ((d0, d1), [m0, m1]))| {
+            let q_row = q.row(row_idx);
             let v0 = hash!(row_idx.to_be_bytes(), q_row);
+            ChaCha20Rng::from_seed(v0).fill_bytes(d0);
+            xor_bytes_inplace(d0, m0);
+
+            let mut q1: Block = q_row.try_into().unwrap();
+            xor_bytes_inplace(&mut q1, delta);
+            let v1 = hash!(row_idx.to_be_bytes(), q1);
+            ChaCha20Rng::from_seed(v1).fill_bytes(d1);
+            xor_bytes_inplace(d1, m1);
+        });
+    instrument::end();
 
-            let d0_idx = row_idx * msg_size * 2;
-            let d1_idx = d0_idx + msg_size;
-
-            let m0 = msg.0[row_idx][0];
-            let mut chacha = ChaCha20Rng::from_seed(v0);
-            let plain = unsafe { vector_slice_mut(&mut d, d0_idx, msg_size) };
-            chacha.fill_bytes(plain);
-            xor_inplace(plain, m0);
-
-            let q_row = unsafe { vector_row_mut(&mut q_transposed, row_idx, matrix_w) };
-            xor_inplace(q_row, delta);
-            let v1 = hash!(row_idx.to_be_bytes(), &q_row);
-
-            let m1 = msg.0[row_idx][1];
-            let mut chacha = ChaCha20Rng::from_seed(v1);
-            let plain = unsafe { vector_slice_mut(&mut d, d1_idx, msg_size) };
-            chacha.fill_bytes(plain);
-            xor_inplace(plain, m1);
-        }
-        */
-        instrument::end();
-
-        instrument::begin("Send d", E_SEND_COLOR);
-        s.send(d.as_slice())?;
-        instrument::end();
-        instrument::end();
-        instrument::end();
+    instrument::begin("Send d", E_SEND_COLOR);
+    s.send(&bincode::serialize(&Lengths::of(msg))?)?;
+    s.send(d.as_slice())?;
+    instrument::end();
+    instrument::end();
 
-        Ok(())
-    }
+    Ok(())
 }
 
 // -------------------------------------------------------------------------------------------------
 // Receiver
 
-impl ObliviousReceiver for Receiver {
-    fn exchange(&self, choices: &[bool], channel: &Channel) -> Result<Payload> {
-        instrument::begin("Apricot Receiver", E_FUNC_COLOR);
-
-        debug_assert!(choices.len() % 8 == 0, "Number of choices must be a multiple of 8!");
+impl Receiver {
+    #[inline]
+    pub fn new(bootstrap: Box<dyn ObliviousSender>) -> Self {
+        Self { bootstrap }
+    }
 
+    /// Correlated OT, returning `t_j = q_j ^ x_j * delta` for each choice bit `x_j`.
+    ///
+    /// See [`Sender::correlated`].
+    pub fn correlated(&self, choices: &[bool], channel: &Channel) -> Result<Vec<Block>> {
+        instrument::begin("Apricot COT Receiver", E_FUNC_COLOR);
         let transaction_properties = TransactionProperties {
             msg_size: choices.len(),
-            protocol: "Apricot".to_string(),
+            protocol: "Apricot COT".to_string(),
         };
         validate_properties(&transaction_properties, channel)?;
 
-        let l = choices.len() + K + S;
-        let l_bytes = l / 8;
-        const K_BYTES: usize = K / 8;
+        let t = self.cote(&pack_bits(choices), channel)?;
+        let t = t
+            .rows()
+            .take(choices.len())
+            .map(|row| row.try_into().unwrap())
+            .collect();
+        instrument::end();
+        Ok(t)
+    }
 
-        let (matrix_w, matrix_h) = (K_BYTES, l);
-        let (matrix_t_w, matrix_t_h) = (matrix_h / 8, matrix_w * 8);
+    /// Random OT on random choice bits `b`, returning `b` and the chosen random messages `r_b`.
+    pub fn random(&self, count: usize, channel: &Channel) -> Result<(Vec<bool>, Vec<[u8; 32]>)> {
+        let mut random = ChaCha20Rng::from_entropy();
+        let choices: Vec<bool> = (0..count).map(|_| random.gen()).collect();
+        let t = self.correlated(&choices, channel)?;
+        let messages = t
+            .par_iter()
+            .enumerate()
+            .map(|(j, t)| hash!(j.to_be_bytes(), t))
+            .collect();
+        Ok((choices, messages))
+    }
 
+    /// COTe with fresh base OTs, see [`cote_receiver`].
+    fn cote(&self, choices: &[u8], channel: &Channel) -> Result<BitMatrix> {
         let mut random = ChaCha20Rng::from_entropy();
-        let (s, r) = channel;
 
         instrument::begin("Chi Coinflip Sender", E_PROT_COLOR);
         let seed = coinflip_sender::<32>(channel)?;
         instrument::end();
 
-        // INITIALIZATION
-        instrument::begin("COTe", E_PROT_COLOR);
-        instrument::begin("Initialization", E_COMP_COLOR);
-        let bonus: [bool; K + S] = random.gen();
+        instrument::begin("Bootstrap", E_COMP_COLOR);
         let seed0: [[u8; 32]; K] = random.gen();
         let seed1: [[u8; 32]; K] = random.gen();
-        instrument::end();
-
-        instrument::begin("Receive msg_size", E_COMP_COLOR);
-        let msg_size_bytes = r.recv()?;
-        let msg_size = ((msg_size_bytes[0] as u16) << 8) | (msg_size_bytes[1] as u16);
-        instrument::end();
-
-        instrument::begin("Bootstrap", E_COMP_COLOR);
         let msg = Message::from_unzipped(&seed0, &seed1);
         self.bootstrap.exchange(&msg, channel)?;
         instrument::end();
 
-        // EXTENSION
-        instrument::begin("Compute t0", E_COMP_COLOR);
-        let mut t0 = vec![0u8; matrix_t_w * matrix_t_h];
-        for row_idx in 0..matrix_t_h {
-            let row = unsafe { vector_row_mut(&mut t0, row_idx, matrix_t_w) };
-            fill_random_bytes_from_seed_array(&seed0[row_idx], row);
-        }
-        instrument::end();
-
-        instrument::begin("Compute t1", E_COMP_COLOR);
-        let mut t1 = vec![0u8; matrix_t_w * matrix_t_h];
-        for row_idx in 0..matrix_t_h {
-            let row = unsafe { vector_row_mut(&mut t1, row_idx, matrix_t_w) };
-            fill_random_bytes_from_seed_array(&seed1[row_idx], row);
-        }
-        instrument::end();
-
-        instrument::begin("Pack choices", E_COMP_COLOR);
-        let padded_choices = [choices, &bonus].concat();
-        let mut packed_choices = vec![0u8; l_bytes];
-        let packed_choices = packed_choices.as_mut_slice();
-        for i in 0..l_bytes {
-            for b in 0..8 {
-                let index = i * 8 + b;
-                if padded_choices[index] {
-                    packed_choices[i] |= 1 << b;
-                }
-            }
-        }
-        instrument::end();
-
-        instrument::begin("Compute u", E_COMP_COLOR);
-        let mut u = vec![0u8; matrix_t_w * matrix_t_h];
-        for row_idx in 0..matrix_t_h {
-            let u_row = unsafe { vector_row_mut(&mut u, row_idx, matrix_t_w) };
-
-            let t0_row = unsafe { vector_row(&t0, row_idx, matrix_t_w) };
-            let t1_row = unsafe { vector_row(&t1, row_idx, matrix_t_w) };
-            xor(u_row, t0_row, t1_row);
-
-            xor_inplace(u_row, packed_choices);
-        }
-        instrument::end();
-
-        instrument::begin("Send u", E_SEND_COLOR);
-        s.send(u.as_slice())?;
-        instrument::end();
-
-        instrument::begin("Transpose t0 -> t", E_COMP_COLOR);
-        let mut t = vec![0u8; matrix_w * matrix_h];
-        transpose_matrix(&t0, &mut t, matrix_t_h, matrix_t_w, matrix_w);
-        instrument::end();
-
-        instrument::end();
-
-        // -- Check correlation / ROTe
-        instrument::begin("ROTe", E_PROT_COLOR);
-
-        instrument::begin("Generate Chi", E_COMP_COLOR);
-        let mut chi = vec![0u8; matrix_w * matrix_h];
-        fill_random_bytes_from_seed_array(&seed, &mut chi);
-        instrument::end();
-
-        instrument::begin("Check Correlation", E_COMP_COLOR);
-        let mut x_sum = vec![0u8; matrix_w];
-        let mut t_sum = vec![0u8; matrix_w];
-        for row_idx in 0..matrix_h {
-            let chi_row = unsafe { vector_row(&chi, row_idx, matrix_w) };
-            if padded_choices[row_idx] {
-                xor_inplace(x_sum.as_mut_slice(), chi_row);
-            }
-
-            let t_row = unsafe { vector_row(&t, row_idx, matrix_w) };
-            polynomial_mul_acc(t_sum.as_mut_slice(), t_row, chi_row);
-        }
-        instrument::end();
-
-        instrument::begin("Send x_sum, t_sum", E_SEND_COLOR);
-        s.send(x_sum.as_slice())?;
-        s.send(t_sum.as_slice())?;
-        instrument::end();
-        instrument::end();
-
-        // -- DeROT
-        instrument::begin("DeROT", E_PROT_COLOR);
-
-        instrument::begin("Compute v", E_COMP_COLOR);
-        let mut v = vec![0u8; 32 * matrix_h];
-        let thread_count = pick_suitable_thread_count(matrix_h);
-        let rows_in_chunk = matrix_h / thread_count;
-        let bytes_in_chunk = rows_in_chunk * 32;
-        rayon::scope(|s| {
-            let t = &t;
-
-            let chunks = v.chunks_mut(bytes_in_chunk);
-            for (chunk_idx, chunk) in chunks.enumerate() {
-                let handle = s.spawn(move |_| {
-                    instrument::begin("Compute v - worker", E_COMP_COLOR);
-
-                    let rows_in_this_chunk = chunk.len() / 32;
-                    for i in 0..rows_in_this_chunk {
-                        let row_idx = chunk_idx * rows_in_chunk + i;
-
-                        let row = unsafe { vector_row_mut(chunk, i, 32) };
-                        let t_row = unsafe { vector_row(&t, row_idx, matrix_w) };
-                        let hash = hash!(row_idx.to_be_bytes(), t_row);
-                        xor_inplace(row, &hash);
-                    }
+        cote_receiver(&seed0, &seed1, 0, |_| seed, choices, channel)
+    }
+}
 
-                    instrument::end();
-                    ()
-                });
-            }
-        });
-        /*
-        for row_idx in 0..matrix_h {
-            let row = unsafe { vector_row_mut(&mut v, row_idx, 32) };
-            let t_row = unsafe { vector_row(&t, row_idx, matrix_w) };
-            let hash = hash!(row_idx.to_be_bytes(), t_row);
-            xor_inplace(row, &hash);
+/// COTe on the base OT seed pairs followed by the correlation check, returning the
+/// `8 * choices.len() + K + S` rows of t for the packed `choices`. See [`cote_sender`] for
+/// `tweak` and `chi_seed`.
+fn cote_receiver(
+    seed0: &[[u8; 32]],
+    seed1: &[[u8; 32]],
+    tweak: u64,
+    chi_seed: impl FnOnce(&[u8]) -> [u8; 32],
+    choices: &[u8],
+    channel: &Channel,
+) -> Result<BitMatrix> {
+    let l = 8 * choices.len() + K + S;
+
+    let mut random = ChaCha20Rng::from_entropy();
+    let (s, _) = channel;
+
+    // INITIALIZATION
+    instrument::begin("COTe", E_PROT_COLOR);
+    instrument::begin("Initialization", E_COMP_COLOR);
+    let bonus: [u8; (K + S) / 8] = random.gen();
+    instrument::end();
+
+    // EXTENSION
+    instrument::begin("Compute t0", E_COMP_COLOR);
+    let mut t0 = BitMatrix::zeros(K, l);
+    t0.par_rows_mut()
+        .zip(seed0.par_iter())
+        .for_each(|(row, seed)| fill_random_bytes_from_seed_stream(seed, tweak, row));
+    instrument::end();
+
+    instrument::begin("Compute t1", E_COMP_COLOR);
+    let mut t1 = BitMatrix::zeros(K, l);
+    t1.par_rows_mut()
+        .zip(seed1.par_iter())
+        .for_each(|(row, seed)| fill_random_bytes_from_seed_stream(seed, tweak, row));
+    instrument::end();
+
+    instrument::begin("Pack choices", E_COMP_COLOR);
+    let padded_choices = [choices, &bonus].concat();
+    let mut u = BitMatrix::from_row(&padded_choices, K);
+    instrument::end();
+
+    instrument::begin("Compute u", E_COMP_COLOR);
+    u ^= &t0;
+    u ^= &t1;
+    instrument::end();
+
+    instrument::begin("Send u", E_SEND_COLOR);
+    s.send(u.as_bytes())?;
+    instrument::end();
+
+    instrument::begin("Transpose t0 -> t", E_COMP_COLOR);
+    let t = t0.transpose();
+    instrument::end();
+
+    instrument::end();
+
+    // -- Check correlation
+    instrument::begin("Correlation check", E_PROT_COLOR);
+
+    instrument::begin("Generate Chi", E_COMP_COLOR);
+    let chi = random_matrix(&chi_seed(u.as_bytes()), l);
+    instrument::end();
+
+    instrument::begin("Check Correlation", E_COMP_COLOR);
+    let mut x_sum = [0u8; K_BYTES];
+    let mut t_sum = [0u8; K_BYTES];
+    for (j, (t_row, chi_row)) in t.rows().zip(&chi).enumerate() {
+        if bit(&padded_choices, j) {
+            xor_bytes_inplace(&mut x_sum, chi_row);
         }
-        */
-        instrument::end();
-
-        instrument::begin("Allocate y", E_COMP_COLOR);
-        let choices_count = choices.len();
-        let mut y = vec![vec![0u8; msg_size as usize]; choices_count];
-        instrument::end();
-
-        instrument::begin("Receive d", E_RECV_COLOR);
-        let d: Vec<u8> = r.recv()?;
-        instrument::end();
-
-        instrument::begin("De-randomize", E_COMP_COLOR);
-        let msg_count = choices.len() / 8;
-        let msg_size = (d.len() / 2) / choices.len();
-
-        //*
-        let thread_count = pick_suitable_thread_count(choices_count);
-        let rows_in_chunk = choices_count / thread_count;
-        rayon::scope(|s| {
-            let d = &d;
-            let v = &v;
-            let packed_choices = &packed_choices;
-
-            let chunks = y.chunks_mut(rows_in_chunk);
-            for (chunk_idx, chunk) in chunks.enumerate() {
-                let handle = s.spawn(move |_| {
-                    instrument::begin("De-randomize - worker", E_COMP_COLOR);
-
-                    let rows_in_this_chunk = chunk.len();
-                    for i in 0..rows_in_this_chunk {
-                        let row_idx = chunk_idx * rows_in_chunk + i;
-
-                        let j = row_idx;
-                        let b = j % 8;
-                        let i_actual = (j - b) / 8;
-
-                        let v_row = unsafe { vector_row(&v, j, 32) };
-                        let mut chacha = ChaCha20Rng::from_seed(*array_from_slice(v_row));
-                        chacha.fill_bytes(chunk[i].as_mut_slice());
+        gf128_mul_acc_bytes(&mut t_sum, t_row, chi_row);
+    }
+    instrument::end();
 
-                        let choice = ((packed_choices[i_actual] >> b) & 1) as usize;
-                        let d_idx = (j * msg_size * 2) + choice * msg_size;
-                        let d = unsafe { vector_slice(&d, d_idx, msg_size) };
+    instrument::begin("Send x_sum, t_sum", E_SEND_COLOR);
+    s.send(&x_sum)?;
+    s.send(&t_sum)?;
+    instrument::end();
+    instrument::end();
 
-                        xor_inplace(chunk[i].as_mut_slice(), d);
-                    }
+    Ok(t)
+}
 
-                    instrument::end();
-                    ()
-                });
-            }
-        });
-        /*
-        for i in 0..(choices.len() / 8) {
-            for b in 0..8 {
-                let j = i * 8 + b;
+impl ObliviousReceiver for Receiver {
+    fn exchange_bits(&self, choices: &[u8], len: usize, channel: &Channel) -> Result<Payload> {
+        instrument::begin("Apricot Receiver", E_FUNC_COLOR);
+        let choices = padded_choices(choices, len);
 
-                let v_row = unsafe { vector_row(&v, j, 32) };
-                let mut chacha = ChaCha20Rng::from_seed(*array_from_slice(v_row));
-                chacha.fill_bytes(y[j].as_mut_slice());
+        let transaction_properties = TransactionProperties {
+            msg_size: len,
+            protocol: "Apricot".to_string(),
+        };
+        validate_properties(&transaction_properties, channel)?;
 
-                let choice = ((packed_choices[i] >> b) & 1) as usize;
-                let d_idx = (j * msg_size * 2) + choice * msg_size;
-                let d = unsafe { vector_slice(&d, d_idx, msg_size) };
+        let t = self.cote(&choices, channel)?;
+        let y = receive_messages(&choices, len, &t, channel)?;
 
-                xor_inplace(y[j].as_mut_slice(), d);
-            }
-        }
-        */
         instrument::end();
-        instrument::end();
-        instrument::end();
-
         Ok(y)
     }
 }
 
-// -------------------------------------------------------------------------------------------------
-// Array/slice helpers
-#[inline]
-unsafe fn unpack_bits_to_vec(bytes: &[u8]) -> Vec<bool> {
-    let mut bits = Vec::with_capacity(8 * bytes.len());
-    for i in 0..bytes.len() {
-        let s = bytes[i];
-        for i in 0..8 {
-            bits.push((s >> i) & 1 == 1);
-        }
+/// The first `len` bits of `choices` padded with zeros to `padded(len)` bits.
+///
+/// # Panics
+/// If `choices` holds fewer than `len` bits.
+fn padded_choices(choices: &[u8], len: usize) -> Vec<u8> {
+    assert!(
+        len <= 8 * choices.len(),
+        "Not enough bytes for {} choices",
+        len
+    );
+    let mut choices = choices[..len.div_ceil(8)].to_vec();
+    if !len.is_multiple_of(8) {
+        choices[len / 8] &= (1 << (len % 8)) - 1;
     }
-
-    bits
+    choices
 }
 
+/// Bit `j` of the packed `bits`.
 #[inline]
-fn array_from_slice<const N: usize>(vector: &[u8]) -> &[u8; N] {
-    unsafe { std::mem::transmute(vector.as_ptr()) }
+fn bit(bits: &[u8], j: usize) -> bool {
+    (bits[j / 8] >> (j % 8)) & 1 == 1
 }
 
-// -------------------------------------------------------------------------------------------------
-// RNG
-#[inline]
-fn fill_random_bytes_from_seed(seed: &Vec<u8>, bytes: &mut [u8]) {
-    let mut random = ChaCha20Rng::from_seed(*array_from_slice(seed.as_slice()));
-    random.fill_bytes(bytes);
-}
+/// Receive the masked message pairs and unmask the chosen ones of the first `len` OTs with the
+/// rows of `t`.
+fn receive_messages(
+    choices: &[u8],
+    len: usize,
+    t: &BitMatrix,
+    channel: &Channel,
+) -> Result<Payload> {
+    let (_, r) = channel;
+
+    // -- DeROT
+    instrument::begin("DeROT", E_PROT_COLOR);
+
+    instrument::begin("Receive d", E_RECV_COLOR);
+    let lengths: Lengths = bincode::deserialize(&r.recv()?)?;
+    let d: Vec<u8> = r.recv()?;
+    instrument::end();
+    let offsets = lengths
+        .offsets(len, d.len())
+        .ok_or(OTError::BadMessage("Apricot ciphertexts"))?;
+
+    instrument::begin("De-randomize", E_COMP_COLOR);
+    let mut y = Payload::with_lengths(offsets.iter().enumerate().map(|(j, (_, [l0, l1]))| {
+        if bit(choices, j) {
+            *l1
+        } else {
+            *l0
+        }
+    }));
+    y.par_iter_mut()
+        .zip(offsets.par_iter())
+        .enumerate()
+        .for_each(|(j, (y, &(offset, [l0, _])))| {
+            let v = hash!(j.to_be_bytes(), t.row(j));
+            let offset = if bit(choices, j) { offset + l0 } else { offset };
+            ChaCha20Rng::from_seed(v).fill_bytes(y);
+            xor_bytes_inplace(y, &d[offset..offset + y.len()]);
+        });
+    instrument::end();
+    instrument::end();
 
-#[inline]
-fn fill_random_bytes_from_seed_array(seed: &[u8; 32], bytes: &mut [u8]) {
-    let mut random = ChaCha20Rng::from_seed(*seed);
-    random.fill_bytes(bytes);
+    Ok(y)
 }
 
 // -------------------------------------------------------------------------------------------------
-// Helpers for working with arrays of packed bits
-#[inline]
-fn xor(destination: &mut [u8], left: &[u8], right: &[u8]) {
-    debug_assert_eq!(left.len(), right.len());
-    debug_assert_eq!(left.len(), destination.len());
-
-    for i in 0..left.len() {
-        destination[i] = left[i] ^ right[i];
-    }
+// Session
+
+/// Base OTs done once per connection, serving any number of extension batches in both
+/// directions.
+///
+/// Batch `i` in a direction expands stream `i` of the base OT seeds, so no two batches share
+/// rows. Instead of a coin flip per batch, chi is derived from a key flipped at setup, the batch
+/// and u.
+pub struct OtExtensionSession {
+    delta: Block,
+    chosen_seeds: Vec<[u8; 32]>,
+    seed0: Vec<[u8; 32]>,
+    seed1: Vec<[u8; 32]>,
+    key: [u8; 32],
+    sent: AtomicU64,
+    received: AtomicU64,
 }
 
-#[inline]
-fn xor_inplace(destination: &mut [u8], right: &[u8]) {
-    debug_assert_eq!(right.len(), destination.len());
-
-    for i in 0..right.len() {
-        destination[i] ^= right[i];
+/// Sender of a batch over an [`OtExtensionSession`].
+pub struct SessionSender<'a>(&'a OtExtensionSession);
+
+/// Receiver of a batch over an [`OtExtensionSession`].
+pub struct SessionReceiver<'a>(&'a OtExtensionSession);
+
+impl OtExtensionSession {
+    /// Run the base OTs with Chou-Orlandi. Exactly one end of the channel must be the `leader`.
+    pub fn new(leader: bool, channel: &Channel) -> Result<Self> {
+        Self::with_bootstrap(
+            leader,
+            &chou_orlandi::Sender,
+            &chou_orlandi::Receiver,
+            channel,
+        )
     }
-}
 
-#[inline]
-fn zero_inplace(destination: &mut [u8]) {
-    for i in 0..destination.len() {
-        destination[i] ^= 0;
-    }
-}
+    /// Run the base OTs for both directions with the given OTs.
+    pub fn with_bootstrap(
+        leader: bool,
+        sender: &dyn ObliviousSender,
+        receiver: &dyn ObliviousReceiver,
+        channel: &Channel,
+    ) -> Result<Self> {
+        instrument::begin("Apricot Session", E_FUNC_COLOR);
+        let mut random = ChaCha20Rng::from_entropy();
+        let mut delta = [0u8; K_BYTES];
+        random.fill_bytes(&mut delta);
+        let seed0: Vec<[u8; 32]> = (0..K).map(|_| random.gen()).collect();
+        let seed1: Vec<[u8; 32]> = (0..K).map(|_| random.gen()).collect();
+        let msg = Message::from_unzipped(&seed0, &seed1);
 
-#[inline]
-fn eq(left: &[u8], right: &[u8]) -> bool {
-    debug_assert_eq!(left.len(), right.len());
+        instrument::begin("Bootstrap", E_COMP_COLOR);
+        let (chosen_seeds, key) = if leader {
+            let chosen_seeds = base_sender(receiver, &delta, channel)?;
+            sender.exchange(&msg, channel)?;
+            (chosen_seeds, coinflip_sender::<32>(channel)?)
+        } else {
+            sender.exchange(&msg, channel)?;
+            let chosen_seeds = base_sender(receiver, &delta, channel)?;
+            (chosen_seeds, coinflip_receiver::<32>(channel)?)
+        };
+        instrument::end();
 
-    for i in 0..left.len() {
-        if left[i] != right[i] {
-            return false;
-        }
+        instrument::end();
+        Ok(Self {
+            delta,
+            chosen_seeds,
+            seed0,
+            seed1,
+            key,
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+        })
     }
 
-    true
-}
-
-#[inline]
-fn transpose_matrix(
-    source: &[u8],
-    target: &mut [u8],
-    transposed_height: usize,
-    transposed_width: usize,
-    original_width: usize,
-) {
-    // TODO: There is probably a better way of doing this!
-    for row_idx in 0..transposed_height {
-        for col_idx in 0..transposed_width {
-            let source_byte = source[index_1d(row_idx, col_idx, transposed_width)];
-            for b in 0..8 {
-                let source_bit = (source_byte >> b) & 1;
-                let target_row = col_idx * 8 + b;
-                let target_col = row_idx / 8;
-                let target_shift = row_idx % 8;
-
-                let idx = index_1d(target_row, target_col, original_width);
-                target[idx] |= source_bit << target_shift;
-            }
-        }
+    /// Send a batch of chosen messages.
+    pub const fn sender(&self) -> SessionSender<'_> {
+        SessionSender(self)
     }
-}
 
-#[inline]
-fn pick_suitable_thread_count(rows: usize) -> usize {
-    let logical_cores = num_cpus::get();
-
-    if rows < logical_cores {
-        return rows;
+    /// Receive a batch of chosen messages.
+    pub const fn receiver(&self) -> SessionReceiver<'_> {
+        SessionReceiver(self)
     }
 
-    if rows % logical_cores == 0 {
-        return logical_cores;
+    fn chi_seed(&self, tweak: u64, u: &[u8]) -> [u8; 32] {
+        hash!(self.key, tweak.to_be_bytes(), u)
     }
-
-    return rows / logical_cores;
-}
-
-// -------------------------------------------------------------------------------------------------
-// Polynomials
-
-#[inline]
-#[cfg(target_arch = "x86_64")]
-unsafe fn _mm_slli_si128_1(value: __m128i) -> __m128i {
-    const SHIFT: i32 = 1;
-    let carry = _mm_bslli_si128(value, 8);
-    let carry = _mm_srli_epi64(carry, 64 - SHIFT);
-    let value = _mm_slli_epi64(value, SHIFT);
-    return _mm_or_si128(value, carry);
-}
-#[inline]
-#[cfg(target_arch = "x86_64")]
-unsafe fn _mm_slli_si128_2(value: __m128i) -> __m128i {
-    const SHIFT: i32 = 2;
-    let carry = _mm_bslli_si128(value, 8);
-    let carry = _mm_srli_epi64(carry, 64 - SHIFT);
-    let value = _mm_slli_epi64(value, SHIFT);
-    return _mm_or_si128(value, carry);
-}
-#[inline]
-#[cfg(target_arch = "x86_64")]
-unsafe fn _mm_slli_si128_7(value: __m128i) -> __m128i {
-    const SHIFT: i32 = 7;
-    let carry = _mm_bslli_si128(value, 8);
-    let carry = _mm_srli_epi64(carry, 64 - SHIFT);
-    let value = _mm_slli_epi64(value, SHIFT);
-    return _mm_or_si128(value, carry);
 }
-#[inline]
-#[cfg(target_arch = "x86_64")]
-pub unsafe fn polynomial_gf128_reduce(x32: __m128i, x10: __m128i) -> __m128i {
-    use std::arch::x86_64::*;
-    let x2 = _mm_extract_epi64(x32, 0) as u64;
-    let x3 = _mm_extract_epi64(x32, 1) as u64;
-
-    let a = x3 >> 63;
-    let b = x3 >> 62;
-    let c = x3 >> 57;
-    let d = x2 ^ a ^ b ^ c;
-
-    let x3d = _mm_set_epi64x(x3 as i64, d as i64);
-    let e = _mm_slli_si128_1(x3d);
-    let f = _mm_slli_si128_2(x3d);
-    let g = _mm_slli_si128_7(x3d);
-
-    let h = _mm_xor_si128(x3d, e);
-    let h = _mm_xor_si128(h, f);
-    let h = _mm_xor_si128(h, g);
-
-    return _mm_xor_si128(h, x10);
-}
-
-#[inline]
-#[cfg(target_arch = "x86_64")]
-fn polynomial_mul_acc(destination: &mut [u8], left: &[u8], right: &[u8]) {
-    use std::arch::x86_64::*;
-    unsafe {
-        let left_bytes = left.as_ptr() as *const __m128i;
-        let right_bytes = right.as_ptr() as *const __m128i;
-        let result_bytes = destination.as_mut_ptr() as *mut __m128i;
-
-        let a = _mm_lddqu_si128(left_bytes);
-        let b = _mm_lddqu_si128(right_bytes);
 
-        let c = _mm_clmulepi64_si128(a, b, 0x00);
-        let d = _mm_clmulepi64_si128(a, b, 0x11);
-        let e = _mm_clmulepi64_si128(a, b, 0x01);
-        let f = _mm_clmulepi64_si128(a, b, 0x10);
+impl ObliviousSender for SessionSender<'_> {
+    fn exchange(&self, msg: &Message, channel: &Channel) -> Result<()> {
+        instrument::begin("Apricot Session Sender", E_FUNC_COLOR);
 
-        let ef = _mm_xor_si128(e, f);
-        let lower = _mm_slli_si128(ef, 64 / 8);
-        let upper = _mm_srli_si128(ef, 64 / 8);
+        let transaction_properties = TransactionProperties {
+            msg_size: msg.len(),
+            protocol: "Apricot session".to_string(),
+        };
+        validate_properties(&transaction_properties, channel)?;
 
-        let left = _mm_xor_si128(d, upper);
-        let right = _mm_xor_si128(c, lower);
+        let session = self.0;
+        let tweak = session.sent.fetch_add(1, Ordering::SeqCst);
+        let q = cote_sender(
+            &session.delta,
+            &session.chosen_seeds,
+            tweak,
+            |u| session.chi_seed(tweak, u),
+            padded(msg.len()),
+            channel,
+        )?;
+        send_messages(msg, &q, &session.delta, channel)?;
 
-        let reduced = polynomial_gf128_reduce(left, right);
-        *result_bytes = _mm_xor_si128(*result_bytes, reduced);
+        instrument::end();
+        Ok(())
     }
 }
 
-#[inline]
-#[cfg(target_arch = "aarch64")]
-fn polynomial_mul_acc(destination: &mut [u8], left: &[u8], right: &[u8]) {
-    use core::arch::aarch64::*;
-
-    #[inline(always)]
-    unsafe fn pmull<const A_LANE: i32, const B_LANE: i32>(a: poly64x2_t, b: poly64x2_t) -> u128 {
-        vmull_p64(vgetq_lane_p64(a, A_LANE), vgetq_lane_p64(b, B_LANE))
-    }
+impl ObliviousReceiver for SessionReceiver<'_> {
+    fn exchange_bits(&self, choices: &[u8], len: usize, channel: &Channel) -> Result<Payload> {
+        instrument::begin("Apricot Session Receiver", E_FUNC_COLOR);
+        let choices = padded_choices(choices, len);
 
-    const fn gf128_reduce(x32: u128, x10: u128) -> u128 {
-        let x2 = x32 as u64;
-        let x3 = (x32 >> 64) as u64;
-
-        let a = x3 >> 63;
-        let b = x3 >> 62;
-        let c = x3 >> 57;
-        let d = x2 ^ a ^ b ^ c;
-
-        let x3d = ((x3 as u128) << 64) | (d as u128); // maybe here?
-        let e = x3d << 1;
-        let f = x3d << 2;
-        let g = x3d << 7;
+        let transaction_properties = TransactionProperties {
+            msg_size: len,
+            protocol: "Apricot session".to_string(),
+        };
+        validate_properties(&transaction_properties, channel)?;
 
-        let h = x3d ^ e ^ f ^ g;
+        let session = self.0;
+        let tweak = session.received.fetch_add(1, Ordering::SeqCst);
+        let t = cote_receiver(
+            &session.seed0,
+            &session.seed1,
+            tweak,
+            |u| session.chi_seed(tweak, u),
+            &choices,
+            channel,
+        )?;
+        let y = receive_messages(&choices, len, &t, channel)?;
 
-        h ^ x10
+        instrument::end();
+        Ok(y)
     }
+}
 
-    unsafe {
-        let left = left.as_ptr() as *const u64;
-        let right = right.as_ptr() as *const u64;
-        let result = destination.as_mut_ptr() as *mut u128;
-
-        let a = vld1q_p64(left);
-        let b = vld1q_p64(right);
-
-        // polynomial multiply
-        let z = vdupq_n_p64(0);
-
-        let c = pmull::<0, 0>(a, b);
-        let d = pmull::<1, 1>(a, b);
-        let e = pmull::<0, 1>(a, b);
-        let f = pmull::<1, 0>(a, b);
-
-        let ef = vaddq_p128(e, f);
-        let lower = vextq_p64(z, vreinterpretq_p64_p128(ef), 1);
-        let upper = vextq_p64(vreinterpretq_p64_p128(ef), z, 1);
-        let left = vaddq_p128(d, vreinterpretq_p128_p64(upper));
-        let right = vaddq_p128(c, vreinterpretq_p128_p64(lower));
+// -------------------------------------------------------------------------------------------------
+// RNG
+#[inline]
+fn fill_random_bytes_from_seed_stream(seed: &[u8; 32], stream: u64, bytes: &mut [u8]) {
+    let mut random = ChaCha20Rng::from_seed(*seed);
+    random.set_stream(stream);
+    random.fill_bytes(bytes);
+}
 
-        let reduced = gf128_reduce(left, right);
-        *result ^= reduced;
-    }
+/// The `rows` x `K` matrix chi of the correlation check.
+fn random_matrix(seed: &[u8; 32], rows: usize) -> BitMatrix {
+    let mut chi = BitMatrix::zeros(rows, K);
+    ChaCha20Rng::from_seed(*seed).fill_bytes(chi.as_mut_bytes());
+    chi
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -767,7 +731,7 @@ mod tests {
                 };
                 let choices = [true; 8 << 2];
                 let msg = receiver.exchange(&choices, &ch2).unwrap();
-                assert_eq!(msg[0], b"World");
+                assert_eq!(&msg[0], b"World");
             });
 
         h1.unwrap().join().unwrap();
@@ -782,7 +746,7 @@ mod tests {
         let ch1 = (s1, r2);
         let ch2 = (s2, r1);
 
-        fn print_vec(vec: &Vec<u8>) {
+        fn print_vec(vec: &[u8]) {
             print!("[");
             for i in 0..vec.len() {
                 print!("{:#04x}", vec[i]);
@@ -813,9 +777,9 @@ mod tests {
 
                 for i in 0..CASES {
                     print!("{:04}: ", i);
-                    print_vec(&m0[i].to_vec());
+                    print_vec(&m0[i]);
                     print!(", ");
-                    print_vec(&m1[i].to_vec());
+                    print_vec(&m1[i]);
                     println!();
                 }
 
@@ -859,4 +823,205 @@ mod tests {
         h1.unwrap().join().unwrap();
         h2.unwrap().join().unwrap();
     }
+
+    #[test]
+    fn test_apricot_correlated() {
+        use crate::ot::chou_orlandi;
+        let (ch1, ch2) = raw::local_channel_pair();
+        let delta: Block = *b"0123456789abcdef";
+        let choices: Vec<bool> = (0..100).map(|i| i % 3 == 0).collect();
+
+        use std::thread;
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            sender.correlated(&delta, 100, &ch1).unwrap()
+        });
+        let c = choices.clone();
+        let h2 = thread::spawn(move || {
+            let receiver = Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            };
+            receiver.correlated(&c, &ch2).unwrap()
+        });
+        let q = h1.join().unwrap();
+        let t = h2.join().unwrap();
+
+        assert_eq!((q.len(), t.len()), (100, 100));
+        for j in 0..100 {
+            let mut expected = q[j];
+            if choices[j] {
+                xor_bytes_inplace(&mut expected, &delta);
+            }
+            assert_eq!(t[j], expected);
+        }
+    }
+
+    #[test]
+    fn test_apricot_random() {
+        use crate::ot::chou_orlandi;
+        let (ch1, ch2) = raw::local_channel_pair();
+
+        use std::thread;
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            sender.random(50, &ch1).unwrap()
+        });
+        let h2 = thread::spawn(move || {
+            let receiver = Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            };
+            receiver.random(50, &ch2).unwrap()
+        });
+        let pairs = h1.join().unwrap();
+        let (choices, messages) = h2.join().unwrap();
+
+        assert_eq!((pairs.len(), choices.len(), messages.len()), (50, 50, 50));
+        for j in 0..50 {
+            assert_eq!(messages[j], pairs[j][choices[j] as usize]);
+            assert_ne!(messages[j], pairs[j][!choices[j] as usize]);
+        }
+    }
+
+    #[test]
+    fn test_apricot_session() {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let batch = |i: u8| -> (Vec<[u8; 16]>, Vec<[u8; 16]>, Vec<bool>) {
+            let m0 = (0..64u8).map(|j| [i ^ j; 16]).collect();
+            let m1 = (0..64u8).map(|j| [!(i ^ j); 16]).collect();
+            let c = (0..64u8).map(|j| (i + j).is_multiple_of(3)).collect();
+            (m0, m1, c)
+        };
+
+        use std::thread;
+        let h1 = thread::spawn(move || {
+            let session = OtExtensionSession::new(true, &ch1).unwrap();
+            let mut received = vec![];
+            for i in 0..3 {
+                let (m0, m1, c) = batch(i);
+                let msg = Message::from_unzipped(&m0, &m1);
+                session.sender().exchange(&msg, &ch1).unwrap();
+                received.push(session.receiver().exchange(&c, &ch1).unwrap());
+            }
+            received
+        });
+        let h2 = thread::spawn(move || {
+            let session = OtExtensionSession::new(false, &ch2).unwrap();
+            let mut received = vec![];
+            for i in 0..3 {
+                let (m0, m1, c) = batch(i);
+                received.push(session.receiver().exchange(&c, &ch2).unwrap());
+                let msg = Message::from_unzipped(&m0, &m1);
+                session.sender().exchange(&msg, &ch2).unwrap();
+            }
+            received
+        });
+
+        for received in [h1.join().unwrap(), h2.join().unwrap()] {
+            for (i, payload) in received.iter().enumerate() {
+                let (m0, m1, c) = batch(i as u8);
+                for j in 0..64 {
+                    let expected = if c[j] { m1[j] } else { m0[j] };
+                    assert_eq!(payload[j], expected);
+                }
+            }
+        }
+    }
+
+    fn transfer(m0: Vec<Vec<u8>>, m1: Vec<Vec<u8>>, choices: Vec<bool>) -> Payload {
+        use crate::ot::chou_orlandi;
+        use std::thread;
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            let msg = Message::from_unzipped(&m0, &m1);
+            sender.exchange(&msg, &ch1).unwrap();
+        });
+        let receiver = Receiver {
+            bootstrap: Box::new(chou_orlandi::Sender),
+        };
+        let payload = receiver.exchange(&choices, &ch2).unwrap();
+        h1.join().unwrap();
+        payload
+    }
+
+    #[test]
+    fn test_apricot_counts() {
+        for n in [0, 1, 7, 9, 13] {
+            let m0: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8; 5]).collect();
+            let m1: Vec<Vec<u8>> = (0..n).map(|i| vec![!i as u8; 5]).collect();
+            let choices: Vec<bool> = (0..n).map(|i| i % 2 == 1).collect();
+            let payload = transfer(m0.clone(), m1.clone(), choices.clone());
+            assert_eq!(payload.len(), n);
+            for i in 0..n {
+                assert_eq!(&payload[i], if choices[i] { &m1[i] } else { &m0[i] });
+            }
+        }
+    }
+
+    #[test]
+    fn test_apricot_packed_choices() {
+        use crate::ot::chou_orlandi;
+        use std::thread;
+        // Bits past the 11 choices are set but must be ignored.
+        let choices = [0b1010_0110, 0b1111_1101, 0xFF];
+        let m0: Vec<Vec<u8>> = (0..11).map(|i| vec![i as u8; 4]).collect();
+        let m1: Vec<Vec<u8>> = (0..11).map(|i| vec![!i as u8; 4]).collect();
+        let (ch1, ch2) = raw::local_channel_pair();
+        let msg = (m0.clone(), m1.clone());
+        let h1 = thread::spawn(move || {
+            let sender = Sender::new(Box::new(chou_orlandi::Receiver));
+            let msg = Message::from_unzipped(&msg.0, &msg.1);
+            sender.exchange(&msg, &ch1).unwrap();
+        });
+        let receiver = Receiver::new(Box::new(chou_orlandi::Sender));
+        let payload = receiver.exchange_bits(&choices, 11, &ch2).unwrap();
+        h1.join().unwrap();
+
+        assert_eq!(payload.len(), 11);
+        for (i, &choice) in u8_vec_to_bool_vec(&choices)[..11].iter().enumerate() {
+            assert_eq!(&payload[i], if choice { &m1[i] } else { &m0[i] });
+        }
+    }
+
+    #[test]
+    fn test_apricot_lengths() {
+        let big = u16::MAX as usize + 1000;
+        let m0 = vec![
+            vec![1u8; 3],
+            vec![],
+            vec![2u8; big],
+            vec![3u8; 40],
+            vec![4u8; 1],
+        ];
+        let m1 = vec![
+            vec![5u8; 17],
+            vec![6u8; 2],
+            vec![7u8; 1],
+            vec![],
+            vec![8u8; big],
+        ];
+        let choices = vec![true, false, false, true, true];
+        let payload = transfer(m0.clone(), m1.clone(), choices.clone());
+        for i in 0..5 {
+            assert_eq!(&payload[i], if choices[i] { &m1[i] } else { &m0[i] });
+        }
+    }
+
+    #[test]
+    fn test_lengths_offsets() {
+        let uniform = Lengths::Uniform(4);
+        assert_eq!(uniform.offsets(2, 16), Some(vec![(0, [4, 4]), (8, [4, 4])]));
+        assert_eq!(uniform.offsets(2, 15), None);
+        let mixed = Lengths::Mixed(vec![[1, 2], [0, 3]]);
+        assert_eq!(mixed.offsets(2, 6), Some(vec![(0, [1, 2]), (3, [0, 3])]));
+        assert_eq!(mixed.offsets(3, 6), None);
+        assert_eq!(Lengths::Mixed(vec![[u64::MAX, 2]]).offsets(1, 1), None);
+        assert_eq!(Lengths::Uniform(u64::MAX).offsets(0, 0), Some(vec![]));
+    }
 }
@@ -0,0 +1,476 @@
+// Offline/online OT via Beaver derandomization (https://doi.org/10.1007/3-540-44750-4_8).
+//
+// Offline, apricot generates random OTs: the sender gets pairs (r0, r1) and the receiver a
+// random choice c with r_c. Both sides queue them in a pool, which can be saved per peer and
+// reloaded between connections. Online, the receiver with choice b sends d = b ^ c and the sender
+// answers with e_i = x_i ^ r_{i ^ d}, so the receiver learns x_b = e_b ^ r_c. That is a single
+// round of XORs, and both pools advance by the same number of OTs.
+//
+// The online step replaces `validate_properties` with the pool offset and the length of d, so
+// that an out of sync or exhausted pool is detected without an extra round. The sender consumes
+// its pairs before answering and the receiver only once the answer arrived, so if an answer is
+// lost the sender is ahead. It then replies with its offset instead, the receiver drops the OTs
+// the sender already used and the transfer can be retried.
+//
+// A saved pool is as secret as the messages it will protect: the sender pairs (r0, r1) reveal
+// every future transfer with that peer, so pool files are only readable by their owner.
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::apricot;
+use crate::ot::common::*;
+use crate::util::*;
+
+#[derive(Debug)]
+pub enum PoolError {
+    /// The pool holds fewer random OTs than the transfer needs.
+    Exhausted { needed: usize, available: usize },
+    /// The two pools have consumed a different number of random OTs.
+    OutOfSync { ours: u64, theirs: u64 },
+}
+
+impl Error for PoolError {}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Exhausted { needed, available } => {
+                write!(
+                    f,
+                    "Need {} precomputed OTs, only {} left",
+                    needed, available
+                )
+            }
+            Self::OutOfSync { ours, theirs } => {
+                write!(f, "OT pools out of sync at offset {} != {}", ours, theirs)
+            }
+        }
+    }
+}
+
+/// Answer of the sender to the choice flips d.
+#[derive(Serialize, Deserialize)]
+enum Reply {
+    Messages(Vec<[Vec<u8>; 2]>),
+    /// The pools are at different offsets, the sender being at this one.
+    OutOfSync(u64),
+    /// The sender has only this many random OTs left.
+    Exhausted(usize),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SenderState {
+    offset: u64,
+    pairs: VecDeque<[[u8; 32]; 2]>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ReceiverState {
+    offset: u64,
+    ots: VecDeque<(bool, [u8; 32])>,
+}
+
+/// Precomputed random OTs for the sending side of a peer.
+#[derive(Default)]
+pub struct SenderPool(Mutex<SenderState>);
+
+/// Precomputed random OTs for the receiving side of a peer.
+#[derive(Default)]
+pub struct ReceiverPool(Mutex<ReceiverState>);
+
+/// Pad of `len` bytes from a random OT message, expanded only for messages over 32 bytes.
+fn pad(r: &[u8; 32], len: usize) -> Vec<u8> {
+    if len <= r.len() {
+        return r[..len].to_vec();
+    }
+    let mut pad = vec![0u8; len];
+    ChaCha20Rng::from_seed(*r).fill_bytes(&mut pad);
+    pad
+}
+
+fn pool_path(dir: &Path, peer: &str, role: &str) -> PathBuf {
+    let name: String = hash!(peer.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    dir.join(format!("{}.{}.pool", name, role))
+}
+
+/// Write `state` to `path`, readable only by the owner.
+fn save<T: Serialize>(state: &T, path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode only applies to new files.
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options.open(path)?;
+    file.write_all(&bincode::serialize(state)?)?;
+    Ok(())
+}
+
+fn load<T: Default + for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(bincode::deserialize(&bytes)?),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+impl SenderPool {
+    /// Add `count` random OTs, run against [`ReceiverPool::precompute`].
+    pub fn precompute(&self, ot: &apricot::Sender, count: usize, ch: &Channel) -> Result<()> {
+        instrument::begin("Precompute random OT", E_FUNC_COLOR);
+        let pairs = ot.random(count, ch)?;
+        self.0.lock().unwrap().pairs.extend(pairs);
+        instrument::end();
+        Ok(())
+    }
+
+    /// Number of random OTs left.
+    pub fn available(&self) -> usize {
+        self.0.lock().unwrap().pairs.len()
+    }
+
+    /// Store the pool for `peer` in `dir`.
+    ///
+    /// The file holds both random messages of every OT, anyone reading it learns all future
+    /// transfers to `peer`. It is created readable by the owner only and must be kept secret.
+    pub fn save(&self, dir: &Path, peer: &str) -> Result<()> {
+        save(&*self.0.lock().unwrap(), &pool_path(dir, peer, "sender"))
+    }
+
+    /// Load the pool for `peer` from `dir`, or an empty pool if there is none.
+    pub fn load(dir: &Path, peer: &str) -> Result<Self> {
+        Ok(Self(Mutex::new(load(&pool_path(dir, peer, "sender"))?)))
+    }
+}
+
+impl ReceiverPool {
+    /// Add `count` random OTs, run against [`SenderPool::precompute`].
+    pub fn precompute(&self, ot: &apricot::Receiver, count: usize, ch: &Channel) -> Result<()> {
+        instrument::begin("Precompute random OT", E_FUNC_COLOR);
+        let (choices, messages) = ot.random(count, ch)?;
+        self.0
+            .lock()
+            .unwrap()
+            .ots
+            .extend(choices.into_iter().zip(messages));
+        instrument::end();
+        Ok(())
+    }
+
+    /// Number of random OTs left.
+    pub fn available(&self) -> usize {
+        self.0.lock().unwrap().ots.len()
+    }
+
+    /// Store the pool for `peer` in `dir`.
+    ///
+    /// The file holds the random choices, which reveal the choices of future transfers to the
+    /// sender. It is created readable by the owner only and must be kept secret.
+    pub fn save(&self, dir: &Path, peer: &str) -> Result<()> {
+        save(&*self.0.lock().unwrap(), &pool_path(dir, peer, "receiver"))
+    }
+
+    /// Load the pool for `peer` from `dir`, or an empty pool if there is none.
+    pub fn load(dir: &Path, peer: &str) -> Result<Self> {
+        Ok(Self(Mutex::new(load(&pool_path(dir, peer, "receiver"))?)))
+    }
+}
+
+impl ObliviousSender for SenderPool {
+    fn exchange(&self, msg: &Message, ch: &Channel) -> Result<()> {
+        instrument::begin("Beaver Sender", E_FUNC_COLOR);
+        let (s, r) = ch;
+        let mut state = self.0.lock().unwrap();
+
+        instrument::begin("Receive d", E_RECV_COLOR);
+        let (offset, d): (u64, Vec<u8>) = bincode::deserialize(&r.recv()?)?;
+        instrument::end();
+        if offset != state.offset {
+            s.send(&bincode::serialize(&Reply::OutOfSync(state.offset))?)?;
+            return Err(Box::new(PoolError::OutOfSync {
+                ours: state.offset,
+                theirs: offset,
+            }));
+        }
+        if d.len() != msg.len().div_ceil(8) {
+            return Err(Box::new(OTError::BadMessage("Beaver choice flips")));
+        }
+        if state.pairs.len() < msg.len() {
+            s.send(&bincode::serialize(&Reply::Exhausted(state.pairs.len()))?)?;
+            return Err(Box::new(PoolError::Exhausted {
+                needed: msg.len(),
+                available: state.pairs.len(),
+            }));
+        }
+
+        instrument::begin("Derandomize", E_COMP_COLOR);
+        let e: Vec<[Vec<u8>; 2]> = msg
+            .0
+            .iter()
+            .zip(state.pairs.drain(..msg.len()))
+            .enumerate()
+            .map(|(i, ([m0, m1], r))| {
+                let flip = (d[i / 8] >> (i % 8)) & 1 == 1;
+                let (r0, r1) = if flip { (&r[1], &r[0]) } else { (&r[0], &r[1]) };
+                [
+                    xor_bytes(m0, &pad(r0, m0.len())),
+                    xor_bytes(m1, &pad(r1, m1.len())),
+                ]
+            })
+            .collect();
+        state.offset += msg.len() as u64;
+        drop(state);
+        instrument::end();
+
+        instrument::begin("Send e", E_SEND_COLOR);
+        s.send(&bincode::serialize(&Reply::Messages(e))?)?;
+        instrument::end();
+
+        instrument::end();
+        Ok(())
+    }
+}
+
+impl ObliviousReceiver for ReceiverPool {
+    fn exchange(&self, choices: &[bool], ch: &Channel) -> Result<Payload> {
+        instrument::begin("Beaver Receiver", E_FUNC_COLOR);
+        let (s, r) = ch;
+        let mut state = self.0.lock().unwrap();
+        if state.ots.len() < choices.len() {
+            return Err(Box::new(PoolError::Exhausted {
+                needed: choices.len(),
+                available: state.ots.len(),
+            }));
+        }
+
+        // The pool is locked for the whole transfer, and only advanced once e has arrived.
+        instrument::begin("Send d", E_SEND_COLOR);
+        let mut d = vec![0u8; choices.len().div_ceil(8)];
+        for (i, (&b, (c, _))) in choices.iter().zip(&state.ots).enumerate() {
+            d[i / 8] |= ((b ^ c) as u8) << (i % 8);
+        }
+        s.send(&bincode::serialize(&(state.offset, d))?)?;
+        instrument::end();
+
+        instrument::begin("Receive e", E_RECV_COLOR);
+        let reply: Reply = bincode::deserialize(&r.recv()?)?;
+        instrument::end();
+        let e = match reply {
+            Reply::Messages(e) if e.len() == choices.len() => e,
+            Reply::Messages(_) => {
+                return Err(Box::new(OTError::BadMessage("Beaver ciphertexts")));
+            }
+            Reply::OutOfSync(theirs) => {
+                let ours = state.offset;
+                // The sender has used the OTs up to its offset, drop them so a retry can succeed.
+                if theirs > ours {
+                    let skip = (theirs - ours).min(state.ots.len() as u64) as usize;
+                    state.ots.drain(..skip);
+                    state.offset = theirs;
+                }
+                return Err(Box::new(PoolError::OutOfSync { ours, theirs }));
+            }
+            Reply::Exhausted(available) => {
+                return Err(Box::new(PoolError::Exhausted {
+                    needed: choices.len(),
+                    available,
+                }));
+            }
+        };
+        let ots: Vec<(bool, [u8; 32])> = state.ots.drain(..choices.len()).collect();
+        state.offset += choices.len() as u64;
+        drop(state);
+
+        instrument::begin("Derandomize", E_COMP_COLOR);
+        let mut msg =
+            Payload::with_lengths(e.iter().zip(choices).map(|(e, &b)| e[b as usize].len()));
+        msg.par_iter_mut()
+            .zip(e.par_iter().zip(choices.par_iter().zip(ots.par_iter())))
+            .for_each(|(m, (e, (&b, (_, r))))| {
+                m.copy_from_slice(&e[b as usize]);
+                xor_bytes_inplace(m, &pad(r, m.len()));
+            });
+        instrument::end();
+
+        instrument::end();
+        Ok(msg)
+    }
+
+    fn exchange_bits(&self, choices: &[u8], len: usize, ch: &Channel) -> Result<Payload> {
+        self.exchange(&unpack_bits(choices, len), ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::chou_orlandi;
+    use std::thread;
+
+    fn pools(count: usize) -> (SenderPool, ReceiverPool) {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let pool = SenderPool::default();
+            let ot = apricot::Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            };
+            pool.precompute(&ot, count, &ch1).unwrap();
+            pool
+        });
+        let pool = ReceiverPool::default();
+        let ot = apricot::Receiver {
+            bootstrap: Box::new(chou_orlandi::Sender),
+        };
+        pool.precompute(&ot, count, &ch2).unwrap();
+        (h1.join().unwrap(), pool)
+    }
+
+    fn transfer(
+        sender: SenderPool,
+        receiver: &ReceiverPool,
+        m0: &[Vec<u8>],
+        m1: &[Vec<u8>],
+        choices: &[bool],
+    ) -> (SenderPool, Result<Payload>) {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let (m0, m1) = (m0.to_vec(), m1.to_vec());
+        let h1 = thread::spawn(move || {
+            let msg = Message::from_unzipped(&m0, &m1);
+            let _ = sender.exchange(&msg, &ch1);
+            sender
+        });
+        let payload = receiver.exchange(choices, &ch2);
+        drop(ch2);
+        (h1.join().unwrap(), payload)
+    }
+
+    #[test]
+    fn test_beaver() {
+        let (sender, receiver) = pools(100);
+        let m0: Vec<Vec<u8>> = (0..30u8).map(|i| vec![i; 1 + i as usize * 3]).collect();
+        let m1: Vec<Vec<u8>> = (0..30u8).map(|i| vec![!i; 1 + i as usize * 3]).collect();
+        let choices: Vec<bool> = (0..30).map(|i| i % 3 == 1).collect();
+
+        let (sender, payload) = transfer(sender, &receiver, &m0, &m1, &choices);
+        let payload = payload.unwrap();
+        for (i, &c) in choices.iter().enumerate() {
+            assert_eq!(&payload[i], if c { &m1[i] } else { &m0[i] });
+        }
+        assert_eq!((sender.available(), receiver.available()), (70, 70));
+
+        let (_, payload) = transfer(sender, &receiver, &m0, &m1, &choices);
+        assert_eq!(payload.unwrap()[1], m1[1]);
+    }
+
+    #[test]
+    fn test_exhausted_and_out_of_sync() {
+        let (sender, receiver) = pools(8);
+        let m = vec![vec![0u8; 4]; 9];
+        let (sender, payload) = transfer(sender, &receiver, &m, &m, &[false; 9]);
+        let err = payload.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PoolError>(),
+            Some(PoolError::Exhausted {
+                needed: 9,
+                available: 8
+            })
+        ));
+
+        // Nothing was used up by the failed transfer.
+        assert_eq!((sender.available(), receiver.available()), (8, 8));
+
+        receiver.0.lock().unwrap().offset = 1;
+        let (_, payload) = transfer(sender, &receiver, &m[..1], &m[..1], &[true]);
+        assert!(matches!(
+            payload.unwrap_err().downcast_ref::<PoolError>(),
+            Some(PoolError::OutOfSync { ours: 1, theirs: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_lost_reply() {
+        let (sender, receiver) = pools(10);
+        let m0 = vec![vec![1u8; 4]; 3];
+        let m1 = vec![vec![2u8; 4]; 3];
+
+        // The sender answers a transfer whose reply never reaches the receiver.
+        let ((s1, r1), (s2, r2)) = raw::local_channel_pair();
+        s2.send(&bincode::serialize(&(0u64, vec![0u8])).unwrap())
+            .unwrap();
+        sender
+            .exchange(&Message::from_unzipped(&m0, &m1), &(s1, r1))
+            .unwrap();
+        drop(r2);
+        assert_eq!((sender.available(), receiver.available()), (7, 10));
+
+        let (sender, payload) = transfer(sender, &receiver, &m0, &m1, &[true; 3]);
+        assert!(matches!(
+            payload.unwrap_err().downcast_ref::<PoolError>(),
+            Some(PoolError::OutOfSync { ours: 0, theirs: 3 })
+        ));
+        assert_eq!((sender.available(), receiver.available()), (7, 7));
+
+        let (_, payload) = transfer(sender, &receiver, &m0, &m1, &[true, false, true]);
+        let payload = payload.unwrap();
+        assert_eq!(payload[0], m1[0]);
+        assert_eq!(payload[1], m0[1]);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = std::env::temp_dir().join(format!("beaver-{}", std::process::id()));
+        let (sender, receiver) = pools(16);
+        sender.save(&dir, "client").unwrap();
+        receiver.save(&dir, "server").unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let path = pool_path(&dir, "client", "sender");
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let sender = SenderPool::load(&dir, "client").unwrap();
+        let receiver = ReceiverPool::load(&dir, "server").unwrap();
+        assert_eq!(
+            ReceiverPool::load(&dir, "someone else")
+                .unwrap()
+                .available(),
+            0
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        let m0 = vec![vec![1u8; 40]; 16];
+        let m1 = vec![vec![2u8; 40]; 16];
+        let choices: Vec<bool> = (0..16).map(|i| i % 2 == 0).collect();
+        let (_, payload) = transfer(sender, &receiver, &m0, &m1, &choices);
+        let payload = payload.unwrap();
+        assert_eq!(payload[0], m1[0]);
+        assert_eq!(payload[1], m0[1]);
+    }
+}
@@ -5,13 +5,13 @@ use std::ops::BitAndAssign;
 use std::ops::BitXor;
 use std::ops::BitXorAssign;
 use std::ops::Index;
-use std::ops::Range;
-use std::ops::RangeFrom;
-use std::ops::RangeFull;
-use std::ops::RangeInclusive;
+use std::ops::IndexMut;
 
 use rayon::prelude::*;
 
+use crate::ot::transpose::transpose;
+use crate::util::pack_bits;
+
 use rayon::iter::FromParallelIterator;
 use rayon::prelude::IntoParallelIterator;
 // BitMatrix and BitVector
@@ -50,15 +50,15 @@ impl BitVector {
         Self(vec)
     }
 
+    /// Copy `bytes` into blocks, `bytes` need not be aligned for `Block`.
     #[inline]
-    pub fn from_bytes(vec: &[u8]) -> Self {
-        unsafe {
-            // TODO: Fallback if alignment fails.
-            let (head, body, tail) = vec.align_to::<Block>();
-            debug_assert!(tail.is_empty());
-            debug_assert!(head.is_empty());
-            Self::from_vec(body.to_vec())
-        }
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        debug_assert!(bytes.len().is_multiple_of(mem::size_of::<Block>()));
+        let blocks = bytes
+            .chunks_exact(mem::size_of::<Block>())
+            .map(|b| Block::from_ne_bytes(b.try_into().unwrap()))
+            .collect();
+        Self::from_vec(blocks)
     }
 
     #[inline]
@@ -167,95 +167,146 @@ impl BitAndAssign for BitVector {
     }
 }
 
-#[repr(transparent)]
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A bit matrix stored row after row in a single buffer, bit `j` of a row is bit `j % BLOCK_SIZE`
+/// of block `j / BLOCK_SIZE`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct BitMatrix {
-    rows: Vec<BitVector>,
+    data: Vec<Block>,
+    rows: usize,
+    cols: usize,
 }
 
 impl BitMatrix {
     #[inline]
     pub fn new(rows: Vec<BitVector>) -> Self {
-        Self { rows }
+        rows.into_iter().collect()
     }
 
     #[inline]
-    pub fn monochrome(choices: &[bool], size: usize) -> Self {
-        let l = choices.len();
-        let mut packed_choices: Vec<Block> = vec![0; l / BLOCK_SIZE];
-        for i in 0..l / BLOCK_SIZE {
-            for b in 0..BLOCK_SIZE {
-                packed_choices[i] |= (choices[i * BLOCK_SIZE + b] as Block) << b;
-            }
-        }
-        let mut x = vec![vec![0; l / BLOCK_SIZE]; size];
-        for row in x.iter_mut() {
-            row[..(l / BLOCK_SIZE)].copy_from_slice(&packed_choices);
-        }
-        Self::from(x)
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Self::from_vec(vec![0; rows * cols / BLOCK_SIZE], rows, cols)
+    }
+
+    /// A `rows` x `cols` matrix from its rows concatenated.
+    ///
+    /// # Panics
+    /// If `cols` is not a multiple of `BLOCK_SIZE` or `data` has the wrong length.
+    #[inline]
+    pub fn from_vec(data: Vec<Block>, rows: usize, cols: usize) -> Self {
+        assert!(cols.is_multiple_of(BLOCK_SIZE));
+        assert_eq!(data.len(), rows * cols / BLOCK_SIZE);
+        Self { data, rows, cols }
+    }
+
+    /// A matrix with `rows` copies of `choices`, cut to a whole number of blocks.
+    #[inline]
+    pub fn monochrome(choices: &[bool], rows: usize) -> Self {
+        let l = choices.len() / BLOCK_SIZE * BLOCK_SIZE;
+        Self::from_row(&pack_bits(&choices[..l]), rows)
+    }
+
+    /// A matrix with `rows` copies of `row`.
+    #[inline]
+    pub fn from_row(row: &[Block], rows: usize) -> Self {
+        Self::from_vec(row.repeat(rows), rows, row.len() * BLOCK_SIZE)
+    }
+
+    #[inline]
+    pub const fn dims(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    /// Number of blocks in a row.
+    #[inline]
+    const fn row_len(&self) -> usize {
+        self.cols / BLOCK_SIZE
+    }
+
+    #[inline]
+    pub fn row(&self, index: usize) -> &[Block] {
+        &self.data[index * self.row_len()..(index + 1) * self.row_len()]
+    }
+
+    #[inline]
+    pub fn row_mut(&mut self, index: usize) -> &mut [Block] {
+        let len = self.row_len();
+        &mut self.data[index * len..(index + 1) * len]
+    }
+
+    #[inline]
+    pub fn rows(&self) -> std::slice::ChunksExact<'_, Block> {
+        self.data.chunks_exact(self.row_len().max(1))
+    }
+
+    #[inline]
+    pub fn rows_mut(&mut self) -> std::slice::ChunksExactMut<'_, Block> {
+        let len = self.row_len().max(1);
+        self.data.chunks_exact_mut(len)
+    }
+
+    #[inline]
+    pub fn par_rows(&self) -> rayon::slice::ChunksExact<'_, Block> {
+        self.data.par_chunks_exact(self.row_len().max(1))
+    }
+
+    #[inline]
+    pub fn par_rows_mut(&mut self) -> rayon::slice::ChunksExactMut<'_, Block> {
+        let len = self.row_len().max(1);
+        self.data.par_chunks_exact_mut(len)
     }
 
     #[inline]
-    pub fn dims(&self) -> (usize, usize) {
-        (self.rows.len(), self.rows[0].len())
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let (head, body, tail) = self.data.align_to::<u8>();
+            debug_assert!(tail.is_empty());
+            debug_assert!(head.is_empty());
+            body
+        }
     }
 
-    // PERF: Work on bytes instead of booleans. See below.
-    // // https://stackoverflow.com/questions/31742483/how-would-you-transpose-a-binary-matrix
     #[inline]
     pub fn transpose(&self) -> Self {
         let (rows, cols) = self.dims();
-        let mut raw: Vec<Vec<Block>> = vec![vec![0; rows / BLOCK_SIZE]; cols];
-        let source = self.rows.as_slice();
-        for row_idx in 0..rows {
-            for col_idx in 0..cols / BLOCK_SIZE {
-                let source_byte = source[row_idx].as_slice()[col_idx];
-                for b in 0..BLOCK_SIZE {
-                    let source_bit = (source_byte >> b) & 1;
-
-                    let target_row = col_idx * BLOCK_SIZE + b;
-                    let target_col = row_idx / BLOCK_SIZE;
-                    let target_shift = row_idx % BLOCK_SIZE;
-
-                    raw[target_row][target_col] |= source_bit << target_shift;
-                }
-            }
-        }
-        let raw: Vec<BitVector> = unsafe { mem::transmute(raw) };
-        Self { rows: raw }
+        let mut target = Self::zeros(cols, rows);
+        transpose(self.as_bytes(), target.as_mut_bytes(), rows, cols);
+        target
     }
-}
 
-fn tranpose_64(matrix: &mut [u64; 64]) {
-    let mut m = 0x0000_0000_FFFF_FFFF; // 32 bit.
-    let mut j = 16;
-    while j != 0 {
-        let mut k = 0;
-        while k < 64 {
-            let t = matrix[k] ^ (matrix[k + j] >> j) & m;
-            matrix[k] ^= t;
-            matrix[k + j] ^= t << j;
-            k = (k + j + 1) & !j;
+    #[inline]
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        unsafe {
+            let (head, body, tail) = self.data.align_to_mut::<u8>();
+            debug_assert!(tail.is_empty());
+            debug_assert!(head.is_empty());
+            body
         }
-        m ^= m << j;
-        j >>= 1;
     }
 }
 
 impl From<Vec<Vec<Block>>> for BitMatrix {
     #[inline]
     fn from(rows: Vec<Vec<Block>>) -> Self {
-        unsafe { mem::transmute(rows) }
+        rows.into_iter().map(BitVector::from_vec).collect()
     }
 }
 
 impl FromIterator<BitVector> for BitMatrix {
+    /// # Panics
+    /// If the rows differ in length.
     fn from_iter<I: IntoIterator<Item = BitVector>>(iter: I) -> Self {
-        let mut rows = Vec::new();
+        let mut data = Vec::new();
+        let mut rows = 0;
+        let mut cols = 0;
         for row in iter {
-            rows.push(row);
+            if rows == 0 {
+                cols = row.len();
+            }
+            assert_eq!(row.len(), cols, "BitMatrix rows must be of equal length");
+            data.extend_from_slice(row.as_slice());
+            rows += 1;
         }
-        Self::new(rows)
+        Self::from_vec(data, rows, cols)
     }
 }
 
@@ -266,87 +317,80 @@ impl FromParallelIterator<BitVector> for BitMatrix {
     }
 }
 
-impl IntoIterator for BitMatrix {
-    type Item = BitVector;
-    type IntoIter = std::vec::IntoIter<BitVector>;
-
-    #[inline]
-    fn into_iter(self) -> Self::IntoIter {
-        self.rows.into_iter()
-    }
-}
-
 impl<'a> IntoIterator for &'a BitMatrix {
-    type Item = &'a BitVector;
-    type IntoIter = std::slice::Iter<'a, BitVector>;
+    type Item = &'a [Block];
+    type IntoIter = std::slice::ChunksExact<'a, Block>;
 
     #[inline]
     fn into_iter(self) -> Self::IntoIter {
-        self.rows.iter()
+        self.rows()
     }
 }
 
 impl Index<usize> for BitMatrix {
-    type Output = BitVector;
+    type Output = [Block];
 
     #[inline]
     fn index(&self, index: usize) -> &Self::Output {
-        &self.rows[index]
+        self.row(index)
     }
 }
 
-impl IntoParallelIterator for BitMatrix {
-    type Item = BitVector;
-    type Iter = rayon::vec::IntoIter<BitVector>;
-
+impl IndexMut<usize> for BitMatrix {
     #[inline]
-    fn into_par_iter(self) -> Self::Iter {
-        self.rows.into_par_iter()
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.row_mut(index)
     }
 }
 
-use std::ops::RangeTo;
-impl Index<RangeTo<usize>> for BitMatrix {
-    type Output = [BitVector];
-
+impl BitXorAssign<&Self> for BitMatrix {
     #[inline]
-    fn index(&self, index: RangeTo<usize>) -> &Self::Output {
-        &self.rows[index]
-    }
-}
-
-impl Index<Range<usize>> for BitMatrix {
-    type Output = [BitVector];
-
-    #[inline]
-    fn index(&self, index: Range<usize>) -> &Self::Output {
-        &self.rows[index]
-    }
-}
-
-impl Index<RangeInclusive<usize>> for BitMatrix {
-    type Output = [BitVector];
-
-    #[inline]
-    fn index(&self, index: RangeInclusive<usize>) -> &Self::Output {
-        &self.rows[index]
+    fn bitxor_assign(&mut self, rhs: &Self) {
+        assert_eq!(
+            self.dims(),
+            rhs.dims(),
+            "BitMatrix dimensions must be equal"
+        );
+        self.data
+            .par_iter_mut()
+            .zip(rhs.data.par_iter())
+            .for_each(|(l, r)| *l ^= *r);
     }
 }
 
-impl Index<RangeFrom<usize>> for BitMatrix {
-    type Output = [BitVector];
-
-    #[inline]
-    fn index(&self, index: RangeFrom<usize>) -> &Self::Output {
-        &self.rows[index]
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_bytes_unaligned() {
+        let bytes: Vec<u8> = (0..33).collect();
+        let v = BitVector::from_bytes(&bytes[1..]);
+        assert_eq!(v.len(), 256);
+        assert_eq!(v.as_bytes(), &bytes[1..]);
+    }
+
+    #[test]
+    fn test_matrix_rows() {
+        let rows: Vec<BitVector> = (0..16u8)
+            .map(|i| BitVector::from_bytes(&[i, !i, i ^ 0x55]))
+            .collect();
+        let m = BitMatrix::new(rows.clone());
+        assert_eq!(m.dims(), (16, 24));
+        for (i, row) in m.rows().enumerate() {
+            assert_eq!(row, rows[i].as_bytes());
+            assert_eq!(&m[i], row);
+        }
 
-impl Index<RangeFull> for BitMatrix {
-    type Output = [BitVector];
+        let t = m.transpose();
+        assert_eq!(t.dims(), (24, 16));
+        // Bit 9 of row 3 is bit 1 of `!3`.
+        assert_eq!((t[9][0] >> 3) & 1, (!3u8 >> 1) & 1);
+        assert_eq!(t.transpose(), m);
 
-    #[inline]
-    fn index(&self, _index: RangeFull) -> &Self::Output {
-        &self.rows[..]
+        let choices = [true, false, true, true, false, false, false, true];
+        let x = BitMatrix::monochrome(&choices, 4);
+        assert_eq!(x.dims(), (4, 8));
+        assert!(x.rows().all(|row| row == [0b1000_1101]));
     }
 }
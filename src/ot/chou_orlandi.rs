@@ -40,37 +40,27 @@ impl ObliviousSender for Sender {
         let n = msg.0.len();
         let mut rng = ChaCha20Rng::from_entropy();
         let secrets = (0..n).map(|_| Scalar::random(&mut rng)).collect::<Vec<_>>();
-        let publics = secrets
+        let points = secrets
             .par_iter()
             .map(|secret| &ED25519_BASEPOINT_TABLE * secret)
-            .map(|public| public.compress())
             .collect::<Vec<_>>();
-        let publics = Public(publics);
+        let publics = Public(points.par_iter().map(|p| p.compress()).collect());
 
         // round 1
-        let pbs = publics.0.iter().map(|&p| p.to_bytes());
-        for pb in pbs {
-            s.send(pb.as_ref())?;
-        }
+        s.send(&bincode::serialize(&publics)?)?;
 
         // round 2
-        let n = msg.0.len();
-        let pb = (0..n)
-            .map(|_| r.recv().unwrap())
-            .map(|p| CompressedEdwardsY::from_slice(&p))
-            .collect();
-        let pb = Public(pb);
+        let their_publics: Public = bincode::deserialize(&r.recv()?)?;
+        let their_publics = their_publics.points(n)?;
 
         // round 3
-        let their_public = &pb;
-        assert!(publics.0.len() == their_public.0.len());
         let payload: Vec<_> = msg
             .0
             .par_iter()
             .enumerate()
             .map(|(i, [m0, m1])| -> CiphertextPair {
-                let their_public = &their_public.0[i].decompress().unwrap();
-                let public = &publics.0[i].decompress().unwrap();
+                let their_public = &their_publics[i];
+                let public = &points[i];
                 let secret = &secrets[i];
 
                 // Compute the two shared keys.
@@ -117,19 +107,13 @@ impl ObliviousReceiver for Receiver {
         let choices = choices.to_vec();
 
         // round 1
-        let pb = (0..n)
-            .map(|_| r.recv().unwrap())
-            .map(|p| CompressedEdwardsY::from_slice(&p))
-            .collect();
-        let their_publics = Public(pb);
+        let their_publics: Public = bincode::deserialize(&r.recv()?)?;
+        let their_publics = their_publics.points(n)?;
 
-        debug_assert_eq!(choices.len(), their_publics.0.len());
         let (publics, keys): (Vec<CompressedEdwardsY>, Vec<_>) = their_publics
-            .0
             .par_iter()
             .enumerate()
-            .map(|(i, p)| -> (CompressedEdwardsY, [u8; 32]) {
-                let their_public = &p.decompress().unwrap();
+            .map(|(i, their_public)| -> (CompressedEdwardsY, [u8; 32]) {
                 let public = if choices[i] {
                     their_public + (&ED25519_BASEPOINT_TABLE * &secrets[i])
                 } else {
@@ -145,31 +129,30 @@ impl ObliviousReceiver for Receiver {
         let publics = Public(publics);
 
         // round 2
-        let pbs = publics.0.iter().map(|&p| p.to_bytes());
-        for pb in pbs {
-            s.send(pb.as_ref())?;
-        }
+        s.send(&bincode::serialize(&publics)?)?;
 
         // round 3
         let payload = r.recv()?;
         let payload: EncryptedPayload = bincode::deserialize(&payload)?;
+        if payload.0.len() != n {
+            return Err(Box::new(OTError::BadMessage("Chou-Orlandi ciphertexts")));
+        }
 
-        let msg = payload
-            .0
-            .par_iter()
-            .enumerate()
-            .map(|(i, [e0, e1])| -> Vec<u8> {
-                let e = if choices[i] { e1 } else { e0 };
-                let k = keys[i];
-                let mut stream = ChaCha20Rng::from_seed(k);
-                let size = e.len();
-                let cipher: Vec<u8> = (0..size).map(|_| stream.gen::<u8>()).collect();
-                xor_bytes(e, &cipher)
-            })
-            .collect();
+        let chosen = |i: usize| &payload.0[i][choices[i] as usize];
+        let mut msg = Payload::with_lengths((0..n).map(|i| chosen(i).len()));
+        msg.par_iter_mut().enumerate().for_each(|(i, m)| {
+            let mut stream = ChaCha20Rng::from_seed(keys[i]);
+            for (m, e) in m.iter_mut().zip(chosen(i)) {
+                *m = e ^ stream.gen::<u8>();
+            }
+        });
 
         Ok(msg)
     }
+
+    fn exchange_bits(&self, choices: &[u8], len: usize, ch: &Channel) -> Result<Payload> {
+        self.exchange(&unpack_bits(choices, len), ch)
+    }
 }
 
 // Old (state-machine) Impl.
@@ -177,6 +160,27 @@ impl ObliviousReceiver for Receiver {
 #[derive(Debug, Clone)]
 pub struct Public(Vec<CompressedEdwardsY>);
 
+impl Public {
+    /// Decompress and validate `n` points received from the other party.
+    ///
+    /// Rejects encodings that do not decompress or are not canonical, and points of small
+    /// order (including the identity), which would leak the secret scalar modulo the cofactor
+    /// or fix the shared key.
+    fn points(&self, n: usize) -> std::result::Result<Vec<EdwardsPoint>, OTError> {
+        if self.0.len() != n {
+            return Err(OTError::BadMessage("Chou-Orlandi public points"));
+        }
+        self.0
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| match p.decompress() {
+                Some(point) if point.compress() == *p && !point.is_small_order() => Ok(point),
+                _ => Err(OTError::BadPoint(i)),
+            })
+            .collect()
+    }
+}
+
 impl Serialize for Public {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -197,6 +201,9 @@ impl<'de> Visitor<'de> for PublicVisitor {
     where
         E: serde::de::Error,
     {
+        if !v.len().is_multiple_of(32) {
+            return Err(E::invalid_length(v.len(), &self));
+        }
         let mut vec = Vec::with_capacity(v.len() / 32);
         for i in 0..v.len() / 32 {
             let p = CompressedEdwardsY::from_slice(&v[i * 32..(i + 1) * 32]);
@@ -215,7 +222,7 @@ impl<'de> Visitor<'de> for PublicVisitor {
     type Value = Public;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("a very special map")
+        formatter.write_str("a sequence of compressed points")
     }
 }
 
@@ -267,7 +274,7 @@ mod tests {
             let receiver = Receiver;
             let choices = [true];
             let msg = receiver.exchange(&choices, &ch2).unwrap();
-            assert_eq!(msg[0], b"World");
+            assert_eq!(&msg[0], b"World");
         });
 
         h1.join().unwrap();
@@ -350,6 +357,71 @@ mod tests {
         assert_eq!(plaintext, "Hello!");
     }
 
+    #[test]
+    fn test_point_validation() {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let valid = (&ED25519_BASEPOINT_TABLE * &Scalar::random(&mut rng)).compress();
+        let identity = CompressedEdwardsY::from_slice(&{
+            let mut b = [0u8; 32];
+            b[0] = 1;
+            b
+        });
+        // The identity with the sign bit set, a non-canonical encoding.
+        let negative_zero = CompressedEdwardsY::from_slice(&{
+            let mut b = identity.to_bytes();
+            b[31] |= 0x80;
+            b
+        });
+        // y = 2 is not on the curve.
+        let off_curve = CompressedEdwardsY::from_slice(&{
+            let mut b = [0u8; 32];
+            b[0] = 2;
+            b
+        });
+
+        assert!(Public(vec![valid, valid]).points(2).is_ok());
+        assert!(matches!(
+            Public(vec![valid]).points(2),
+            Err(OTError::BadMessage(_))
+        ));
+        for (bad, i) in [(identity, 1), (negative_zero, 0), (off_curve, 1)] {
+            let mut points = vec![valid, valid];
+            points[i] = bad;
+            assert!(matches!(
+                Public(points).points(2),
+                Err(OTError::BadPoint(j)) if j == i
+            ));
+        }
+    }
+
+    #[test]
+    fn test_malicious_sender() {
+        let (ch1, ch2) = raw::local_channel_pair();
+
+        use std::thread;
+        let h1 = thread::spawn(move || {
+            // Send the identity as every public point.
+            let pb = TransactionProperties {
+                msg_size: 8,
+                protocol: "Chou-Orlandi".to_string(),
+            };
+            validate_properties(&pb, &ch1).unwrap();
+            let mut identity = [0u8; 32];
+            identity[0] = 1;
+            ch1.0
+                .send(&bincode::serialize(&identity.repeat(8)).unwrap())
+                .unwrap();
+            ch1
+        });
+
+        let error = Receiver.exchange(&[true; 8], &ch2).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<OTError>(),
+            Some(OTError::BadPoint(0))
+        ));
+        h1.join().unwrap();
+    }
+
     #[test]
     fn test_public_serialize() {
         let public = Public(
@@ -1,5 +1,8 @@
 use crate::common::*;
+use crate::util::pack_bits;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::ops::{Index, IndexMut, Range};
 
 /// Pair of plaintexts
 
@@ -24,6 +27,12 @@ pub(crate) fn validate_properties(pb: &TransactionProperties, (s, r): &Channel)
 pub enum OTError {
     BadProperties(TransactionProperties, TransactionProperties),
     PolychromaticInput(),
+    /// A message from the other party has the wrong size.
+    BadMessage(&'static str),
+    /// The curve point at this index is not a canonical encoding of a point of large order.
+    BadPoint(usize),
+    /// The choice is not below the number of messages.
+    ChoiceOutOfRange(usize, usize),
 }
 
 impl std::error::Error for OTError {}
@@ -32,6 +41,11 @@ impl std::fmt::Display for OTError {
         match self {
             OTError::BadProperties(pb1, pb2) => write!(f, "Bad properties: {:?} != {:?}", pb1, pb2),
             OTError::PolychromaticInput() => write!(f, "Polychromatic input, cheating receiver."),
+            Self::BadMessage(what) => write!(f, "Malformed {}", what),
+            Self::BadPoint(i) => write!(f, "Invalid curve point at index {}", i),
+            Self::ChoiceOutOfRange(choice, n) => {
+                write!(f, "Choice {} out of range for {} messages", choice, n)
+            }
         }
     }
 }
@@ -58,6 +72,23 @@ impl<'a> Message<'a> {
         Self(m)
     }
 
+    /// Pairs of `len` bytes each, stored back to back in `buffer` as `m0, m1, m0, m1, ...`.
+    ///
+    /// # Panics
+    /// If `buffer` is not a whole number of pairs.
+    pub fn from_flat(buffer: &'a [u8], len: usize) -> Self {
+        if len == 0 {
+            return Self(vec![]);
+        }
+        assert!(buffer.len().is_multiple_of(2 * len));
+        Self(
+            buffer
+                .chunks_exact(2 * len)
+                .map(|pair| <[_; 2]>::from(pair.split_at(len)))
+                .collect(),
+        )
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -71,8 +102,214 @@ pub trait ObliviousSender {
     fn exchange(&self, msg: &Message, channel: &Channel) -> Result<()>;
 }
 
-pub type Payload = Vec<Vec<u8>>;
+/// The messages received in a batch of OTs, stored back to back in a single buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    data: Vec<u8>,
+    /// Start of every row in `data`, followed by the end of the last one.
+    offsets: Vec<usize>,
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            offsets: vec![0],
+        }
+    }
+}
+
+impl Payload {
+    /// `n` rows of `len` zero bytes.
+    pub fn uniform(n: usize, len: usize) -> Self {
+        Self {
+            data: vec![0; n * len],
+            offsets: (0..=n).map(|i| i * len).collect(),
+        }
+    }
+
+    /// Rows of zero bytes with the given lengths.
+    pub fn with_lengths(lengths: impl IntoIterator<Item = usize>) -> Self {
+        let mut offsets = vec![0];
+        let mut end = 0;
+        for len in lengths {
+            end += len;
+            offsets.push(end);
+        }
+        Self {
+            data: vec![0; end],
+            offsets,
+        }
+    }
+
+    /// Number of rows.
+    pub const fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn row(&self, index: usize) -> &[u8] {
+        &self.data[self.offsets[index]..self.offsets[index + 1]]
+    }
+
+    pub fn row_mut(&mut self, index: usize) -> &mut [u8] {
+        &mut self.data[self.offsets[index]..self.offsets[index + 1]]
+    }
+
+    pub const fn iter(&self) -> Rows<'_> {
+        Rows {
+            payload: self,
+            range: 0..self.len(),
+        }
+    }
+
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = &[u8]> {
+        (0..self.len()).into_par_iter().map(|i| self.row(i))
+    }
+
+    /// The rows as disjoint mutable slices, to be filled in parallel.
+    pub fn par_iter_mut(&mut self) -> impl IndexedParallelIterator<Item = &mut [u8]> {
+        let mut rows = Vec::with_capacity(self.len());
+        let mut rest = self.data.as_mut_slice();
+        for w in self.offsets.windows(2) {
+            let (row, tail) = rest.split_at_mut(w[1] - w[0]);
+            rows.push(row);
+            rest = tail;
+        }
+        rows.into_par_iter()
+    }
+
+    /// All rows concatenated.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.iter().map(<[u8]>::to_vec).collect()
+    }
+}
+
+impl Index<usize> for Payload {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &[u8] {
+        self.row(index)
+    }
+}
+
+impl IndexMut<usize> for Payload {
+    fn index_mut(&mut self, index: usize) -> &mut [u8] {
+        self.row_mut(index)
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for Payload {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut payload = Self {
+            data: vec![],
+            offsets: vec![0],
+        };
+        for row in iter {
+            payload.data.extend_from_slice(row.as_ref());
+            payload.offsets.push(payload.data.len());
+        }
+        payload
+    }
+}
+
+impl<T: AsRef<[u8]>> From<Vec<T>> for Payload {
+    fn from(rows: Vec<T>) -> Self {
+        rows.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a Payload {
+    type Item = &'a [u8];
+    type IntoIter = Rows<'a>;
+
+    fn into_iter(self) -> Rows<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the rows of a [`Payload`].
+pub struct Rows<'a> {
+    payload: &'a Payload,
+    range: Range<usize>,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        self.range.next().map(|i| self.payload.row(i))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Rows<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|i| self.payload.row(i))
+    }
+}
+
+impl ExactSizeIterator for Rows<'_> {}
 
 pub trait ObliviousReceiver {
-    fn exchange(&self, choices: &[bool], channel: &Channel) -> Result<Payload>;
+    /// Receive message `x_j` of each pair for the first `len` choice bits, packed LSB-first so
+    /// `x_j` is bit `j % 8` of byte `j / 8`, the order of [`crate::util::u8_vec_to_bool_vec`].
+    ///
+    /// # Panics
+    /// If `choices` holds fewer than `len` bits.
+    fn exchange_bits(&self, choices: &[u8], len: usize, channel: &Channel) -> Result<Payload>;
+
+    /// Like [`ObliviousReceiver::exchange_bits`] with one `bool` per choice.
+    fn exchange(&self, choices: &[bool], channel: &Channel) -> Result<Payload> {
+        self.exchange_bits(&pack_bits(choices), choices.len(), channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_rows() {
+        let rows = vec![b"Hello".to_vec(), vec![], b"World!".to_vec()];
+        let payload = Payload::from(rows.clone());
+        assert_eq!(payload.len(), 3);
+        assert_eq!(payload.as_bytes(), b"HelloWorld!");
+        assert_eq!(&payload[1], b"");
+        assert_eq!(payload.iter().next_back(), Some(&b"World!"[..]));
+        assert_eq!(payload.to_vec(), rows);
+        assert!(Payload::default().is_empty());
+        assert_eq!(Payload::default(), Payload::from(Vec::<Vec<u8>>::new()));
+        assert_eq!(Payload::default(), Payload::uniform(0, 16));
+
+        let mut payload = Payload::with_lengths([2, 0, 3]);
+        payload
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(i, row)| row.fill(i as u8 + 1));
+        assert_eq!(payload.as_bytes(), [1, 1, 3, 3, 3]);
+        assert_eq!(
+            payload,
+            Payload::from(vec![vec![1, 1], vec![], vec![3, 3, 3]])
+        );
+    }
+
+    #[test]
+    fn test_message_from_flat() {
+        let buffer = b"aabbccdd";
+        let msg = Message::from_flat(buffer, 2);
+        assert_eq!(msg.len(), 2);
+        assert_eq!(msg.0[1], [&b"cc"[..], &b"dd"[..]]);
+        assert!(Message::from_flat(&[], 0).is_empty());
+    }
 }
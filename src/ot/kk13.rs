@@ -0,0 +1,326 @@
+// Batched 1-of-N OT extension from Kolesnikov and Kumaresan (https://eprint.iacr.org/2013/491).
+//
+// IKNP with the repetition code replaced by a linear code C of W bits: the receiver commits to
+// C(r_j) for each choice r_j, and the sender, holding s, learns q_j = t_j ^ (C(r_j) & s). The key
+// of message x in transfer j is then derived from q_j ^ (C(x) & s), which equals the receiver's
+// t_j exactly when x = r_j. C is the Walsh-Hadamard code of the choice, repeated to W bits, so
+// any two codewords differ in at least W/2 positions.
+use crate::common::*;
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::apricot;
+use crate::ot::chou_orlandi;
+use crate::ot::common::*;
+use crate::ot::one_of_many::fk;
+use crate::ot::oprf::transpose;
+use crate::util::*;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Width of the code in bits.
+const W: usize = 256;
+const W_BYTES: usize = W / 8;
+
+/// Largest supported domain, the Walsh-Hadamard code of a larger domain is wider than W.
+pub const MAX_DOMAIN: u32 = 8;
+
+/// Number of messages in each table, failing if `domain` exceeds [`MAX_DOMAIN`].
+fn table_size(domain: u32) -> Result<usize> {
+    if domain > MAX_DOMAIN {
+        return Err(Box::new(OTError::BadMessage("KK13 domain")));
+    }
+    Ok(1 << domain)
+}
+
+/// Walsh-Hadamard codeword of `x`, bit `p` is the parity of `x & p` for `p` in the domain.
+fn code(x: u32, domain: u32) -> [u8; W_BYTES] {
+    let mask = (1 << domain) - 1;
+    let mut c = [0u8; W_BYTES];
+    for b in 0..W {
+        let bit = ((x & b as u32 & mask).count_ones() & 1) as u8;
+        c[b / 8] |= bit << (b % 8);
+    }
+    c
+}
+
+pub struct Sender {
+    pub bootstrap: Box<dyn ObliviousReceiver>,
+}
+
+pub struct Receiver {
+    pub bootstrap: Box<dyn ObliviousSender>,
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Self {
+            bootstrap: Box::new(apricot::Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            }),
+        }
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self {
+            bootstrap: Box::new(apricot::Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            }),
+        }
+    }
+}
+
+impl Sender {
+    /// One 1-of-2^`domain` OT for each table in `messages`, failing before anything is sent if
+    /// `domain` exceeds [`MAX_DOMAIN`], a table does not have 2^`domain` messages, or the
+    /// messages differ in length.
+    pub fn exchange(&self, messages: &[Vec<Vec<u8>>], domain: u32, ch: &Channel) -> Result<()> {
+        instrument::begin("KK13 Sender", E_FUNC_COLOR);
+        let n = table_size(domain)?;
+        let length = messages.first().and_then(|m| m.first()).map_or(0, Vec::len);
+        if !messages
+            .iter()
+            .all(|m| m.len() == n && m.iter().all(|x| x.len() == length))
+        {
+            return Err(Box::new(OTError::BadMessage("KK13 messages")));
+        }
+
+        let pb = TransactionProperties {
+            msg_size: messages.len(),
+            protocol: format!("KK13 1-of-{}", n),
+        };
+        validate_properties(&pb, ch)?;
+        if messages.is_empty() {
+            instrument::end();
+            return Ok(());
+        }
+        let (s, r) = ch;
+        let rows = messages.len().div_ceil(8) * 8;
+        let row_bytes = rows / 8;
+
+        let secret: [u8; W_BYTES] = rand::thread_rng().gen();
+        let choices: Vec<bool> = (0..W)
+            .map(|i| (secret[i / 8] >> (i % 8)) & 1 == 1)
+            .collect();
+
+        instrument::begin("Base OT", E_PROT_COLOR);
+        let seeds = self.bootstrap.exchange_bits(&secret, W, ch)?;
+        instrument::end();
+
+        instrument::begin("Receive u", E_RECV_COLOR);
+        let u = r.recv()?;
+        instrument::end();
+        if u.len() != W * row_bytes {
+            return Err(Box::new(OTError::BadMessage("KK13 matrix")));
+        }
+
+        instrument::begin("Compute q", E_COMP_COLOR);
+        let columns: Vec<Vec<u8>> = (0..W)
+            .into_par_iter()
+            .map(|i| {
+                let mut column = vec![0u8; row_bytes];
+                fk(&seeds[i], i as u32, row_bytes, &mut column);
+                if choices[i] {
+                    xor_bytes_inplace(&mut column, &u[i * row_bytes..(i + 1) * row_bytes]);
+                }
+                column
+            })
+            .collect();
+        let q = transpose(columns);
+        instrument::end();
+
+        instrument::begin("Compute y", E_COMP_COLOR);
+        let masks: Vec<[u8; W_BYTES]> = (0..n as u32)
+            .map(|x| {
+                let mut c = code(x, domain);
+                c.iter_mut().zip(&secret).for_each(|(c, s)| *c &= s);
+                c
+            })
+            .collect();
+        let mut y = vec![0u8; messages.len() * n * length];
+        if length > 0 {
+            y.par_chunks_mut(n * length)
+                .zip(messages.par_iter())
+                .enumerate()
+                .for_each(|(j, (y, table))| {
+                    for ((y, m), mask) in y.chunks_mut(length).zip(table).zip(&masks) {
+                        let key = xor_bytes(q.row(j), mask);
+                        fk(&key, j as u32, length, y);
+                        xor_bytes_inplace(y, m);
+                    }
+                });
+        }
+        instrument::end();
+
+        instrument::begin("Send y", E_SEND_COLOR);
+        s.send(&(length as u64).to_be_bytes())?;
+        s.send(&y)?;
+        instrument::end();
+
+        instrument::end();
+        Ok(())
+    }
+}
+
+impl Receiver {
+    /// Returns message `choices[j]` of table `j` for every transfer.
+    /// Fails before anything is sent if `domain` exceeds [`MAX_DOMAIN`] or a choice is outside
+    /// the domain.
+    pub fn exchange(&self, choices: &[u32], domain: u32, ch: &Channel) -> Result<Payload> {
+        instrument::begin("KK13 Receiver", E_FUNC_COLOR);
+        let n = table_size(domain)?;
+        if let Some(&c) = choices.iter().find(|&&c| c as usize >= n) {
+            return Err(Box::new(OTError::ChoiceOutOfRange(c as usize, n)));
+        }
+
+        let pb = TransactionProperties {
+            msg_size: choices.len(),
+            protocol: format!("KK13 1-of-{}", n),
+        };
+        validate_properties(&pb, ch)?;
+        if choices.is_empty() {
+            instrument::end();
+            return Ok(Payload::default());
+        }
+        let (s, r) = ch;
+        let rows = choices.len().div_ceil(8) * 8;
+        let row_bytes = rows / 8;
+
+        let mut random = rand::thread_rng();
+        let seeds: Vec<[[u8; 32]; 2]> = (0..W).map(|_| random.gen()).collect();
+        instrument::begin("Base OT", E_PROT_COLOR);
+        self.bootstrap.exchange(&Message::from_zipped(&seeds), ch)?;
+        instrument::end();
+
+        instrument::begin("Compute u", E_COMP_COLOR);
+        // Unused rows have the all zero code.
+        let mut codes = vec![vec![0u8; W_BYTES]; rows];
+        codes
+            .par_iter_mut()
+            .zip(choices.par_iter())
+            .for_each(|(c, &x)| c.copy_from_slice(&code(x, domain)));
+        let codes = transpose(codes);
+
+        let (t, u): (Vec<Vec<u8>>, Vec<Vec<u8>>) = (0..W)
+            .into_par_iter()
+            .map(|i| {
+                let mut t = vec![0u8; row_bytes];
+                let mut u = vec![0u8; row_bytes];
+                fk(&seeds[i][0], i as u32, row_bytes, &mut t);
+                fk(&seeds[i][1], i as u32, row_bytes, &mut u);
+                xor_bytes_inplace(&mut u, &t);
+                xor_bytes_inplace(&mut u, codes.row(i));
+                (t, u)
+            })
+            .unzip();
+        instrument::end();
+
+        instrument::begin("Send u", E_SEND_COLOR);
+        s.send(&u.concat())?;
+        instrument::end();
+
+        instrument::begin("Receive y", E_RECV_COLOR);
+        let length = r.recv()?;
+        let length: [u8; 8] = length
+            .as_slice()
+            .try_into()
+            .map_err(|_| OTError::BadMessage("KK13 message length"))?;
+        let length = u64::from_be_bytes(length) as usize;
+        let y = r.recv()?;
+        instrument::end();
+        if Some(y.len()) != length.checked_mul(n * choices.len()) {
+            return Err(Box::new(OTError::BadMessage("KK13 ciphertexts")));
+        }
+
+        instrument::begin("Reconstruct values", E_COMP_COLOR);
+        let t = transpose(t);
+        let mut messages = Payload::uniform(choices.len(), length);
+        messages
+            .par_iter_mut()
+            .zip(choices.par_iter())
+            .enumerate()
+            .for_each(|(j, (m, &x))| {
+                let offset = (j * n + x as usize) * length;
+                fk(t.row(j), j as u32, length, m);
+                xor_bytes_inplace(m, &y[offset..offset + length]);
+            });
+        instrument::end();
+
+        instrument::end();
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn run(domain: u32, choices: Vec<u32>) {
+        let n = 1u32 << domain;
+        let tables: Vec<Vec<Vec<u8>>> = (0..choices.len() as u32)
+            .map(|j| (0..n).map(|x| (j * n + x).to_be_bytes().to_vec()).collect())
+            .collect();
+        let (ch1, ch2) = raw::local_channel_pair();
+        let sent = tables.clone();
+        let h1 = thread::spawn(move || Sender::default().exchange(&sent, domain, &ch1).unwrap());
+        let c = choices.clone();
+        let h2 = thread::spawn(move || Receiver::default().exchange(&c, domain, &ch2).unwrap());
+        h1.join().unwrap();
+        let received = h2.join().unwrap();
+
+        assert_eq!(received.len(), choices.len());
+        for (j, &x) in choices.iter().enumerate() {
+            assert_eq!(received[j], tables[j][x as usize]);
+        }
+    }
+
+    #[test]
+    fn test_kk13() {
+        run(4, (0..100).map(|j| j % 16).collect());
+        run(MAX_DOMAIN, (0..20).map(|j| (j * 37) % 256).collect());
+        run(0, vec![0; 3]);
+        run(3, vec![]);
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        // Every check happens before the channel is used.
+        let (ch, _) = raw::local_channel_pair();
+        let bad_message = |e: Error| matches!(e.downcast_ref(), Some(OTError::BadMessage(_)));
+
+        let err = Receiver::default().exchange(&[0], MAX_DOMAIN + 1, &ch);
+        assert!(bad_message(err.unwrap_err()));
+        let err = Receiver::default().exchange(&[1, 4], 2, &ch).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(OTError::ChoiceOutOfRange(4, 4))
+        ));
+
+        let table = vec![vec![0u8; 4]; 4];
+        let err = Sender::default().exchange(std::slice::from_ref(&table), MAX_DOMAIN + 1, &ch);
+        assert!(bad_message(err.unwrap_err()));
+        let err = Sender::default().exchange(&[vec![], table.clone()], 2, &ch);
+        assert!(bad_message(err.unwrap_err()));
+        let err = Sender::default().exchange(std::slice::from_ref(&table), 3, &ch);
+        assert!(bad_message(err.unwrap_err()));
+        let mut short = table.clone();
+        short[2].pop();
+        let err = Sender::default().exchange(&[table, short], 2, &ch);
+        assert!(bad_message(err.unwrap_err()));
+    }
+
+    #[test]
+    fn test_code_distance() {
+        for domain in [1, 4, MAX_DOMAIN] {
+            let codes: Vec<_> = (0..1 << domain).map(|x| code(x, domain)).collect();
+            for (a, ca) in codes.iter().enumerate() {
+                for cb in &codes[a + 1..] {
+                    let distance: u32 = xor_bytes(ca, cb).iter().map(|b| b.count_ones()).sum();
+                    assert!(distance as usize >= W / 2);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+// Endemic OT from Masny and Rindal (https://eprint.iacr.org/2019/706) over Ristretto.
+//
+// The sender publishes A = aG. For choice c the receiver samples b, a random point r_{1-c} and
+// sets r_c = bG - H(r_{1-c}), so that r_c + H(r_{1-c}) = bG while r_{1-c} + H(r_c) is a point
+// it does not know the discrete log of. The sender derives k_i from a(r_i + H(r_{1-i})) and the
+// receiver k_c from bA. The two first messages are independent, so both are sent at once.
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use sha2::{Digest, Sha512};
+
+use crate::common::*;
+use crate::ot::common::*;
+use crate::util::*;
+
+pub struct Sender;
+pub struct Receiver;
+
+/// Hash onto the group, separated by the OT index.
+fn hash_to_point(i: usize, p: &CompressedRistretto) -> RistrettoPoint {
+    let mut hasher = Sha512::new();
+    hasher.update(b"Masny-Rindal");
+    hasher.update((i as u64).to_be_bytes());
+    hasher.update(p.as_bytes());
+    RistrettoPoint::from_uniform_bytes(&hasher.finalize().into())
+}
+
+fn key(i: usize, point: &RistrettoPoint) -> [u8; 32] {
+    hash!((i as u64).to_be_bytes(), point.compress().as_bytes())
+}
+
+fn stream(key: [u8; 32], m: &[u8]) -> Vec<u8> {
+    let mut out = vec![0u8; m.len()];
+    stream_into(key, m, &mut out);
+    out
+}
+
+/// Like [`stream`], writing into `out`.
+fn stream_into(key: [u8; 32], m: &[u8], out: &mut [u8]) {
+    let mut stream = ChaCha20Rng::from_seed(key);
+    for (o, m) in out.iter_mut().zip(m) {
+        *o = m ^ stream.gen::<u8>();
+    }
+}
+
+/// Decompress `n` points, rejecting non-canonical encodings and the identity.
+fn points(bytes: &[u8], n: usize) -> std::result::Result<Vec<CompressedRistretto>, OTError> {
+    if bytes.len() != n * 32 {
+        return Err(OTError::BadMessage("Masny-Rindal points"));
+    }
+    bytes
+        .par_chunks_exact(32)
+        .enumerate()
+        .map(|(i, p)| {
+            let p = CompressedRistretto::from_slice(p);
+            match p.decompress() {
+                Some(point) if point != RistrettoPoint::identity() => Ok(p),
+                _ => Err(OTError::BadPoint(i)),
+            }
+        })
+        .collect()
+}
+
+impl ObliviousSender for Sender {
+    fn exchange(&self, msg: &Message, ch: &Channel) -> Result<()> {
+        let pb = TransactionProperties {
+            msg_size: msg.len(),
+            protocol: "Masny-Rindal".to_string(),
+        };
+        validate_properties(&pb, ch)?;
+        let (s, r) = ch;
+        let n = msg.len();
+
+        let a = Scalar::random(&mut ChaCha20Rng::from_entropy());
+        let public = &RISTRETTO_BASEPOINT_TABLE * &a;
+
+        // round 1
+        s.send(public.compress().as_bytes())?;
+
+        // round 2
+        let rs = points(&r.recv()?, 2 * n)?;
+
+        // round 3
+        let payload: Vec<[Vec<u8>; 2]> = msg
+            .0
+            .par_iter()
+            .enumerate()
+            .map(|(i, [m0, m1])| {
+                let (r0, r1) = (&rs[2 * i], &rs[2 * i + 1]);
+                // Both were validated above.
+                let b0 = r0.decompress().unwrap() + hash_to_point(i, r1);
+                let b1 = r1.decompress().unwrap() + hash_to_point(i, r0);
+                [stream(key(i, &(a * b0)), m0), stream(key(i, &(a * b1)), m1)]
+            })
+            .collect();
+        s.send(&bincode::serialize(&payload)?)?;
+        Ok(())
+    }
+}
+
+impl ObliviousReceiver for Receiver {
+    fn exchange(&self, choices: &[bool], ch: &Channel) -> Result<Payload> {
+        let pb = TransactionProperties {
+            msg_size: choices.len(),
+            protocol: "Masny-Rindal".to_string(),
+        };
+        validate_properties(&pb, ch)?;
+        let (s, r) = ch;
+        let n = choices.len();
+
+        let mut rng = ChaCha20Rng::from_entropy();
+        let secrets: Vec<Scalar> = (0..n).map(|_| Scalar::random(&mut rng)).collect();
+        let others: Vec<RistrettoPoint> =
+            (0..n).map(|_| RistrettoPoint::random(&mut rng)).collect();
+
+        // round 1
+        let rs: Vec<u8> = choices
+            .par_iter()
+            .zip(&secrets)
+            .zip(&others)
+            .enumerate()
+            .flat_map_iter(|(i, ((&c, b), other))| {
+                let other = other.compress();
+                let chosen = (&RISTRETTO_BASEPOINT_TABLE * b - hash_to_point(i, &other)).compress();
+                let (r0, r1) = if c { (other, chosen) } else { (chosen, other) };
+                r0.to_bytes().into_iter().chain(r1.to_bytes())
+            })
+            .collect();
+        s.send(&rs)?;
+
+        // round 2
+        let public = points(&r.recv()?, 1)?[0].decompress().unwrap();
+
+        // round 3
+        let payload: Vec<[Vec<u8>; 2]> = bincode::deserialize(&r.recv()?)?;
+        if payload.len() != n {
+            return Err(Box::new(OTError::BadMessage("Masny-Rindal ciphertexts")));
+        }
+        let mut msg = Payload::with_lengths(
+            payload
+                .iter()
+                .zip(choices)
+                .map(|(e, &c)| e[c as usize].len()),
+        );
+        msg.par_iter_mut()
+            .zip(payload.par_iter().zip(choices.par_iter().zip(&secrets)))
+            .enumerate()
+            .for_each(|(i, (m, (e, (&c, b))))| {
+                stream_into(key(i, &(b * public)), &e[c as usize], m);
+            });
+        Ok(msg)
+    }
+
+    fn exchange_bits(&self, choices: &[u8], len: usize, ch: &Channel) -> Result<Payload> {
+        self.exchange(&unpack_bits(choices, len), ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ot::apricot;
+    use std::thread;
+
+    #[test]
+    fn test_masny_rindal() {
+        let m0: Vec<[u8; 8]> = (0..16u64).map(|i| i.to_be_bytes()).collect();
+        let m1: Vec<[u8; 8]> = (0..16u64).map(|i| (i + 100).to_be_bytes()).collect();
+        let choices: Vec<bool> = (0..16).map(|i| i % 3 == 0).collect();
+
+        let (ch1, ch2) = raw::local_channel_pair();
+        let (sent0, sent1) = (m0.clone(), m1.clone());
+        let h1 = thread::spawn(move || {
+            let msg = Message::from_unzipped(&sent0, &sent1);
+            Sender.exchange(&msg, &ch1).unwrap();
+        });
+        let c = choices.clone();
+        let h2 = thread::spawn(move || Receiver.exchange(&c, &ch2).unwrap());
+        h1.join().unwrap();
+        let received = h2.join().unwrap();
+
+        for (i, &c) in choices.iter().enumerate() {
+            let expected = if c { m1[i] } else { m0[i] };
+            assert_eq!(received[i], expected);
+        }
+    }
+
+    #[test]
+    fn test_apricot_bootstrap() {
+        let m0: Vec<[u8; 32]> = (0..64u8).map(|i| [i; 32]).collect();
+        let m1: Vec<[u8; 32]> = (0..64u8).map(|i| [!i; 32]).collect();
+        let choices: Vec<bool> = (0..64).map(|i| i % 2 == 0).collect();
+
+        let (ch1, ch2) = raw::local_channel_pair();
+        let (sent0, sent1) = (m0.clone(), m1.clone());
+        let h1 = thread::spawn(move || {
+            let sender = apricot::Sender {
+                bootstrap: Box::new(Receiver),
+            };
+            let msg = Message::from_unzipped(&sent0, &sent1);
+            sender.exchange(&msg, &ch1).unwrap();
+        });
+        let c = choices.clone();
+        let h2 = thread::spawn(move || {
+            let receiver = apricot::Receiver {
+                bootstrap: Box::new(Sender),
+            };
+            receiver.exchange(&c, &ch2).unwrap()
+        });
+        h1.join().unwrap();
+        let received = h2.join().unwrap();
+
+        for (i, &c) in choices.iter().enumerate() {
+            let expected = if c { m1[i] } else { m0[i] };
+            assert_eq!(received[i], expected);
+        }
+    }
+
+    #[test]
+    fn test_point_validation() {
+        let point = RistrettoPoint::random(&mut ChaCha20Rng::from_entropy()).compress();
+        let identity = RistrettoPoint::identity().compress();
+        assert!(points(point.as_bytes(), 1).is_ok());
+        assert!(matches!(
+            points(point.as_bytes(), 2),
+            Err(OTError::BadMessage(_))
+        ));
+        let bytes = [point.to_bytes(), identity.to_bytes()].concat();
+        assert!(matches!(points(&bytes, 2), Err(OTError::BadPoint(1))));
+        let bytes = [[0xffu8; 32], point.to_bytes()].concat();
+        assert!(matches!(points(&bytes, 2), Err(OTError::BadPoint(0))));
+    }
+}
@@ -1,8 +1,15 @@
 pub mod apricot_classic;
 pub mod apricot;
+pub mod beaver;
 pub mod bitmatrix;
 pub mod chou_orlandi;
 mod coinflip;
 pub mod common;
+pub mod kk13;
+pub mod masny_rindal;
 pub mod one_of_many;
+pub mod oprf;
 pub mod polynomial;
+#[cfg(feature = "silent")]
+pub mod silent;
+pub mod transpose;
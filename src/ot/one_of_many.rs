@@ -6,6 +6,7 @@ use crate::util::*;
 use rand::RngCore;
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 
 #[inline]
@@ -15,7 +16,7 @@ fn array<const N: usize>(vector: &Vec<u8>) -> [u8; N] {
 
 // 1-to-n extensions for OT :D
 // https://dl.acm.org/doi/pdf/10.1145/301250.301312
-fn fk(key: &[u8], choice: u32, length: usize, buffer: &mut [u8]) {
+pub(crate) fn fk(key: &[u8], choice: u32, length: usize, buffer: &mut [u8]) {
     let mut hasher = Sha256::new();
     hasher.update(choice.to_be_bytes());
     hasher.update(key);
@@ -25,99 +26,92 @@ fn fk(key: &[u8], choice: u32, length: usize, buffer: &mut [u8]) {
     prg.fill_bytes(buffer);
 }
 
+const KEY_BYTES: usize = SECURITY_PARAM / 8;
+
+/// Random pairs of keys, one pair for each bit of the domain, stored as `k0, k1, k0, k1, ...`.
+fn generate_keys(l: usize) -> Vec<u8> {
+    let mut keys = vec![0u8; 2 * l * KEY_BYTES];
+    random_bytes(&mut keys);
+    keys
+}
+
+/// Key `bit` of pair `j`.
+fn key(keys: &[u8], j: usize, bit: usize) -> &[u8] {
+    let start = (2 * j + bit) * KEY_BYTES;
+    &keys[start..start + KEY_BYTES]
+}
+
+/// Mask row `i` of `y` with the keys selected by the bits of `i`.
+fn mask(keys: &[u8], i: usize, row: &mut [u8]) {
+    let mut hash = vec![0u8; row.len()];
+    for j in 0..keys.len() / (2 * KEY_BYTES) {
+        fk(key(keys, j, (i >> j) & 1), i as u32, row.len(), &mut hash);
+        xor_bytes_inplace(row, &hash);
+    }
+}
+
+/// Remove the masks of row `choice` of `y`, given the keys selected by its bits.
+fn unmask(keys: &Payload, choice: u32, row: &mut [u8]) {
+    let mut hash = vec![0u8; row.len()];
+    for key in keys {
+        fk(key, choice, row.len(), &mut hash);
+        xor_bytes_inplace(row, &hash);
+    }
+}
+
+/// Target size of each message of `y` sent by [`ManyOTSender::exchange_streaming`].
+pub const STREAM_CHUNK_BYTES: usize = 1 << 20;
+
 pub struct ManyOTSender {
     pub interal_sender: Box<dyn ObliviousSender>,
 }
 
+/// Number of messages and their common length.
+fn validate_messages(messages: &[Vec<u8>]) -> Result<(u32, usize), Error> {
+    let byte_length = messages.first().map_or(0, Vec::len);
+    if messages.is_empty() || messages.iter().any(|m| m.len() != byte_length) {
+        return Err(Box::new(OTError::BadMessage("1-to-n OT messages")));
+    }
+    let n = u32::try_from(messages.len()).map_err(|_| OTError::BadMessage("1-to-n OT messages"))?;
+    Ok((n, byte_length))
+}
+
+/// Agree on the number of messages, with the domain rounded up to a power of two.
+fn handshake(n: u32, ch: &Channel) -> Result<u32, Error> {
+    let pb = TransactionProperties {
+        msg_size: n as usize,
+        protocol: "1-to-n OT".to_string(),
+    };
+    validate_properties(&pb, ch)?;
+    Ok(log2(n))
+}
+
 impl ManyOTSender {
-    pub fn exchange(&self, messages: &[Vec<u8>], domain: u32, ch: &Channel) -> Result<(), Error> {
+    /// Send one of `messages`, which must all have the same length, possibly zero. Their number
+    /// need not be a power of two, the domain is padded with random dummies.
+    pub fn exchange(&self, messages: &[Vec<u8>], ch: &Channel) -> Result<(), Error> {
         instrument::begin("1-to-n OT Sender", E_FUNC_COLOR);
-        let byte_length = messages[0].len();
+        let (n, byte_length) = validate_messages(messages)?;
+        let domain = handshake(n, ch)?;
 
         // 1. B: Prepare random keys
-        let l = domain as usize;
-
         instrument::begin("Generate keys", E_COMP_COLOR);
-        let mut keys: Vec<[Vec<u8>; 2]> = Vec::with_capacity(l);
-        for _i in 0..l {
-            let mut left = vec![0u8; SECURITY_PARAM / 8];
-            let mut right = vec![0u8; SECURITY_PARAM / 8];
-
-            random_bytes(&mut left);
-            random_bytes(&mut right);
-
-            keys.push([left, right]);
-        }
+        let keys = generate_keys(domain as usize);
         instrument::end();
 
         instrument::begin("Compute y", E_COMP_COLOR);
-        let domain_max = 1 << domain; // 2^domain
-        let mut y = vec![0u8; domain_max * byte_length];
-
-        // In this case is does not make sense to multi-thread when the number of rows in y is
-        // relatively small, if this is the case we do it the "single-threaded" way instead.
-        if domain <= 2 {
-            let mut hash = vec![0u8; byte_length];
-            for i in 0..domain_max {
-                let y_value = unsafe { vector_row_mut(&mut y, i, byte_length) };
-                xor_bytes_inplace(y_value, messages[i].as_slice());
-
-                for j in 0..domain {
-                    let bit = (i >> j) & 1;
-                    fk(
-                        &keys[j as usize][bit as usize],
-                        i as u32,
-                        byte_length,
-                        &mut hash,
-                    );
-                    xor_bytes_inplace(y_value, &hash);
-                }
-            }
-        } else {
-            let desired_thread_count = num_cpus::get();
-            let actual_thread_count = if domain_max <= desired_thread_count {
-                domain_max as usize
-            } else {
-                desired_thread_count
-            };
-            debug_assert_eq!(0, domain_max % actual_thread_count);
-
-            let rows_in_chunk = domain_max / actual_thread_count;
-            let bytes_in_chunk = rows_in_chunk * byte_length;
-
-            // NOTE: This is slightly slower for very small domain, but the difference shouldn't matter
-            rayon::scope(|s| {
-                let keys = &keys;
-
-                let y_chunks = y.chunks_mut(bytes_in_chunk);
-                for (chunk_idx, chunk) in y_chunks.enumerate() {
-                    let handle = s.spawn(move |_| {
-                        instrument::begin("Compute y - worker", E_COMP_COLOR);
-
-                        let mut hash = vec![0u8; byte_length];
-
-                        for i in 0..rows_in_chunk {
-                            let y_value = unsafe { vector_row_mut(chunk, i, byte_length) };
-                            let domain_index = rows_in_chunk * chunk_idx + i;
-                            xor_bytes_inplace(y_value, messages[domain_index].as_slice());
-
-                            for j in 0..domain {
-                                let bit = (domain_index >> j) & 1;
-                                fk(
-                                    &keys[j as usize][bit as usize],
-                                    domain_index as u32,
-                                    byte_length,
-                                    &mut hash,
-                                );
-                                xor_bytes_inplace(y_value, &hash);
-                            }
-                        }
-
-                        instrument::end();
-                        ()
-                    });
-                }
-            });
+        let mut y = vec![0u8; (1 << domain) * byte_length];
+        if byte_length > 0 {
+            y.par_chunks_mut(byte_length)
+                .enumerate()
+                .for_each(|(i, y_value)| match messages.get(i) {
+                    Some(message) => {
+                        y_value.copy_from_slice(message);
+                        mask(&keys, i, y_value);
+                    }
+                    // Padding, indistinguishable from a masked message.
+                    None => random_bytes(y_value),
+                });
         }
         instrument::end();
 
@@ -127,22 +121,72 @@ impl ManyOTSender {
         instrument::end();
 
         // 2. Initiate 1-out-of-2 OTs by sending challenges
-        instrument::begin("Build boostrap messages", E_COMP_COLOR);
-        let mut messages = Vec::with_capacity(l);
-        for i in 0..l {
-            let m0 = keys[i as usize][0].to_vec();
-            let m1 = keys[i as usize][1].to_vec();
-            messages.push([m0, m1]);
-        }
-        let message = Message::from_zipped(messages.as_slice());
+        self.send_keys(&keys, ch)?;
+
         instrument::end();
 
-        instrument::begin("Boostrap", E_PROT_COLOR);
-        self.interal_sender.exchange(&message, ch)?;
+        Ok(())
+    }
+
+    /// Like [`ManyOTSender::exchange`], but computes and sends `y` in chunks of about
+    /// [`STREAM_CHUNK_BYTES`], so only one chunk is held in memory at a time. Must be matched by
+    /// [`ManyOTReceiver::exchange_streaming`].
+    pub fn exchange_streaming(&self, messages: &[Vec<u8>], ch: &Channel) -> Result<(), Error> {
+        instrument::begin("Streaming 1-to-n OT Sender", E_FUNC_COLOR);
+        let (n, byte_length) = validate_messages(messages)?;
+        let domain = handshake(n, ch)?;
+        let domain_max = 1usize << domain;
+
+        instrument::begin("Generate keys", E_COMP_COLOR);
+        let keys = generate_keys(domain as usize);
         instrument::end();
 
+        let (s, _r) = ch;
+        let rows = (STREAM_CHUNK_BYTES / byte_length.max(1)).clamp(1, domain_max);
+        s.send(&bincode::serialize(&(byte_length as u64, rows as u64))?)?;
+
+        let mut chunk = vec![0u8; rows * byte_length];
+        for start in (0..domain_max).step_by(rows) {
+            instrument::begin("Compute y chunk", E_COMP_COLOR);
+            let chunk = &mut chunk[..rows.min(domain_max - start) * byte_length];
+            if byte_length > 0 {
+                chunk
+                    .par_chunks_mut(byte_length)
+                    .enumerate()
+                    .for_each(|(k, y_value)| {
+                        let i = start + k;
+                        match messages.get(i) {
+                            Some(message) => {
+                                y_value.copy_from_slice(message);
+                                mask(&keys, i, y_value);
+                            }
+                            // Padding, indistinguishable from a masked message.
+                            None => random_bytes(y_value),
+                        }
+                    });
+            }
+            instrument::end();
+
+            instrument::begin("Send y chunk", E_SEND_COLOR);
+            s.send(chunk)?;
+            instrument::end();
+        }
+
+        self.send_keys(&keys, ch)?;
+
+        instrument::end();
+        Ok(())
+    }
+
+    /// Transfer one of each pair of keys with the internal 1-out-of-2 OT.
+    fn send_keys(&self, keys: &[u8], ch: &Channel) -> Result<(), Error> {
+        instrument::begin("Build boostrap messages", E_COMP_COLOR);
+        let message = Message::from_flat(keys, KEY_BYTES);
         instrument::end();
 
+        instrument::begin("Boostrap", E_PROT_COLOR);
+        self.interal_sender.exchange(&message, ch)?;
+        instrument::end();
         Ok(())
     }
 }
@@ -152,57 +196,93 @@ pub struct ManyOTReceiver {
 }
 
 impl ManyOTReceiver {
-    pub fn exchange(&self, choice: u32, domain: u32, ch: &Channel) -> Result<Vec<u8>, Error> {
+    /// Receive message `choice` out of `n`, failing before anything is sent if `choice >= n`.
+    pub fn exchange(&self, choice: u32, n: u32, ch: &Channel) -> Result<Vec<u8>, Error> {
         instrument::begin("1-to-n OT Receiver", E_FUNC_COLOR);
-        let l = domain as usize;
-
-        // construct choices
-        instrument::begin("Build choices", E_COMP_COLOR);
-        let mut choices: Vec<bool> = Vec::with_capacity(l);
-        for i in 0..l {
-            let bit = (choice & (1 << i)) >> i;
-            choices.push(bit == 1);
+        if choice >= n {
+            return Err(Box::new(OTError::ChoiceOutOfRange(choice as usize, n as usize)));
         }
-        instrument::end();
+        let domain = handshake(n, ch)?;
 
         let (_s, r) = ch;
         instrument::begin("Receive y", E_RECV_COLOR);
         let mut y: Vec<u8> = r.recv()?;
         instrument::end();
 
-        instrument::begin("Bootstrap", E_PROT_COLOR);
-        let messages = self.internal_receiver.exchange(&choices, ch)?;
+        let keys = self.receive_keys(choice, domain, ch)?;
+
+        // reconstruct x from choice and keys
+        instrument::begin("Reconstruct value", E_COMP_COLOR);
+        if !y.len().is_multiple_of(1 << domain) {
+            return Err(Box::new(OTError::BadMessage("1-to-n OT y")));
+        }
+        let byte_length = y.len() / (1 << domain);
+        let x = unsafe { vector_row_mut(&mut y, choice as usize, byte_length) };
+        unmask(&keys, choice, x);
         instrument::end();
 
-        // convert payload to keys
-        instrument::begin("Convert payload to keys", E_COMP_COLOR);
-        let mut keys: Vec<Vec<u8>> = Vec::with_capacity(l);
-        for i in 0..l {
-            let message = &messages[i];
-            debug_assert_eq!(message.len() % LENGTH, 0);
+        instrument::end();
+        Ok(x.to_vec())
+    }
 
-            let mut key = Vec::with_capacity(message.len());
-            for j in 0..message.len() {
-                key.push(message[j]);
-            }
+    /// Like [`ManyOTReceiver::exchange`] against [`ManyOTSender::exchange_streaming`], keeping
+    /// only the chosen row of `y` while receiving it.
+    pub fn exchange_streaming(&self, choice: u32, n: u32, ch: &Channel) -> Result<Vec<u8>, Error> {
+        instrument::begin("Streaming 1-to-n OT Receiver", E_FUNC_COLOR);
+        if choice >= n {
+            return Err(Box::new(OTError::ChoiceOutOfRange(choice as usize, n as usize)));
+        }
+        let domain = handshake(n, ch)?;
+        let domain_max = 1usize << domain;
 
-            keys.push(key);
+        let (_s, r) = ch;
+        let (byte_length, rows): (u64, u64) = bincode::deserialize(&r.recv()?)?;
+        let (byte_length, rows) = (byte_length as usize, rows as usize);
+        if rows == 0 {
+            return Err(Box::new(OTError::BadMessage("1-to-n OT chunk size")));
+        }
+
+        instrument::begin("Receive y", E_RECV_COLOR);
+        let mut x = Vec::new();
+        for start in (0..domain_max).step_by(rows) {
+            let chunk = r.recv()?;
+            let count = rows.min(domain_max - start);
+            if Some(chunk.len()) != count.checked_mul(byte_length) {
+                return Err(Box::new(OTError::BadMessage("1-to-n OT y")));
+            }
+            let k = choice as usize;
+            if (start..start + count).contains(&k) {
+                let offset = (k - start) * byte_length;
+                x = chunk[offset..offset + byte_length].to_vec();
+            }
         }
         instrument::end();
 
-        // reconstruct x from choice and keys
+        let keys = self.receive_keys(choice, domain, ch)?;
+
         instrument::begin("Reconstruct value", E_COMP_COLOR);
-        let byte_length = y.len() / (1 << domain);
-        let x = unsafe { vector_row_mut(&mut y, choice as usize, byte_length) };
-        let mut hash = vec![0u8; byte_length];
-        for i in 0..domain {
-            fk(&keys[i as usize], choice, byte_length, &mut hash);
-            xor_bytes_inplace(x, &hash);
-        }
+        unmask(&keys, choice, &mut x);
         instrument::end();
 
         instrument::end();
-        Ok(x.to_vec())
+        Ok(x)
+    }
+
+    /// Receive the keys selected by the bits of `choice` with the internal 1-out-of-2 OT.
+    fn receive_keys(&self, choice: u32, domain: u32, ch: &Channel) -> Result<Payload, Error> {
+        let l = domain as usize;
+
+        // bit i of the choice selects key i
+        instrument::begin("Bootstrap", E_PROT_COLOR);
+        let keys = self
+            .internal_receiver
+            .exchange_bits(&choice.to_le_bytes(), l, ch)?;
+        instrument::end();
+        if keys.len() != l || keys.iter().any(|key| key.len() != KEY_BYTES) {
+            return Err(Box::new(OTError::BadMessage("1-to-n OT keys")));
+        }
+
+        Ok(keys)
     }
 }
 
@@ -210,12 +290,11 @@ impl ManyOTReceiver {
 mod tests {
     use super::*;
     use crate::common::*;
-    use crate::util::{log2, LENGTH};
+    use crate::util::LENGTH;
 
     #[test]
     fn test_channel_1_to_n() {
         let n = 8u8;
-        let domain = log2(n) as u32;
         let mut messages = Vec::with_capacity(n as usize);
         for i in 0u8..n {
             messages.push(vec![i; LENGTH]);
@@ -235,7 +314,7 @@ mod tests {
                 let sender = ManyOTSender {
                     interal_sender: Box::new(crate::ot::chou_orlandi::Sender),
                 };
-                sender.exchange(&messages, domain, &ch1).unwrap();
+                sender.exchange(&messages, &ch1).unwrap();
             });
 
         let h2 = thread::Builder::new()
@@ -244,7 +323,7 @@ mod tests {
                 let receiver = ManyOTReceiver {
                     internal_receiver: Box::new(crate::ot::chou_orlandi::Receiver),
                 };
-                receiver.exchange(choice, domain, &ch2).unwrap()
+                receiver.exchange(choice, n as u32, &ch2).unwrap()
             });
 
         h1.unwrap().join().unwrap();
@@ -254,4 +333,82 @@ mod tests {
             assert_eq!(orig_msg[choice as usize][i], output[i]);
         }
     }
+
+    fn transfer(messages: Vec<Vec<u8>>, choice: u32, n: u32) -> Result<Vec<u8>> {
+        use std::thread;
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let sender = ManyOTSender {
+                interal_sender: Box::new(crate::ot::chou_orlandi::Sender),
+            };
+            sender.exchange(&messages, &ch1).is_ok()
+        });
+        let receiver = ManyOTReceiver {
+            internal_receiver: Box::new(crate::ot::chou_orlandi::Receiver),
+        };
+        let output = receiver.exchange(choice, n, &ch2);
+        drop(ch2);
+        h1.join().unwrap();
+        output
+    }
+
+    #[test]
+    fn test_any_n() {
+        for n in [1u32, 3, 5, 6, 7, 9] {
+            let messages: Vec<Vec<u8>> = (0..n).map(|i| vec![i as u8; 20]).collect();
+            for choice in [0, n / 2, n - 1] {
+                let output = transfer(messages.clone(), choice, n).unwrap();
+                assert_eq!(output, messages[choice as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_messages() {
+        let output = transfer(vec![Vec::new(); 5], 3, 5).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_choice_out_of_range() {
+        let messages = vec![vec![0u8; 4]; 5];
+        let err = transfer(messages.clone(), 5, 5).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<OTError>(),
+            Some(OTError::ChoiceOutOfRange(5, 5))
+        ));
+
+        // The handshake rejects a different number of messages.
+        let err = transfer(messages, 5, 6).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<OTError>(),
+            Some(OTError::BadProperties(..))
+        ));
+    }
+
+    #[test]
+    fn test_streaming() {
+        use std::thread;
+        let n = 1000u32;
+        // A few rows per chunk, and a last chunk that is cut short.
+        let messages: Vec<Vec<u8>> = (0..n)
+            .map(|i| vec![i as u8; STREAM_CHUNK_BYTES / 300])
+            .collect();
+        for choice in [299, 999] {
+            let (ch1, ch2) = raw::local_channel_pair();
+            let sent = messages.clone();
+            let h1 = thread::spawn(move || {
+                let sender = ManyOTSender {
+                    interal_sender: Box::new(crate::ot::chou_orlandi::Sender),
+                };
+                sender.exchange_streaming(&sent, &ch1).unwrap();
+            });
+            let receiver = ManyOTReceiver {
+                internal_receiver: Box::new(crate::ot::chou_orlandi::Receiver),
+            };
+            let output = receiver.exchange_streaming(choice, n, &ch2).unwrap();
+            h1.join().unwrap();
+            assert_eq!(output, messages[choice as usize]);
+        }
+    }
 }
@@ -0,0 +1,230 @@
+// Batched oblivious PRF, following KKRT16 (https://eprint.iacr.org/2016/799).
+//
+// The receiver holds inputs r_1..r_m and learns F(j, r_j) for every j, the sender learns a key
+// with which it can compute F(j, x) for any j and x. The W base OTs are done with OT extension
+// (apricot), the pseudorandom code is a random oracle and columns are expanded with `fk`.
+use crate::common::*;
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::apricot;
+use crate::ot::bitmatrix::{BitMatrix, BitVector};
+use crate::ot::chou_orlandi;
+use crate::ot::common::*;
+use crate::ot::one_of_many::fk;
+use crate::util::*;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Width of the pseudorandom code in bits.
+pub const W: usize = 512;
+const W_BYTES: usize = W / 8;
+
+/// Output of the OPRF.
+pub type Output = [u8; 32];
+
+/// Pseudorandom code of `x`.
+#[inline]
+fn code(x: &[u8]) -> [u8; W_BYTES] {
+    let mut c = [0u8; W_BYTES];
+    fk(x, u32::MAX, W_BYTES, &mut c);
+    c
+}
+
+#[inline]
+fn output(j: usize, row: &[u8]) -> Output {
+    hash!(j.to_be_bytes(), row)
+}
+
+/// Transpose W columns of `rows` bits each into `rows` rows of W bits.
+pub(crate) fn transpose(columns: Vec<Vec<u8>>) -> BitMatrix {
+    let columns: BitMatrix = columns.into_iter().map(BitVector::from_vec).collect();
+    columns.transpose()
+}
+
+/// Number of rows used for `n` inputs, the bit matrices need a multiple of 8.
+const fn rows(n: usize) -> usize {
+    n.div_ceil(8) * 8
+}
+
+pub struct Sender {
+    pub bootstrap: Box<dyn ObliviousReceiver>,
+}
+
+pub struct Receiver {
+    pub bootstrap: Box<dyn ObliviousSender>,
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Self {
+            bootstrap: Box::new(apricot::Receiver {
+                bootstrap: Box::new(chou_orlandi::Sender),
+            }),
+        }
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self {
+            bootstrap: Box::new(apricot::Sender {
+                bootstrap: Box::new(chou_orlandi::Receiver),
+            }),
+        }
+    }
+}
+
+/// The sender's key, evaluating the OPRF on any input.
+pub struct Key {
+    s: [u8; W_BYTES],
+    q: BitMatrix,
+    n: usize,
+}
+
+impl Key {
+    /// Number of inputs of the receiver.
+    pub const fn len(&self) -> usize {
+        self.n
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Evaluate F(j, x), equal to the receiver's `j`th output if `x` was its `j`th input.
+    pub fn eval(&self, j: usize, x: &[u8]) -> Output {
+        debug_assert!(j < self.n);
+        let mut row = code(x);
+        for ((r, s), q) in row.iter_mut().zip(&self.s).zip(self.q.row(j)) {
+            *r = (*r & s) ^ q;
+        }
+        output(j, &row)
+    }
+}
+
+impl Sender {
+    pub fn exchange(&self, ch: &Channel) -> Result<Key> {
+        instrument::begin("OPRF Sender", E_FUNC_COLOR);
+        let s: [u8; W_BYTES] = rand::thread_rng().gen();
+        let choices: Vec<bool> = (0..W).map(|i| (s[i / 8] >> (i % 8)) & 1 == 1).collect();
+
+        instrument::begin("Base OT", E_PROT_COLOR);
+        let seeds = self.bootstrap.exchange_bits(&s, W, ch)?;
+        instrument::end();
+
+        instrument::begin("Receive u", E_RECV_COLOR);
+        let (_, r) = ch;
+        let u = r.recv()?;
+        instrument::end();
+        if u.len() % W != 0 {
+            return Err(Box::new(OTError::BadMessage("OPRF matrix")));
+        }
+        let row_bytes = u.len() / W;
+        let n = row_bytes * 8;
+
+        instrument::begin("Compute q", E_COMP_COLOR);
+        let columns: Vec<Vec<u8>> = (0..W)
+            .into_par_iter()
+            .map(|i| {
+                let mut column = vec![0u8; row_bytes];
+                fk(&seeds[i], i as u32, row_bytes, &mut column);
+                if choices[i] {
+                    xor_bytes_inplace(&mut column, &u[i * row_bytes..(i + 1) * row_bytes]);
+                }
+                column
+            })
+            .collect();
+        let q = transpose(columns);
+        instrument::end();
+
+        instrument::end();
+        Ok(Key { s, q, n })
+    }
+}
+
+impl Receiver {
+    /// Returns F(j, r_j) for every input r_j.
+    pub fn exchange<T: AsRef<[u8]> + Sync>(
+        &self,
+        inputs: &[T],
+        ch: &Channel,
+    ) -> Result<Vec<Output>> {
+        instrument::begin("OPRF Receiver", E_FUNC_COLOR);
+        let n = rows(inputs.len());
+        let row_bytes = n / 8;
+
+        let mut random = rand::thread_rng();
+        let seeds: Vec<[[u8; 32]; 2]> = (0..W).map(|_| random.gen()).collect();
+        instrument::begin("Base OT", E_PROT_COLOR);
+        self.bootstrap.exchange(&Message::from_zipped(&seeds), ch)?;
+        instrument::end();
+
+        instrument::begin("Compute u", E_COMP_COLOR);
+        // Unused rows have the all zero code.
+        let mut codes = vec![vec![0u8; W_BYTES]; n];
+        codes
+            .par_iter_mut()
+            .zip(inputs.par_iter())
+            .for_each(|(c, x)| c.copy_from_slice(&code(x.as_ref())));
+        let codes = transpose(codes);
+
+        let (t, u): (Vec<Vec<u8>>, Vec<Vec<u8>>) = (0..W)
+            .into_par_iter()
+            .map(|i| {
+                let mut t = vec![0u8; row_bytes];
+                let mut u = vec![0u8; row_bytes];
+                fk(&seeds[i][0], i as u32, row_bytes, &mut t);
+                fk(&seeds[i][1], i as u32, row_bytes, &mut u);
+                xor_bytes_inplace(&mut u, &t);
+                xor_bytes_inplace(&mut u, codes.row(i));
+                (t, u)
+            })
+            .unzip();
+        instrument::end();
+
+        instrument::begin("Send u", E_SEND_COLOR);
+        let (s, _) = ch;
+        s.send(&u.concat())?;
+        instrument::end();
+
+        instrument::begin("Compute outputs", E_COMP_COLOR);
+        let t = transpose(t);
+        let outputs = (0..inputs.len())
+            .into_par_iter()
+            .map(|j| output(j, t.row(j)))
+            .collect();
+        instrument::end();
+
+        instrument::end();
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_oprf() {
+        let inputs: Vec<Vec<u8>> = (0..100u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || Sender::default().exchange(&ch1).unwrap());
+        let receiver_inputs = inputs.clone();
+        let h2 = thread::spawn(move || {
+            Receiver::default()
+                .exchange(&receiver_inputs, &ch2)
+                .unwrap()
+        });
+        let key = h1.join().unwrap();
+        let outputs = h2.join().unwrap();
+
+        assert_eq!(key.len(), 104);
+        assert_eq!(outputs.len(), 100);
+        for (j, x) in inputs.iter().enumerate() {
+            assert_eq!(key.eval(j, x), outputs[j]);
+            assert_ne!(key.eval(j, b"other"), outputs[j]);
+            assert_ne!(key.eval((j + 1) % 100, x), outputs[j]);
+        }
+    }
+}
@@ -4,6 +4,7 @@
 use crate::ot::bitmatrix::*;
 use serde::{Deserialize, Serialize};
 use std::ops::{Add, AddAssign, Mul, MulAssign};
+use std::sync::OnceLock;
 
 #[repr(transparent)]
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -97,19 +98,43 @@ impl MulAssign for Polynomial {
     }
 }
 
-#[cfg(target_arch = "x86_64")]
-pub fn gf128_mul_acc(result: &mut BitVector, left: &BitVector, right: &BitVector) {
-    polynomial_mul_acc_x86(result, left, right);
+/// An implementation of `destination += left * right` on 16 byte elements.
+type MulAcc = unsafe fn(&mut [u8], &[u8], &[u8]);
+
+static MUL_ACC: OnceLock<MulAcc> = OnceLock::new();
+
+/// Fastest implementation supported by the CPU we are running on.
+fn select_mul_acc() -> MulAcc {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1") {
+        return mul_acc_x86;
+    }
+
+    // PMULL is part of the AES extension.
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("aes") {
+        return mul_acc_arm64;
+    }
+
+    mul_acc_generic
 }
 
-#[cfg(target_arch = "aarch64")]
-pub fn gf128_mul_acc(result: &mut BitVector, left: &BitVector, right: &BitVector) {
-    polynomial_mul_acc_arm64(result, left, right);
+/// Multiply `left` and `right` in GF(2^128) and add the product to `destination`, all given as 16
+/// little-endian bytes. The implementation is picked once, on the first call.
+///
+/// # Panics
+/// If any of the slices is not 16 bytes long.
+#[inline]
+pub fn gf128_mul_acc_bytes(destination: &mut [u8], left: &[u8], right: &[u8]) {
+    assert!(destination.len() == 16 && left.len() == 16 && right.len() == 16);
+    let mul_acc = MUL_ACC.get_or_init(select_mul_acc);
+    // SAFETY: `select_mul_acc` only picks implementations the CPU supports.
+    unsafe { mul_acc(destination, left, right) }
 }
 
-#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
 pub fn gf128_mul_acc(result: &mut BitVector, left: &BitVector, right: &BitVector) {
-    polynomial_mul_acc_generic(result, left, right);
+    gf128_mul_acc_bytes(result.as_mut_bytes(), left.as_bytes(), right.as_bytes());
 }
 
 pub const fn gf128_reduce(x32: u128, x10: u128) -> u128 {
@@ -135,13 +160,14 @@ pub const fn gf128_reduce(x32: u128, x10: u128) -> u128 {
 pub fn polynomial_mul_acc_generic(result: &mut BitVector, left: &BitVector, right: &BitVector) {
     debug_assert!(left.len() == 128);
     debug_assert!(right.len() == 128);
+    mul_acc_generic(result.as_mut_bytes(), left.as_bytes(), right.as_bytes());
+}
 
-    #[inline]
-    unsafe fn clmul<const A: isize, const B: isize>(a: *const u64, b: *const u64) -> u128 {
-        let mut a = *a.offset(A) as u128;
-        let mut b = *b.offset(B) as u128;
+fn mul_acc_generic(destination: &mut [u8], left: &[u8], right: &[u8]) {
+    const fn clmul(mut a: u64, b: u64) -> u128 {
+        let mut b = b as u128;
         let mut r: u128 = 0;
-        while b != 0 {
+        while a != 0 {
             if a & 1 == 1 {
                 r ^= b;
             }
@@ -151,47 +177,32 @@ pub fn polynomial_mul_acc_generic(result: &mut BitVector, left: &BitVector, righ
         r
     }
 
-    let a = left.as_bytes().as_ptr() as *const u64;
-    let b = right.as_bytes().as_ptr() as *const u64;
-
-    unsafe {
-        let c = clmul::<0, 0>(a, b);
-        let d = clmul::<1, 1>(a, b);
-        let e = clmul::<0, 1>(a, b);
-        let f = clmul::<1, 0>(a, b);
+    let half = |x: &[u8], i: usize| u64::from_le_bytes(x[8 * i..8 * i + 8].try_into().unwrap());
+    let (a0, a1) = (half(left, 0), half(left, 1));
+    let (b0, b1) = (half(right, 0), half(right, 1));
 
-        let ef = e ^ f;
-        let lower = ef << 64;
-        let upper = ef >> 64;
-        let left = d ^ upper;
-        let right = c ^ lower;
+    let c = clmul(a0, b0);
+    let d = clmul(a1, b1);
+    let e = clmul(a0, b1);
+    let f = clmul(a1, b0);
 
-        let res = gf128_reduce(left, right);
+    let ef = e ^ f;
+    let lower = ef << 64;
+    let upper = ef >> 64;
+    let left = d ^ upper;
+    let right = c ^ lower;
 
-        let acc = result.as_mut_bytes().as_mut_ptr() as *mut u128;
-        *acc ^= res;
-    }
+    let acc =
+        u128::from_le_bytes(destination[..16].try_into().unwrap()) ^ gf128_reduce(left, right);
+    destination[..16].copy_from_slice(&acc.to_le_bytes());
 }
 
 // -------------------------------------------------------------------------------------------------
 // x86 implementations of gf128_reduce and gf128_mul_acc
-#[inline]
-#[cfg(target_arch = "x86_64")]
-fn polynomial_mul_acc(destination: &mut BitVector, left: &BitVector, right: &BitVector) {
-    polynomial_mul_acc_x86(destination, left, right);
-}
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-#[cfg(target_arch = "x86_64")]
-unsafe fn m128i_to_u128(a: __m128i) -> u128 {
-    let a0 = _mm_extract_epi64(a, 0) as u64;
-    let a1 = _mm_extract_epi64(a, 1) as u64;
-    let a = ((a1 as u128) << 64) | (a0 as u128);
-    a
-}
-
 #[inline]
 #[cfg(target_arch = "x86_64")]
 unsafe fn _mm_slli_si128_1(value: __m128i) -> __m128i {
@@ -220,10 +231,12 @@ unsafe fn _mm_slli_si128_7(value: __m128i) -> __m128i {
     return _mm_or_si128(value, carry);
 }
 
+/// # Safety
+/// The CPU must support SSE4.1.
 #[inline]
 #[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
 pub unsafe fn polynomial_gf128_reduce(x32: __m128i, x10: __m128i) -> __m128i {
-    use std::arch::x86_64::*;
     let x2 = _mm_extract_epi64(x32, 0) as u64;
     let x3 = _mm_extract_epi64(x32, 1) as u64;
 
@@ -244,40 +257,72 @@ pub unsafe fn polynomial_gf128_reduce(x32: __m128i, x10: __m128i) -> __m128i {
     return _mm_xor_si128(h, x10);
 }
 
+/// # Safety
+/// The CPU must support PCLMULQDQ and SSE4.1, use [`gf128_mul_acc`] to check at runtime.
 #[inline]
 #[cfg(target_arch = "x86_64")]
-pub fn polynomial_mul_acc_x86(destination: &mut BitVector, left: &BitVector, right: &BitVector) {
-    use std::arch::x86_64::*;
-    unsafe {
-        let left_bytes = left.as_bytes().as_ptr() as *const __m128i;
-        let right_bytes = right.as_bytes().as_ptr() as *const __m128i;
-        let result_bytes = destination.as_mut_bytes().as_mut_ptr() as *mut u128;
-
-        let a = _mm_lddqu_si128(left_bytes);
-        let b = _mm_lddqu_si128(right_bytes);
-
-        let c = _mm_clmulepi64_si128(a, b, 0x00);
-        let d = _mm_clmulepi64_si128(a, b, 0x11);
-        let e = _mm_clmulepi64_si128(a, b, 0x01);
-        let f = _mm_clmulepi64_si128(a, b, 0x10);
-
-        let ef = _mm_xor_si128(e, f);
-        let lower = _mm_slli_si128(ef, 64 / 8);
-        let upper = _mm_srli_si128(ef, 64 / 8); // should this be in the lower or upper register?
-
-        let left = _mm_xor_si128(d, upper);
-        let right = _mm_xor_si128(c, lower);
-
-        let reduced = polynomial_gf128_reduce(left, right);
-        // TODO: This "cast" seems very and unnecessary!
-        *result_bytes ^= m128i_to_u128(reduced)
-    }
+#[target_feature(enable = "pclmulqdq,sse4.1")]
+pub unsafe fn polynomial_mul_acc_x86(
+    destination: &mut BitVector,
+    left: &BitVector,
+    right: &BitVector,
+) {
+    mul_acc_x86(
+        destination.as_mut_bytes(),
+        left.as_bytes(),
+        right.as_bytes(),
+    );
 }
 
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "pclmulqdq,sse4.1")]
+unsafe fn mul_acc_x86(destination: &mut [u8], left: &[u8], right: &[u8]) {
+    let result_bytes = destination.as_mut_ptr() as *mut __m128i;
+
+    let a = _mm_loadu_si128(left.as_ptr() as *const __m128i);
+    let b = _mm_loadu_si128(right.as_ptr() as *const __m128i);
+
+    let c = _mm_clmulepi64_si128(a, b, 0x00);
+    let d = _mm_clmulepi64_si128(a, b, 0x11);
+    let e = _mm_clmulepi64_si128(a, b, 0x01);
+    let f = _mm_clmulepi64_si128(a, b, 0x10);
+
+    let ef = _mm_xor_si128(e, f);
+    let lower = _mm_slli_si128(ef, 64 / 8);
+    let upper = _mm_srli_si128(ef, 64 / 8);
+
+    let left = _mm_xor_si128(d, upper);
+    let right = _mm_xor_si128(c, lower);
+
+    let reduced = polynomial_gf128_reduce(left, right);
+    let acc = _mm_xor_si128(_mm_loadu_si128(result_bytes), reduced);
+    _mm_storeu_si128(result_bytes, acc);
+}
+
+// -------------------------------------------------------------------------------------------------
+// aarch64 implementation of gf128_mul_acc
+
+/// # Safety
+/// The CPU must support PMULL, use [`gf128_mul_acc`] to check at runtime.
 #[cfg(target_arch = "aarch64")]
-pub fn polynomial_mul_acc_arm64(destination: &mut BitVector, left: &BitVector, right: &BitVector) {
+#[target_feature(enable = "neon,aes")]
+pub unsafe fn polynomial_mul_acc_arm64(
+    destination: &mut BitVector,
+    left: &BitVector,
+    right: &BitVector,
+) {
     debug_assert!(left.len() == 128);
     debug_assert!(right.len() == 128);
+    mul_acc_arm64(
+        destination.as_mut_bytes(),
+        left.as_bytes(),
+        right.as_bytes(),
+    );
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon,aes")]
+unsafe fn mul_acc_arm64(destination: &mut [u8], left: &[u8], right: &[u8]) {
     use core::arch::aarch64::*;
 
     #[inline(always)]
@@ -285,31 +330,26 @@ pub fn polynomial_mul_acc_arm64(destination: &mut BitVector, left: &BitVector, r
         vmull_p64(vgetq_lane_p64(a, A_LANE), vgetq_lane_p64(b, B_LANE))
     }
 
-    unsafe {
-        let left = left.as_bytes().as_ptr() as *const u64;
-        let right = right.as_bytes().as_ptr() as *const u64;
-        let result = destination.as_mut_bytes().as_mut_ptr() as *mut u128;
-
-        let a = vld1q_p64(left);
-        let b = vld1q_p64(right);
+    let a = vld1q_p64(left.as_ptr() as *const u64);
+    let b = vld1q_p64(right.as_ptr() as *const u64);
 
-        // polynomial multiply
-        let z = vdupq_n_p64(0);
+    // polynomial multiply
+    let z = vdupq_n_p64(0);
 
-        let c = pmull::<0, 0>(a, b);
-        let d = pmull::<1, 1>(a, b);
-        let e = pmull::<0, 1>(a, b);
-        let f = pmull::<1, 0>(a, b);
+    let c = pmull::<0, 0>(a, b);
+    let d = pmull::<1, 1>(a, b);
+    let e = pmull::<0, 1>(a, b);
+    let f = pmull::<1, 0>(a, b);
 
-        let ef = vaddq_p128(e, f);
-        let lower = vextq_p64(z, vreinterpretq_p64_p128(ef), 1);
-        let upper = vextq_p64(vreinterpretq_p64_p128(ef), z, 1);
-        let left = vaddq_p128(d, vreinterpretq_p128_p64(upper));
-        let right = vaddq_p128(c, vreinterpretq_p128_p64(lower));
+    let ef = vaddq_p128(e, f);
+    let lower = vextq_p64(z, vreinterpretq_p64_p128(ef), 1);
+    let upper = vextq_p64(vreinterpretq_p64_p128(ef), z, 1);
+    let left = vaddq_p128(d, vreinterpretq_p128_p64(upper));
+    let right = vaddq_p128(c, vreinterpretq_p128_p64(lower));
 
-        let reduced = gf128_reduce(left, right);
-        *result ^= reduced;
-    }
+    let acc =
+        u128::from_le_bytes(destination[..16].try_into().unwrap()) ^ gf128_reduce(left, right);
+    destination[..16].copy_from_slice(&acc.to_le_bytes());
 }
 
 #[cfg(test)]
@@ -346,7 +386,7 @@ mod tests {
             BitVector::from_bytes(&[0b00000101, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // x^2 + 1
         let mut result = Polynomial::new();
 
-        polynomial_mul_acc_x86(&mut result.0, &left, &right);
+        unsafe { polynomial_mul_acc_x86(&mut result.0, &left, &right) };
 
         // Expecting x^3 + x^2 + x + 1
         let result_bytes = result.0.as_bytes();
@@ -366,7 +406,7 @@ mod tests {
             BitVector::from_bytes(&[0b00000101, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]); // x^2 + 1
         let mut result = Polynomial::new();
 
-        polynomial_mul_acc_arm64(&mut result.0, &left, &right);
+        unsafe { polynomial_mul_acc_arm64(&mut result.0, &left, &right) };
 
         println!("Result: {:?}", result);
         // Expecting x^3 + x^2 + x + 1
@@ -390,7 +430,7 @@ mod tests {
             let a = BitVector::from_bytes(&a);
             let b = BitVector::from_bytes(&b);
             let mut c1 = BitVector::from_bytes(&[0x00; 16]);
-            polynomial_mul_acc_arm64(&mut c1, &a, &b);
+            unsafe { polynomial_mul_acc_arm64(&mut c1, &a, &b) };
             let r1 = Polynomial::from(c1);
 
             let mut c2 = BitVector::from_bytes(&[0x00; 16]);
@@ -414,7 +454,7 @@ mod tests {
             let a = BitVector::from_bytes(&a);
             let b = BitVector::from_bytes(&b);
             let mut c1 = BitVector::from_bytes(&[0x00; 16]);
-            polynomial_mul_acc_x86(&mut c1, &a, &b);
+            unsafe { polynomial_mul_acc_x86(&mut c1, &a, &b) };
             let r1 = Polynomial::from(c1);
 
             let mut c2 = BitVector::from_bytes(&[0x00; 16]);
@@ -452,4 +492,38 @@ mod tests {
             assert_eq!(c1, c2);
         }
     }
+
+    #[test]
+    fn test_implementations_agree() {
+        use super::*;
+        use rand::{Rng, SeedableRng};
+        use rand_chacha::ChaChaRng;
+        let mut rng = ChaChaRng::from_seed([1; 32]);
+        for _ in 0..100 {
+            let a = BitVector::from_bytes(&rng.gen::<[u8; 16]>());
+            let b = BitVector::from_bytes(&rng.gen::<[u8; 16]>());
+            let acc = BitVector::from_bytes(&rng.gen::<[u8; 16]>());
+
+            let mut expected = acc.clone();
+            polynomial_mul_acc_generic(&mut expected, &a, &b);
+
+            let mut dispatched = acc.clone();
+            gf128_mul_acc(&mut dispatched, &a, &b);
+            assert_eq!(dispatched, expected);
+
+            #[cfg(target_arch = "x86_64")]
+            if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse4.1") {
+                let mut x86 = acc.clone();
+                unsafe { polynomial_mul_acc_x86(&mut x86, &a, &b) };
+                assert_eq!(x86, expected);
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            if std::arch::is_aarch64_feature_detected!("aes") {
+                let mut arm64 = acc.clone();
+                unsafe { polynomial_mul_acc_arm64(&mut arm64, &a, &b) };
+                assert_eq!(arm64, expected);
+            }
+        }
+    }
 }
@@ -0,0 +1,703 @@
+// Silent OT extension following Ferret (https://eprint.iacr.org/2020/924), semi-honest version.
+//
+// Produces correlated OTs: the sender holds a global delta and keys K_i, the receiver holds
+// random bits x_i and M_i = K_i ^ x_i * delta. Each iteration turns k correlated OTs into n by
+// the primal LPN assumption, x = u * A + e for a sparse local code A and regular noise e with one
+// non-zero position in each of t blocks. The noise is shared with a punctured GGM tree per block,
+// costing t * log(n / t) base OTs, and k of the outputs are reserved as the next iteration's
+// u. Only the first iteration needs k base correlated OTs, taken from apricot, so communication
+// is sublinear in the number of OTs produced. Chosen message OT is done by derandomizing the
+// correlated OTs.
+//
+// The sender and receiver keep delta, the reserved outputs and any outputs not used yet between
+// calls, so the base OTs are done once for a pair and an iteration serves as many calls as it
+// has outputs for.
+use std::sync::Mutex;
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, NewBlockCipher};
+use aes::Aes128;
+use rand::{Rng, SeedableRng};
+use rand_chacha::{ChaCha20Rng, ChaCha8Rng};
+use rayon::prelude::*;
+
+use crate::common::*;
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::apricot;
+use crate::ot::chou_orlandi;
+use crate::ot::coinflip::{coinflip_receiver, coinflip_sender};
+use crate::ot::common::*;
+use crate::util::*;
+
+pub type Block = u128;
+
+/// Non-zero entries in each column of the local linear code.
+const D: usize = 10;
+/// Outputs of the local linear code computed from one PRG stream.
+const CHUNK: usize = 1 << 12;
+
+/// Largest supported depth of a GGM tree.
+pub const MAX_DEPTH: usize = 30;
+
+/// LPN parameters, each iteration producing `t << depth` correlated OTs from `k`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// Dimension, the number of correlated OTs used as input.
+    pub k: usize,
+    /// Noise weight, the number of GGM trees.
+    pub t: usize,
+    /// Depth of each GGM tree.
+    pub depth: usize,
+}
+
+impl Params {
+    /// Regular noise parameters from Ferret for 128-bit security, n = 10,805,248.
+    pub const FERRET: Self = Self {
+        k: 589_760,
+        t: 1_319,
+        depth: 13,
+    };
+
+    /// Correlated OTs produced per iteration, including the `k` reserved for the next.
+    ///
+    /// # Panics
+    /// If `depth` exceeds [`MAX_DEPTH`] or the count overflows.
+    pub const fn n(&self) -> usize {
+        match self.checked_n() {
+            Some(n) => n,
+            None => panic!("Silent OT parameters overflow"),
+        }
+    }
+
+    const fn checked_n(&self) -> Option<usize> {
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        self.t.checked_mul(1 << self.depth)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self.checked_n() {
+            Some(n) if self.t != 0 && self.depth != 0 && self.k < n => Ok(()),
+            _ => Err(Box::new(OTError::BadMessage("silent OT parameters"))),
+        }
+    }
+}
+
+/// Correlated OTs kept by the sender between calls.
+struct SenderState {
+    delta: Block,
+    /// The `k` keys reserved for the next iteration, once bootstrapped.
+    base: Option<Vec<Block>>,
+    /// Keys produced but not returned yet.
+    keys: Vec<Block>,
+}
+
+/// Correlated OTs kept by the receiver between calls.
+#[derive(Default)]
+struct ReceiverState {
+    /// The choice bits and MACs reserved for the next iteration, once bootstrapped.
+    base: Option<(Vec<bool>, Vec<Block>)>,
+    /// Choice bits and MACs produced but not returned yet.
+    bits: Vec<bool>,
+    macs: Vec<Block>,
+}
+
+/// Silent OT sender, to be used with the same [`Receiver`] for every call.
+pub struct Sender {
+    pub bootstrap: apricot::Sender,
+    pub params: Params,
+    state: Mutex<SenderState>,
+}
+
+/// Silent OT receiver, to be used with the same [`Sender`] for every call.
+pub struct Receiver {
+    pub bootstrap: apricot::Receiver,
+    pub params: Params,
+    state: Mutex<ReceiverState>,
+}
+
+impl Default for Sender {
+    fn default() -> Self {
+        Self::new(
+            apricot::Sender::new(Box::new(chou_orlandi::Receiver)),
+            Params::FERRET,
+        )
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new(
+            apricot::Receiver::new(Box::new(chou_orlandi::Sender)),
+            Params::FERRET,
+        )
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Primitives
+
+/// Length doubling PRG for the GGM trees, fixed-key AES in Davies-Meyer mode.
+struct Prg([Aes128; 2]);
+
+impl Prg {
+    fn new() -> Self {
+        Self([
+            Aes128::new(GenericArray::from_slice(&[0u8; 16])),
+            Aes128::new(GenericArray::from_slice(&[1u8; 16])),
+        ])
+    }
+
+    #[inline]
+    fn children(&self, seed: Block) -> [Block; 2] {
+        let mut blocks = [
+            GenericArray::from(seed.to_le_bytes()),
+            GenericArray::from(seed.to_le_bytes()),
+        ];
+        self.0[0].encrypt_block(&mut blocks[0]);
+        self.0[1].encrypt_block(&mut blocks[1]);
+        blocks.map(|b| Block::from_le_bytes(b.into()) ^ seed)
+    }
+}
+
+/// Expand a GGM tree into `leaves`, returning the XOR of all left and right children per level.
+fn expand(prg: &Prg, seed: Block, leaves: &mut [Block]) -> Vec<[Block; 2]> {
+    let depth = leaves.len().trailing_zeros() as usize;
+    let mut sums = vec![[0; 2]; depth];
+    leaves[0] = seed;
+    for (level, sum) in sums.iter_mut().enumerate() {
+        // Expand in place from the back, so parents are read before being overwritten.
+        for i in (0..1 << level).rev() {
+            let children = prg.children(leaves[i]);
+            leaves[2 * i] = children[0];
+            leaves[2 * i + 1] = children[1];
+            sum[0] ^= children[0];
+            sum[1] ^= children[1];
+        }
+    }
+    sums
+}
+
+/// Reconstruct all leaves but `alpha` from the sums of the children not on the path to it.
+fn puncture(prg: &Prg, alpha: usize, sums: &[Block], leaves: &mut [Block]) {
+    let depth = sums.len();
+    leaves[0] = 0;
+    for (level, &sum) in sums.iter().enumerate() {
+        let path = alpha >> (depth - level);
+        let mut known = [0; 2];
+        for i in (0..1 << level).rev() {
+            let children = if i == path {
+                [0; 2]
+            } else {
+                prg.children(leaves[i])
+            };
+            leaves[2 * i] = children[0];
+            leaves[2 * i + 1] = children[1];
+            known[0] ^= children[0];
+            known[1] ^= children[1];
+        }
+        let next = alpha >> (depth - level - 1);
+        let sibling = next ^ 1;
+        leaves[sibling] = sum ^ known[sibling & 1];
+    }
+}
+
+/// The `j`th base OT indices of every output in chunk `c` of the local linear code.
+fn code(seed: [u8; 32], chunk: usize, k: usize, len: usize) -> Vec<[u32; D]> {
+    let mut prg = ChaCha8Rng::from_seed(seed);
+    prg.set_stream(chunk as u64);
+    (0..len)
+        .map(|_| [(); D].map(|_| prg.gen_range(0..k as u32)))
+        .collect()
+}
+
+/// One time pad for message `i` with length `len`, from a correlated OT key.
+fn pad(i: usize, key: Block, len: usize) -> Vec<u8> {
+    let mut prg = ChaCha20Rng::from_seed(hash!((i as u64).to_be_bytes(), key.to_le_bytes()));
+    (0..len).map(|_| prg.gen::<u8>()).collect()
+}
+
+/// Read a block from the bootstrap OT or the other party.
+fn block(bytes: &[u8]) -> std::result::Result<Block, OTError> {
+    let bytes = bytes
+        .try_into()
+        .map_err(|_| OTError::BadMessage("silent OT block"))?;
+    Ok(Block::from_le_bytes(bytes))
+}
+
+// -------------------------------------------------------------------------------------------------
+// Sender
+
+impl Sender {
+    pub fn new(bootstrap: apricot::Sender, params: Params) -> Self {
+        let state = SenderState {
+            delta: ChaCha20Rng::from_entropy().gen(),
+            base: None,
+            keys: Vec::new(),
+        };
+        Self {
+            bootstrap,
+            params,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Produce `count` correlated OTs, returning delta and the keys K_i.
+    ///
+    /// Delta is the same for every call. The base OTs are done by the first call that needs an
+    /// iteration, and outputs left over by an iteration are returned by the following calls.
+    pub fn correlated(&self, count: usize, ch: &Channel) -> Result<(Block, Vec<Block>)> {
+        instrument::begin("Silent Sender", E_FUNC_COLOR);
+        let params = self.params;
+        params.validate()?;
+        let pb = TransactionProperties {
+            msg_size: count,
+            protocol: format!("Silent {:?}", params),
+        };
+        validate_properties(&pb, ch)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.keys.len() < count {
+            let mut base = match state.base.take() {
+                Some(base) => base,
+                None => {
+                    instrument::begin("Base OT", E_PROT_COLOR);
+                    let delta = state.delta.to_le_bytes();
+                    let q = self.bootstrap.correlated(&delta, params.k, ch)?;
+                    instrument::end();
+                    q.into_iter().map(Block::from_le_bytes).collect()
+                }
+            };
+            while state.keys.len() < count {
+                let mut keys = self.extend(state.delta, &base, ch)?;
+                state.keys.extend(keys.drain(params.k..));
+                base = keys;
+            }
+            state.base = Some(base);
+        }
+        let rest = state.keys.len() - count;
+        let keys = state.keys.split_off(rest);
+        let delta = state.delta;
+        drop(state);
+
+        instrument::end();
+        Ok((delta, keys))
+    }
+
+    fn extend(&self, delta: Block, base: &[Block], ch: &Channel) -> Result<Vec<Block>> {
+        let params = self.params;
+        let seed = coinflip_sender::<32>(ch)?;
+
+        instrument::begin("Expand GGM trees", E_COMP_COLOR);
+        let prg = Prg::new();
+        let mut keys = vec![0; params.n()];
+        let seeds: Vec<Block> = {
+            let mut random = ChaCha20Rng::from_entropy();
+            (0..params.t).map(|_| random.gen()).collect()
+        };
+        let (sums, corrections): (Vec<_>, Vec<_>) = keys
+            .par_chunks_mut(1 << params.depth)
+            .zip(seeds)
+            .map(|(leaves, seed)| {
+                let sums = expand(&prg, seed, leaves);
+                let correction = leaves.iter().fold(delta, |acc, l| acc ^ l);
+                (sums, correction)
+            })
+            .unzip();
+        instrument::end();
+
+        instrument::begin("GGM OT", E_PROT_COLOR);
+        let messages: Vec<[[u8; 16]; 2]> = sums
+            .iter()
+            .flatten()
+            .map(|s| s.map(Block::to_le_bytes))
+            .collect();
+        self.bootstrap
+            .exchange(&Message::from_zipped(&messages), ch)?;
+        instrument::end();
+
+        instrument::begin("Send corrections", E_SEND_COLOR);
+        let (s, _) = ch;
+        let corrections: Vec<u8> = corrections.iter().flat_map(|c| c.to_le_bytes()).collect();
+        s.send(&corrections)?;
+        instrument::end();
+
+        instrument::begin("Local linear code", E_COMP_COLOR);
+        keys.par_chunks_mut(CHUNK)
+            .enumerate()
+            .for_each(|(c, chunk)| {
+                let rows = code(seed, c, params.k, chunk.len());
+                for (key, row) in chunk.iter_mut().zip(rows) {
+                    *key = row.iter().fold(*key, |acc, &j| acc ^ base[j as usize]);
+                }
+            });
+        instrument::end();
+
+        Ok(keys)
+    }
+}
+
+impl ObliviousSender for Sender {
+    fn exchange(&self, msg: &Message, ch: &Channel) -> Result<()> {
+        let (delta, keys) = self.correlated(msg.len(), ch)?;
+        let (s, r) = ch;
+
+        instrument::begin("Receive flips", E_RECV_COLOR);
+        let flips = r.recv()?;
+        if flips.len() != msg.len().div_ceil(8) {
+            return Err(Box::new(OTError::BadMessage("silent OT choice flips")));
+        }
+        instrument::end();
+
+        instrument::begin("Encrypt messages", E_COMP_COLOR);
+        let payload: Vec<[Vec<u8>; 2]> = msg
+            .0
+            .par_iter()
+            .zip(keys.par_iter())
+            .enumerate()
+            .map(|(i, ([m0, m1], key))| {
+                let flip = (flips[i / 8] >> (i % 8)) & 1 == 1;
+                let (k0, k1) = if flip {
+                    (key ^ delta, *key)
+                } else {
+                    (*key, key ^ delta)
+                };
+                [
+                    xor_bytes(m0, &pad(i, k0, m0.len())),
+                    xor_bytes(m1, &pad(i, k1, m1.len())),
+                ]
+            })
+            .collect();
+        instrument::end();
+
+        s.send(&bincode::serialize(&payload)?)?;
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Receiver
+
+impl Receiver {
+    pub fn new(bootstrap: apricot::Receiver, params: Params) -> Self {
+        Self {
+            bootstrap,
+            params,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Produce `count` correlated OTs, returning the random bits x_i and M_i = K_i ^ x_i * delta.
+    ///
+    /// See [`Sender::correlated`].
+    pub fn correlated(&self, count: usize, ch: &Channel) -> Result<(Vec<bool>, Vec<Block>)> {
+        instrument::begin("Silent Receiver", E_FUNC_COLOR);
+        let params = self.params;
+        params.validate()?;
+        let pb = TransactionProperties {
+            msg_size: count,
+            protocol: format!("Silent {:?}", params),
+        };
+        validate_properties(&pb, ch)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.macs.len() < count {
+            let (mut u, mut base) = match state.base.take() {
+                Some(base) => base,
+                None => {
+                    instrument::begin("Base OT", E_PROT_COLOR);
+                    let mut random = ChaCha20Rng::from_entropy();
+                    let choices: Vec<bool> = (0..params.k).map(|_| random.gen()).collect();
+                    let t = self.bootstrap.correlated(&choices, ch)?;
+                    instrument::end();
+                    (choices, t.into_iter().map(Block::from_le_bytes).collect())
+                }
+            };
+            while state.macs.len() < count {
+                let (mut x, mut m) = self.extend(&u, &base, ch)?;
+                state.bits.extend(x.drain(params.k..));
+                state.macs.extend(m.drain(params.k..));
+                u = x;
+                base = m;
+            }
+            state.base = Some((u, base));
+        }
+        let rest = state.macs.len() - count;
+        let bits = state.bits.split_off(rest);
+        let macs = state.macs.split_off(rest);
+        drop(state);
+
+        instrument::end();
+        Ok((bits, macs))
+    }
+
+    fn extend(&self, u: &[bool], base: &[Block], ch: &Channel) -> Result<(Vec<bool>, Vec<Block>)> {
+        let params = self.params;
+        let seed = coinflip_receiver::<32>(ch)?;
+
+        let mut random = ChaCha20Rng::from_entropy();
+        let alphas: Vec<usize> = (0..params.t)
+            .map(|_| random.gen_range(0..1 << params.depth))
+            .collect();
+
+        instrument::begin("GGM OT", E_PROT_COLOR);
+        let choices: Vec<bool> = alphas
+            .iter()
+            .flat_map(|alpha| {
+                (0..params.depth).map(move |l| (alpha >> (params.depth - l - 1)) & 1 == 0)
+            })
+            .collect();
+        let sums = self.bootstrap.exchange(&choices, ch)?;
+        let sums = sums
+            .iter()
+            .map(block)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        instrument::end();
+
+        instrument::begin("Receive corrections", E_RECV_COLOR);
+        let (_, r) = ch;
+        let corrections = r.recv()?;
+        if corrections.len() != params.t * 16 {
+            return Err(Box::new(OTError::BadMessage("silent OT corrections")));
+        }
+        instrument::end();
+
+        instrument::begin("Puncture GGM trees", E_COMP_COLOR);
+        let prg = Prg::new();
+        let mut macs = vec![0; params.n()];
+        macs.par_chunks_mut(1 << params.depth)
+            .enumerate()
+            .for_each(|(j, leaves)| {
+                let sums = &sums[j * params.depth..(j + 1) * params.depth];
+                puncture(&prg, alphas[j], sums, leaves);
+                let correction = corrections[j * 16..(j + 1) * 16].try_into().unwrap();
+                let correction = Block::from_le_bytes(correction);
+                leaves[alphas[j]] = leaves.iter().fold(correction, |acc, l| acc ^ l);
+            });
+        let mut bits = vec![false; params.n()];
+        for (j, alpha) in alphas.iter().enumerate() {
+            bits[(j << params.depth) + alpha] = true;
+        }
+        instrument::end();
+
+        instrument::begin("Local linear code", E_COMP_COLOR);
+        macs.par_chunks_mut(CHUNK)
+            .zip(bits.par_chunks_mut(CHUNK))
+            .enumerate()
+            .for_each(|(c, (macs, bits))| {
+                let rows = code(seed, c, params.k, macs.len());
+                for ((mac, bit), row) in macs.iter_mut().zip(bits.iter_mut()).zip(rows) {
+                    for j in row {
+                        *mac ^= base[j as usize];
+                        *bit ^= u[j as usize];
+                    }
+                }
+            });
+        instrument::end();
+
+        Ok((bits, macs))
+    }
+}
+
+impl ObliviousReceiver for Receiver {
+    fn exchange(&self, choices: &[bool], ch: &Channel) -> Result<Payload> {
+        let (bits, macs) = self.correlated(choices.len(), ch)?;
+        let (s, r) = ch;
+
+        instrument::begin("Send flips", E_SEND_COLOR);
+        let flips: Vec<bool> = choices.iter().zip(&bits).map(|(c, x)| c ^ x).collect();
+        s.send(&pack_bits(&flips))?;
+        instrument::end();
+
+        let payload: Vec<[Vec<u8>; 2]> = bincode::deserialize(&r.recv()?)?;
+        if payload.len() != choices.len() {
+            return Err(Box::new(OTError::BadMessage("silent OT ciphertexts")));
+        }
+
+        instrument::begin("Decrypt messages", E_COMP_COLOR);
+        let mut msg = Payload::with_lengths(
+            payload
+                .iter()
+                .zip(choices)
+                .map(|(e, &c)| e[c as usize].len()),
+        );
+        msg.par_iter_mut()
+            .zip(
+                payload
+                    .par_iter()
+                    .zip(choices.par_iter().zip(macs.par_iter())),
+            )
+            .enumerate()
+            .for_each(|(i, (m, (e, (&c, &mac))))| {
+                m.copy_from_slice(&e[c as usize]);
+                xor_bytes_inplace(m, &pad(i, mac, m.len()));
+            });
+        instrument::end();
+
+        Ok(msg)
+    }
+
+    fn exchange_bits(&self, choices: &[u8], len: usize, ch: &Channel) -> Result<Payload> {
+        self.exchange(&unpack_bits(choices, len), ch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Small and insecure, but exercises several iterations.
+    const PARAMS: Params = Params {
+        k: 256,
+        t: 8,
+        depth: 6,
+    };
+
+    #[test]
+    fn test_puncture() {
+        let prg = Prg::new();
+        let mut leaves = vec![0; 64];
+        let sums = expand(&prg, 42, &mut leaves);
+        for alpha in [0, 1, 17, 63] {
+            let chosen: Vec<Block> = sums
+                .iter()
+                .enumerate()
+                .map(|(l, s)| s[((alpha >> (5 - l)) & 1) ^ 1])
+                .collect();
+            let mut punctured = vec![0; 64];
+            puncture(&prg, alpha, &chosen, &mut punctured);
+            for i in 0..64 {
+                if i != alpha {
+                    assert_eq!(punctured[i], leaves[i]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_correlated() {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                params: PARAMS,
+                ..Sender::default()
+            };
+            sender.correlated(1000, &ch1).unwrap()
+        });
+        let h2 = thread::spawn(move || {
+            let receiver = Receiver {
+                params: PARAMS,
+                ..Receiver::default()
+            };
+            receiver.correlated(1000, &ch2).unwrap()
+        });
+        let (delta, keys) = h1.join().unwrap();
+        let (bits, macs) = h2.join().unwrap();
+
+        assert_eq!(keys.len(), 1000);
+        for i in 0..1000 {
+            let expected = if bits[i] { keys[i] ^ delta } else { keys[i] };
+            assert_eq!(macs[i], expected);
+        }
+        // The choice bits should be roughly balanced.
+        let ones = bits.iter().filter(|b| **b).count();
+        assert!(ones > 400 && ones < 600);
+    }
+
+    #[test]
+    fn test_reuse() {
+        // Served from the left over outputs, from a new iteration, and from several.
+        let counts = [100, 200, 0, 300, 1000];
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                params: PARAMS,
+                ..Sender::default()
+            };
+            counts.map(|n| sender.correlated(n, &ch1).unwrap())
+        });
+        let h2 = thread::spawn(move || {
+            let receiver = Receiver {
+                params: PARAMS,
+                ..Receiver::default()
+            };
+            counts.map(|n| receiver.correlated(n, &ch2).unwrap())
+        });
+        let sent = h1.join().unwrap();
+        let received = h2.join().unwrap();
+
+        let delta = sent[0].0;
+        for (n, ((d, keys), (bits, macs))) in counts.iter().zip(sent.iter().zip(&received)) {
+            assert_eq!(*d, delta);
+            assert_eq!((keys.len(), bits.len(), macs.len()), (*n, *n, *n));
+            for i in 0..*n {
+                let expected = if bits[i] { keys[i] ^ delta } else { keys[i] };
+                assert_eq!(macs[i], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chosen_messages() {
+        let m0: Vec<[u8; 20]> = (0..100u8).map(|i| [i; 20]).collect();
+        let m1: Vec<[u8; 20]> = (0..100u8).map(|i| [!i; 20]).collect();
+        let choices: Vec<bool> = (0..100).map(|i| i % 3 == 1).collect();
+
+        let (ch1, ch2) = raw::local_channel_pair();
+        let (sent0, sent1) = (m0.clone(), m1.clone());
+        let h1 = thread::spawn(move || {
+            let sender = Sender {
+                params: PARAMS,
+                ..Sender::default()
+            };
+            let msg = Message::from_unzipped(&sent0, &sent1);
+            sender.exchange(&msg, &ch1).unwrap();
+        });
+        let c = choices.clone();
+        let h2 = thread::spawn(move || {
+            let receiver = Receiver {
+                params: PARAMS,
+                ..Receiver::default()
+            };
+            receiver.exchange(&c, &ch2).unwrap()
+        });
+        h1.join().unwrap();
+        let received = h2.join().unwrap();
+
+        for (i, &c) in choices.iter().enumerate() {
+            let expected = if c { m1[i] } else { m0[i] };
+            assert_eq!(received[i], expected);
+        }
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(PARAMS.validate().is_ok());
+        assert!(Params::FERRET.validate().is_ok());
+        for params in [
+            Params { t: 0, ..PARAMS },
+            Params { depth: 0, ..PARAMS },
+            Params {
+                k: PARAMS.n(),
+                ..PARAMS
+            },
+            Params {
+                depth: MAX_DEPTH + 1,
+                ..PARAMS
+            },
+            Params {
+                depth: usize::BITS as usize,
+                ..PARAMS
+            },
+            Params {
+                t: usize::MAX,
+                ..PARAMS
+            },
+        ] {
+            assert!(params.validate().is_err(), "{:?}", params);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Bit matrix transposition shared by the OT extensions.
+//
+// Matrices are stored row-major with bit `j` of a row in bit `j % 8` of byte `j / 8`. The target
+// is filled one column byte at a time: the 8 columns stored in byte `c` of every source row become
+// target rows `8c..8c + 8`, so the column bytes are transposed in parallel. Within a column byte,
+// x86_64 gathers 16 (SSE2) or 32 (AVX2) rows and extracts one bit of each with `movemask`, aarch64
+// does the same for 8 rows with NEON, and anything left is handled by an Eklundh transpose of 8x8
+// bit blocks packed in a `u64`.
+use rayon::prelude::*;
+
+/// Transpose the `rows` x `cols` bit matrix in `source` into `target`.
+///
+/// # Panics
+/// If `rows` or `cols` is not a multiple of 8, or a buffer does not hold `rows * cols` bits.
+pub fn transpose(source: &[u8], target: &mut [u8], rows: usize, cols: usize) {
+    transpose_by(source, target, rows, cols, transpose_column);
+}
+
+/// [`transpose`] without SIMD, for comparison.
+pub fn transpose_portable(source: &[u8], target: &mut [u8], rows: usize, cols: usize) {
+    transpose_by(source, target, rows, cols, |source, block, rows, c| {
+        transpose_column_portable(source, block, 0, rows, c);
+    });
+}
+
+fn transpose_by(
+    source: &[u8],
+    target: &mut [u8],
+    rows: usize,
+    cols: usize,
+    column: impl Fn(&[u8], &mut [u8], usize, usize) + Sync,
+) {
+    assert!(rows.is_multiple_of(8) && cols.is_multiple_of(8));
+    assert_eq!(source.len(), rows * cols / 8);
+    assert_eq!(target.len(), rows * cols / 8);
+    if rows == 0 || cols == 0 {
+        return;
+    }
+
+    target
+        .par_chunks_mut(rows)
+        .enumerate()
+        .for_each(|(c, block)| column(source, block, rows, c));
+}
+
+/// Transpose column byte `c` of `source` into the 8 target rows in `block`.
+fn transpose_column(source: &[u8], block: &mut [u8], rows: usize, c: usize) {
+    #[allow(unused_mut)]
+    let mut start = 0;
+
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        if is_x86_feature_detected!("avx2") {
+            start = x86::transpose_column_avx2(source, block, start, rows, c);
+        }
+        start = x86::transpose_column_sse2(source, block, start, rows, c);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        start = arm64::transpose_column_neon(source, block, start, rows, c);
+    }
+
+    transpose_column_portable(source, block, start, rows, c);
+}
+
+/// Transpose the 8x8 bit matrix with row `i` in byte `i` of `x`.
+const fn transpose_8x8(mut x: u64) -> u64 {
+    let t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+    x ^= t ^ (t << 7);
+    let t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+    x ^= t ^ (t << 14);
+    let t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+    x ^ t ^ (t << 28)
+}
+
+fn transpose_column_portable(source: &[u8], block: &mut [u8], start: usize, rows: usize, c: usize) {
+    let row_bytes = source.len() / rows;
+    let target_row_bytes = rows / 8;
+    for r in (start..rows).step_by(8) {
+        let mut x = 0u64;
+        for k in 0..8 {
+            x |= (source[(r + k) * row_bytes + c] as u64) << (8 * k);
+        }
+        for (b, byte) in transpose_8x8(x).to_le_bytes().into_iter().enumerate() {
+            block[b * target_row_bytes + r / 8] = byte;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    // `movemask` collects the top bit of every byte, so the rows are extracted from bit 7 down,
+    // shifting the next bit into place each time.
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn transpose_column_sse2(
+        source: &[u8],
+        block: &mut [u8],
+        mut start: usize,
+        rows: usize,
+        c: usize,
+    ) -> usize {
+        let row_bytes = source.len() / rows;
+        let target_row_bytes = rows / 8;
+        while start + 16 <= rows {
+            let mut bytes = [0u8; 16];
+            for (k, byte) in bytes.iter_mut().enumerate() {
+                *byte = source[(start + k) * row_bytes + c];
+            }
+            let mut v = _mm_loadu_si128(bytes.as_ptr() as *const __m128i);
+            for b in (0..8).rev() {
+                let mask = _mm_movemask_epi8(v) as u16;
+                let offset = b * target_row_bytes + start / 8;
+                block[offset..offset + 2].copy_from_slice(&mask.to_le_bytes());
+                v = _mm_slli_epi64(v, 1);
+            }
+            start += 16;
+        }
+        start
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn transpose_column_avx2(
+        source: &[u8],
+        block: &mut [u8],
+        mut start: usize,
+        rows: usize,
+        c: usize,
+    ) -> usize {
+        let row_bytes = source.len() / rows;
+        let target_row_bytes = rows / 8;
+        while start + 32 <= rows {
+            let mut bytes = [0u8; 32];
+            for (k, byte) in bytes.iter_mut().enumerate() {
+                *byte = source[(start + k) * row_bytes + c];
+            }
+            let mut v = _mm256_loadu_si256(bytes.as_ptr() as *const __m256i);
+            for b in (0..8).rev() {
+                let mask = _mm256_movemask_epi8(v) as u32;
+                let offset = b * target_row_bytes + start / 8;
+                block[offset..offset + 4].copy_from_slice(&mask.to_le_bytes());
+                v = _mm256_slli_epi64(v, 1);
+            }
+            start += 32;
+        }
+        start
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm64 {
+    use std::arch::aarch64::*;
+
+    // NEON has no `movemask`, instead each lane is tested against the bit and weighted by its
+    // position before a horizontal add.
+
+    pub(super) unsafe fn transpose_column_neon(
+        source: &[u8],
+        block: &mut [u8],
+        mut start: usize,
+        rows: usize,
+        c: usize,
+    ) -> usize {
+        const WEIGHTS: [u8; 8] = [1, 2, 4, 8, 16, 32, 64, 128];
+        let weights = vld1_u8(WEIGHTS.as_ptr());
+        let row_bytes = source.len() / rows;
+        let target_row_bytes = rows / 8;
+        while start + 8 <= rows {
+            let mut bytes = [0u8; 8];
+            for (k, byte) in bytes.iter_mut().enumerate() {
+                *byte = source[(start + k) * row_bytes + c];
+            }
+            let v = vld1_u8(bytes.as_ptr());
+            for b in 0..8 {
+                let set = vtst_u8(v, vdup_n_u8(1 << b));
+                block[b * target_row_bytes + start / 8] = vaddv_u8(vand_u8(set, weights));
+            }
+            start += 8;
+        }
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn naive(source: &[u8], rows: usize, cols: usize) -> Vec<u8> {
+        let mut target = vec![0u8; source.len()];
+        for r in 0..rows {
+            for c in 0..cols {
+                let bit = (source[r * cols / 8 + c / 8] >> (c % 8)) & 1;
+                target[c * rows / 8 + r / 8] |= bit << (r % 8);
+            }
+        }
+        target
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut random = rand::thread_rng();
+        for (rows, cols) in [
+            (8, 8),
+            (16, 8),
+            (24, 40),
+            (128, 1032),
+            (1048, 128),
+            (72, 256),
+        ] {
+            let source: Vec<u8> = (0..rows * cols / 8).map(|_| random.gen()).collect();
+            let expected = naive(&source, rows, cols);
+
+            let mut target = vec![0u8; source.len()];
+            transpose(&source, &mut target, rows, cols);
+            assert_eq!(target, expected);
+
+            let mut portable = vec![0u8; source.len()];
+            transpose_portable(&source, &mut portable, rows, cols);
+            assert_eq!(portable, expected);
+
+            let mut back = vec![0u8; source.len()];
+            transpose(&target, &mut back, cols, rows);
+            assert_eq!(back, source);
+        }
+    }
+
+    #[test]
+    fn test_transpose_empty() {
+        transpose(&[], &mut [], 0, 128);
+        transpose(&[], &mut [], 128, 0);
+    }
+}
@@ -0,0 +1,321 @@
+// Private set intersection following KKRT16 (https://eprint.iacr.org/2016/799).
+//
+// The receiver places its items in a cuckoo table with three hash functions and a small stash,
+// and evaluates the batched OPRF on the item in every bin and stash slot. The sender evaluates
+// the OPRF on each of its items in each of the three bins it could have been placed in and in
+// every stash slot, and sends the results shuffled. The receiver learns which of its items are
+// in the intersection, while the sender learns nothing but the size of the receiver's table.
+//
+// The hash functions are drawn once, before hashing, so they do not depend on the receiver's
+// set. With the stash, insertion fails with negligible probability, and is then reported as an
+// error rather than retried with new hash functions.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rayon::prelude::*;
+
+use crate::common::*;
+use crate::instrument;
+use crate::instrument::{E_COMP_COLOR, E_FUNC_COLOR, E_PROT_COLOR, E_RECV_COLOR, E_SEND_COLOR};
+use crate::ot::common::OTError;
+use crate::ot::oprf;
+use crate::util::*;
+
+/// Number of hash functions used for cuckoo hashing.
+const HASHES: usize = 3;
+/// Number of bytes of each OPRF output sent by the sender.
+const TAG: usize = 16;
+/// Evictions before an item is moved to the stash.
+const MAX_EVICTIONS: usize = 500;
+
+type Tag = [u8; TAG];
+type Seed = [u8; 32];
+
+/// Number of bins in the cuckoo table for `n` items.
+const fn bins(n: usize) -> usize {
+    n + n / 2 + HASHES
+}
+
+/// Number of stash slots for `n` items, larger for small sets where insertion fails more often.
+const fn stash(n: usize) -> usize {
+    match n {
+        0..=0x100 => 12,
+        0x101..=0x1000 => 6,
+        0x1001..=0x10000 => 4,
+        0x10001..=0x100000 => 3,
+        _ => 2,
+    }
+}
+
+#[derive(Debug)]
+pub enum PsiError {
+    /// The receiver's items did not fit in the cuckoo table and its stash.
+    StashOverflow,
+}
+
+impl Error for PsiError {}
+
+impl fmt::Display for PsiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StashOverflow => write!(f, "Cuckoo hashing overflowed the stash"),
+        }
+    }
+}
+
+#[inline]
+fn bin(seed: &Seed, h: usize, x: &[u8], bins: usize) -> usize {
+    let digest = hash!(seed, (h as u32).to_be_bytes(), x);
+    u64::from_be_bytes(digest[..8].try_into().unwrap()) as usize % bins
+}
+
+/// Input to the OPRF for `x` placed with hash function `h`, or `HASHES` in the stash.
+#[inline]
+fn tagged(x: &[u8], h: usize) -> Vec<u8> {
+    let mut input = x.to_vec();
+    input.push(h as u8);
+    input
+}
+
+#[inline]
+fn tag(output: &oprf::Output) -> Tag {
+    output[..TAG].try_into().unwrap()
+}
+
+/// Cuckoo hash `items` into `bins` bins followed by `stash` stash slots, every occupied slot
+/// holding an item index and the hash function used, `HASHES` in the stash.
+fn cuckoo<T: AsRef<[u8]>>(
+    items: &[T],
+    seed: &Seed,
+    bins: usize,
+    stash: usize,
+) -> Option<Vec<Option<(usize, usize)>>> {
+    let mut table = vec![None; bins + stash];
+    let mut stashed = 0;
+    for i in 0..items.len() {
+        // The item still looking for a bin, moving to its next hash function when evicted.
+        let mut homeless = Some((i, 0));
+        for _ in 0..=MAX_EVICTIONS {
+            let Some((j, h)) = homeless else { break };
+            let b = bin(seed, h, items[j].as_ref(), bins);
+            homeless = table[b].replace((j, h)).map(|(j, h)| (j, (h + 1) % HASHES));
+        }
+        if let Some((j, _)) = homeless {
+            if stashed == stash {
+                return None;
+            }
+            table[bins + stashed] = Some((j, HASHES));
+            stashed += 1;
+        }
+    }
+    Some(table)
+}
+
+#[derive(Default)]
+pub struct Sender {
+    pub oprf: oprf::Sender,
+}
+
+#[derive(Default)]
+pub struct Receiver {
+    pub oprf: oprf::Receiver,
+}
+
+impl Sender {
+    pub fn exchange<T: AsRef<[u8]> + Sync>(&self, items: &[T], ch: &Channel) -> Result<()> {
+        instrument::begin("PSI Sender", E_FUNC_COLOR);
+        let (s, r) = ch;
+
+        instrument::begin("Receive hash functions", E_RECV_COLOR);
+        let (seed, bins, stash): (Seed, u64, u64) = bincode::deserialize(&r.recv()?)?;
+        let (bins, stash) = (bins as usize, stash as usize);
+        instrument::end();
+
+        instrument::begin("OPRF", E_PROT_COLOR);
+        let key = self.oprf.exchange(ch)?;
+        instrument::end();
+        if bins == 0 || bins.checked_add(stash).is_none_or(|n| key.len() < n) {
+            return Err(Box::new(OTError::BadMessage("cuckoo table size")));
+        }
+
+        instrument::begin("Evaluate OPRF", E_COMP_COLOR);
+        let mut tags: Vec<Tag> = items
+            .par_iter()
+            .flat_map_iter(|x| {
+                let x = x.as_ref();
+                let key = &key;
+                let stashed = tagged(x, HASHES);
+                (0..HASHES)
+                    .map(move |h| tag(&key.eval(bin(&seed, h, x, bins), &tagged(x, h))))
+                    .chain((bins..bins + stash).map(move |j| tag(&key.eval(j, &stashed))))
+            })
+            .collect();
+        tags.shuffle(&mut rand::thread_rng());
+        instrument::end();
+
+        instrument::begin("Send tags", E_SEND_COLOR);
+        s.send(&tags.concat())?;
+        instrument::end();
+
+        instrument::end();
+        Ok(())
+    }
+}
+
+impl Receiver {
+    /// Returns the indices of the `items` also held by the sender, in increasing order.
+    ///
+    /// Fails with `PsiError::StashOverflow`, before anything is sent, in the unlikely case that
+    /// the items do not fit in the cuckoo table.
+    pub fn exchange<T: AsRef<[u8]> + Sync>(&self, items: &[T], ch: &Channel) -> Result<Vec<usize>> {
+        let n = items.len();
+        self.exchange_with(items, bins(n), stash(n), ch)
+    }
+
+    fn exchange_with<T: AsRef<[u8]> + Sync>(
+        &self,
+        items: &[T],
+        bins: usize,
+        stash: usize,
+        ch: &Channel,
+    ) -> Result<Vec<usize>> {
+        instrument::begin("PSI Receiver", E_FUNC_COLOR);
+        let (s, r) = ch;
+        let mut random = rand::thread_rng();
+
+        instrument::begin("Cuckoo hashing", E_COMP_COLOR);
+        let seed: Seed = random.gen();
+        let table = cuckoo(items, &seed, bins, stash).ok_or(PsiError::StashOverflow)?;
+        // Empty bins get random inputs, which never match the sender's.
+        let inputs: Vec<Vec<u8>> = table
+            .iter()
+            .map(|entry| match entry {
+                Some((i, h)) => tagged(items[*i].as_ref(), *h),
+                None => random.gen::<Seed>().to_vec(),
+            })
+            .collect();
+        instrument::end();
+
+        instrument::begin("Send hash functions", E_SEND_COLOR);
+        s.send(&bincode::serialize(&(seed, bins as u64, stash as u64))?)?;
+        instrument::end();
+
+        instrument::begin("OPRF", E_PROT_COLOR);
+        let outputs = self.oprf.exchange(&inputs, ch)?;
+        instrument::end();
+
+        instrument::begin("Receive tags", E_RECV_COLOR);
+        let tags = r.recv()?;
+        instrument::end();
+        if tags.len() % TAG != 0 {
+            return Err(Box::new(OTError::BadMessage("PSI tags")));
+        }
+
+        instrument::begin("Intersect", E_COMP_COLOR);
+        let tags: HashSet<Tag> = tags
+            .chunks_exact(TAG)
+            .map(|t| t.try_into().unwrap())
+            .collect();
+        let mut intersection: Vec<usize> = table
+            .iter()
+            .zip(&outputs)
+            .filter_map(|(entry, output)| match entry {
+                Some((i, _)) if tags.contains(&tag(output)) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        intersection.sort_unstable();
+        instrument::end();
+
+        instrument::end();
+        Ok(intersection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn intersect(sender: Vec<u32>, receiver: Vec<u32>) -> Vec<usize> {
+        let n = receiver.len();
+        intersect_with(sender, receiver, bins(n), stash(n))
+    }
+
+    fn intersect_with(
+        sender: Vec<u32>,
+        receiver: Vec<u32>,
+        bins: usize,
+        stash: usize,
+    ) -> Vec<usize> {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let h1 = thread::spawn(move || {
+            let items: Vec<_> = sender.iter().map(|x| x.to_be_bytes()).collect();
+            Sender::default().exchange(&items, &ch1).unwrap()
+        });
+        let h2 = thread::spawn(move || {
+            let items: Vec<_> = receiver.iter().map(|x| x.to_be_bytes()).collect();
+            Receiver::default()
+                .exchange_with(&items, bins, stash, &ch2)
+                .unwrap()
+        });
+        h1.join().unwrap();
+        h2.join().unwrap()
+    }
+
+    #[test]
+    fn test_psi() {
+        let sender: Vec<u32> = (0..1000).map(|x| x * 3).collect();
+        let receiver: Vec<u32> = (0..1000).map(|x| x * 5).collect();
+        let expected: Vec<usize> = (0..1000)
+            .filter(|i| i * 5 % 3 == 0 && i * 5 < 3000)
+            .collect();
+        assert_eq!(intersect(sender, receiver), expected);
+    }
+
+    #[test]
+    fn test_psi_disjoint_and_empty() {
+        assert_eq!(
+            intersect(vec![1, 2, 3], vec![4, 5, 6, 7]),
+            Vec::<usize>::new()
+        );
+        assert_eq!(intersect(vec![], vec![4, 5]), Vec::<usize>::new());
+        assert_eq!(intersect(vec![4, 5], vec![]), Vec::<usize>::new());
+        assert_eq!(intersect(vec![9], vec![9]), vec![0]);
+    }
+
+    #[test]
+    fn test_psi_stash() {
+        // Five bins for twelve items, so at least seven end up in the stash.
+        let sender: Vec<u32> = (0..12).map(|x| x * 2).collect();
+        let receiver: Vec<u32> = (0..12).map(|x| x * 3).collect();
+        let expected = vec![0, 2, 4, 6];
+        assert_eq!(intersect_with(sender, receiver, 5, 12), expected);
+    }
+
+    #[test]
+    fn test_cuckoo() {
+        let items: Vec<[u8; 4]> = (0..5000u32).map(|x| x.to_be_bytes()).collect();
+        let seed = [7; 32];
+        let bins = bins(items.len());
+        let table = cuckoo(&items, &seed, bins, stash(items.len())).unwrap();
+        let mut placed: Vec<usize> = table.iter().flatten().map(|(i, _)| *i).collect();
+        placed.sort_unstable();
+        assert_eq!(placed, (0..5000).collect::<Vec<_>>());
+        for (b, entry) in table.iter().enumerate() {
+            match entry {
+                Some((i, h)) if b < bins => assert_eq!(bin(&seed, *h, &items[*i], bins), b),
+                Some((_, h)) => assert_eq!(*h, HASHES),
+                None => {}
+            }
+        }
+
+        // More items than bins and stash slots.
+        assert!(cuckoo(&items[..10], &seed, 4, 2).is_none());
+        let table = cuckoo(&items[..10], &seed, 4, 6).unwrap();
+        assert!(table[4..].iter().all(Option::is_some));
+    }
+}
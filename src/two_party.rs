@@ -0,0 +1,510 @@
+// Generic two-party garbling.
+//
+// The garbler garbles a circuit and sends it to the evaluator together with the labels for its
+// own inputs. The labels for the evaluator's inputs are transferred obliviously, binary inputs
+// in a single batch of apricot OTs and inputs in larger domains with a 1-of-m OT each.
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit::dsl::Program;
+use crate::circuit::Circuit;
+use crate::common::*;
+use crate::garble::*;
+use crate::instrument;
+use crate::instrument::E_PROT_COLOR;
+use crate::ot::apricot;
+use crate::ot::chou_orlandi;
+use crate::ot::common::Message as MessagePair;
+use crate::ot::common::*;
+use crate::ot::one_of_many::{ManyOTReceiver, ManyOTSender};
+use crate::util::*;
+use crate::wires::{Domain, Wire};
+
+#[derive(Debug)]
+pub enum GarbleError {
+    /// The garbled circuit is not the circuit the evaluator expected.
+    CircuitMismatch,
+    /// Wrong number of inputs, actual and expected.
+    InputCount(usize, usize),
+    /// Input value outside of the domain of its wire, wire and value.
+    InputRange(usize, u16),
+    /// A transferred label has the wrong length.
+    MalformedLabel,
+    /// Wrong number of outputs, actual and expected.
+    OutputCount(usize, usize),
+    /// An input belongs to neither the garbler nor the evaluator.
+    UnknownParty(String),
+}
+
+impl Error for GarbleError {}
+impl fmt::Display for GarbleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CircuitMismatch => write!(f, "Garbled circuit does not match the circuit"),
+            Self::InputCount(a, b) => write!(f, "Got {a} inputs, but expected {b}"),
+            Self::InputRange(w, x) => write!(f, "Input {x} out of range for wire {w}"),
+            Self::MalformedLabel => write!(f, "Malformed input label"),
+            Self::OutputCount(a, b) => write!(f, "Got {a} outputs, but expected {b}"),
+            Self::UnknownParty(p) => write!(f, "Unknown party `{p}`"),
+        }
+    }
+}
+
+/// Obliviously send the labels for the evaluator's input `wires`.
+pub fn send_labels(e: &EncodingKey, wires: &[usize], ch: &Channel) -> Result<()> {
+    let (binary, other): (Vec<usize>, Vec<usize>) =
+        wires.iter().copied().partition(|&w| e.domain(w) == 2);
+
+    if !binary.is_empty() {
+        let pairs: Vec<[WireBytes; 2]> = binary
+            .iter()
+            .map(|&w| {
+                [
+                    e.encode_wire(w, 0).as_bytes(),
+                    e.encode_wire(w, 1).as_bytes(),
+                ]
+            })
+            .collect();
+        let ot = apricot::Sender {
+            bootstrap: Box::new(chou_orlandi::Receiver),
+        };
+        ot.exchange(&MessagePair::from_zipped(&pairs), ch)?;
+    }
+
+    for w in other {
+        let messages: Vec<Vec<u8>> = e.labels(w).iter().map(|l| l.as_bytes().to_vec()).collect();
+        let ot = ManyOTSender {
+            interal_sender: Box::new(chou_orlandi::Sender),
+        };
+        ot.exchange(&messages, ch)?;
+    }
+    Ok(())
+}
+
+/// Obliviously receive the labels for inputs `x` in the given `domains`.
+pub fn receive_labels(domains: &[u16], x: &[u16], ch: &Channel) -> Result<Vec<Wire>> {
+    debug_assert_eq!(domains.len(), x.len());
+    let to_wire = |bytes: &[u8], domain: u16| {
+        if bytes.len() != LENGTH {
+            return Err(GarbleError::MalformedLabel);
+        }
+        Ok(Wire::from_bytes(bytes, Domain::new(domain)))
+    };
+
+    let mut labels = vec![None; x.len()];
+    let binary: Vec<usize> = (0..x.len()).filter(|&i| domains[i] == 2).collect();
+    if !binary.is_empty() {
+        let choices: Vec<bool> = binary.iter().map(|&i| x[i] == 1).collect();
+        let ot = apricot::Receiver {
+            bootstrap: Box::new(chou_orlandi::Sender),
+        };
+        let payload = ot.exchange(&choices, ch)?;
+        for (&i, bytes) in binary.iter().zip(&payload) {
+            labels[i] = Some(to_wire(bytes, 2)?);
+        }
+    }
+
+    for i in (0..x.len()).filter(|&i| domains[i] != 2) {
+        let ot = ManyOTReceiver {
+            internal_receiver: Box::new(chou_orlandi::Receiver),
+        };
+        let bytes = ot.exchange(x[i] as u32, domains[i] as u32, ch)?;
+        labels[i] = Some(to_wire(&bytes, domains[i])?);
+    }
+    Ok(labels.into_iter().map(Option::unwrap).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Party {
+    Garbler,
+    Evaluator,
+}
+
+/// Which parties learn an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reveal {
+    Garbler,
+    Evaluator,
+    Both,
+}
+
+impl Reveal {
+    const fn to(self, party: Party) -> bool {
+        matches!(
+            (self, party),
+            (Self::Both, _) | (Self::Garbler, Party::Garbler) | (Self::Evaluator, Party::Evaluator)
+        )
+    }
+}
+
+/// A circuit computed between a garbler and an evaluator.
+///
+/// Every input of the circuit is owned by one of the parties, and every output is revealed to
+/// one or both of them. Outputs revealed to the garbler are sent back as labels by the evaluator,
+/// so the garbler learns nothing but the value of those outputs.
+#[derive(Debug, Clone)]
+pub struct TwoPartyComputation {
+    circuit: Circuit,
+    inputs: Vec<Party>,
+    outputs: Vec<Reveal>,
+}
+
+impl TwoPartyComputation {
+    /// * `inputs`: The owner of each input of the circuit.
+    /// * `outputs`: Who learns each output of the circuit.
+    pub fn new(
+        circuit: Circuit,
+        inputs: Vec<Party>,
+        outputs: Vec<Reveal>,
+    ) -> std::result::Result<Self, GarbleError> {
+        if inputs.len() != circuit.num_inputs {
+            return Err(GarbleError::InputCount(inputs.len(), circuit.num_inputs));
+        }
+        if outputs.len() != circuit.num_outputs {
+            return Err(GarbleError::OutputCount(outputs.len(), circuit.num_outputs));
+        }
+        Ok(Self {
+            circuit,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Use the parties declared by a compiled program, `garbler` or `evaluator`.
+    /// Every output is revealed to both parties.
+    pub fn from_program(program: &Program) -> std::result::Result<Self, GarbleError> {
+        let mut inputs = vec![Party::Garbler; program.circuit.num_inputs];
+        for input in &program.inputs {
+            let party = match input.party.as_str() {
+                "garbler" => Party::Garbler,
+                "evaluator" => Party::Evaluator,
+                p => return Err(GarbleError::UnknownParty(p.to_string())),
+            };
+            for w in input.wires.clone() {
+                inputs[w] = party;
+            }
+        }
+        let outputs = vec![Reveal::Both; program.circuit.num_outputs];
+        Self::new(program.circuit.clone(), inputs, outputs)
+    }
+
+    /// Reveal output `i` to `reveal` instead, failing with `OutputCount` if there is no such
+    /// output.
+    pub fn reveal(mut self, i: usize, reveal: Reveal) -> std::result::Result<Self, GarbleError> {
+        let n = self.outputs.len();
+        *self
+            .outputs
+            .get_mut(i)
+            .ok_or(GarbleError::OutputCount(i + 1, n))? = reveal;
+        Ok(self)
+    }
+
+    pub const fn circuit(&self) -> &Circuit {
+        &self.circuit
+    }
+
+    /// The input wires owned by `party`.
+    pub fn wires(&self, party: Party) -> Vec<usize> {
+        (0..self.inputs.len())
+            .filter(|&i| self.inputs[i] == party)
+            .collect()
+    }
+
+    /// The outputs revealed to `party`.
+    pub fn revealed(&self, party: Party) -> Vec<usize> {
+        (0..self.outputs.len())
+            .filter(|&i| self.outputs[i].to(party))
+            .collect()
+    }
+
+    fn check_inputs(&self, party: Party, x: &[u16]) -> std::result::Result<(), GarbleError> {
+        let wires = self.wires(party);
+        if wires.len() != x.len() {
+            return Err(GarbleError::InputCount(x.len(), wires.len()));
+        }
+        for (&w, &x) in wires.iter().zip(x) {
+            if x >= self.circuit.input_domains[w] {
+                return Err(GarbleError::InputRange(w, x));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run as the garbler with inputs `x` for the garbler's wires, in order.
+    ///
+    /// Returns the value of every output, `None` for outputs not revealed to the garbler.
+    pub fn garbler(&self, x: &[u16], ch: &Channel) -> Result<Vec<Option<u16>>> {
+        instrument::begin("Garbler", E_PROT_COLOR);
+        self.check_inputs(Party::Garbler, x)?;
+        let (gc, e, d) = garble(&self.circuit);
+        let (s, r) = ch;
+
+        s.send(&bincode::serialize(&gc)?)?;
+        let encoded: Vec<Wire> = self
+            .wires(Party::Garbler)
+            .into_iter()
+            .zip(x)
+            .map(|(w, &x)| e.encode_wire(w, x))
+            .collect();
+        s.send(&bincode::serialize(&encoded)?)?;
+        send_labels(&e, &self.wires(Party::Evaluator), ch)?;
+        s.send(&bincode::serialize(
+            &d.restrict(&self.revealed(Party::Evaluator)),
+        )?)?;
+
+        let mut y = vec![None; self.outputs.len()];
+        let ours = self.revealed(Party::Garbler);
+        if !ours.is_empty() {
+            let labels: Vec<Wire> = bincode::deserialize(&r.recv()?)?;
+            if labels.len() != ours.len() {
+                return Err(GarbleError::OutputCount(labels.len(), ours.len()).into());
+            }
+            for (&i, z) in ours.iter().zip(&labels) {
+                y[i] = Some(d.decode_output(i, z)?);
+            }
+        }
+
+        instrument::end();
+        Ok(y)
+    }
+
+    /// Run as the evaluator with inputs `y` for the evaluator's wires, in order.
+    ///
+    /// Returns the value of every output, `None` for outputs not revealed to the evaluator.
+    pub fn evaluator(&self, y: &[u16], ch: &Channel) -> Result<Vec<Option<u16>>> {
+        instrument::begin("Evaluator", E_PROT_COLOR);
+        self.check_inputs(Party::Evaluator, y)?;
+        let (s, r) = ch;
+
+        let gc: GarbledCircuit = bincode::deserialize(&r.recv()?)?;
+        if bincode::serialize(&gc.circuit)? != bincode::serialize(&self.circuit)? {
+            return Err(GarbleError::CircuitMismatch.into());
+        }
+        let theirs = self.wires(Party::Garbler);
+        let encoded: Vec<Wire> = bincode::deserialize(&r.recv()?)?;
+        if encoded.len() != theirs.len() {
+            return Err(GarbleError::InputCount(encoded.len(), theirs.len()).into());
+        }
+        let ours = self.wires(Party::Evaluator);
+        let domains: Vec<u16> = ours
+            .iter()
+            .map(|&w| self.circuit.input_domains[w])
+            .collect();
+        let received = receive_labels(&domains, y, ch)?;
+        let d: DecodingKey = bincode::deserialize(&r.recv()?)?;
+
+        let mut input = vec![Wire::empty(); self.circuit.num_inputs];
+        for (w, label) in theirs
+            .into_iter()
+            .chain(ours)
+            .zip(encoded.into_iter().chain(received))
+        {
+            input[w] = label;
+        }
+        let z = evaluate(&gc, &input);
+
+        let mut output = vec![None; self.outputs.len()];
+        for i in self.revealed(Party::Evaluator) {
+            output[i] = Some(d.decode_output(i, &z[i])?);
+        }
+        let labels: Vec<&Wire> = self
+            .revealed(Party::Garbler)
+            .iter()
+            .map(|&i| &z[i])
+            .collect();
+        if !labels.is_empty() {
+            s.send(&bincode::serialize(&labels)?)?;
+        }
+
+        instrument::end();
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::*;
+    use std::thread;
+
+    /// `x + y mod 7` and whether the sum of the bits `b` is below 2.
+    fn mixed_circuit() -> Circuit {
+        let gates = vec![
+            Gate {
+                kind: GateKind::Add,
+                inputs: vec![0, 1],
+                output: 5,
+                domain: 7,
+            },
+            Gate {
+                kind: GateKind::Proj(ProjKind::Map(4)),
+                inputs: vec![2],
+                output: 6,
+                domain: 2,
+            },
+            Gate {
+                kind: GateKind::Proj(ProjKind::Map(4)),
+                inputs: vec![3],
+                output: 7,
+                domain: 2,
+            },
+            Gate {
+                kind: GateKind::Proj(ProjKind::Map(4)),
+                inputs: vec![4],
+                output: 8,
+                domain: 2,
+            },
+            Gate {
+                kind: GateKind::Add,
+                inputs: vec![6, 7, 8],
+                output: 9,
+                domain: 4,
+            },
+            Gate {
+                kind: GateKind::Proj(ProjKind::Less(2)),
+                inputs: vec![9],
+                output: 10,
+                domain: 4,
+            },
+            Gate {
+                kind: GateKind::Add,
+                inputs: vec![5],
+                output: 11,
+                domain: 7,
+            },
+        ];
+        let circuit = Circuit {
+            num_wires: 12,
+            num_inputs: 5,
+            num_outputs: 2,
+            gates,
+            input_domains: vec![7, 7, 2, 2, 2],
+        };
+        verify_circuit(&circuit).unwrap();
+        circuit
+    }
+
+    /// The mixed circuit with the first `n` inputs held by the garbler.
+    fn mixed(n: usize) -> TwoPartyComputation {
+        let mut inputs = vec![Party::Evaluator; 5];
+        inputs[..n].fill(Party::Garbler);
+        TwoPartyComputation::new(mixed_circuit(), inputs, vec![Reveal::Both; 2]).unwrap()
+    }
+
+    #[test]
+    fn test_non_binary_evaluator_inputs() {
+        for (x, y) in [(3, 6), (0, 0), (6, 1)] {
+            let (_, e) = compute(mixed(1), vec![x], vec![y, 1, 0, 1]);
+            assert_eq!(e, vec![Some(0), Some((x + y) % 7)]);
+        }
+        let (g, e) = compute(mixed(1), vec![2], vec![4, 0, 0, 1]);
+        assert_eq!(g, vec![Some(1), Some(6)]);
+        assert_eq!(e, vec![Some(1), Some(6)]);
+    }
+
+    #[test]
+    fn test_garbler_only_output() {
+        let tpc = mixed(3)
+            .reveal(0, Reveal::Garbler)
+            .and_then(|tpc| tpc.reveal(1, Reveal::Garbler))
+            .unwrap();
+        let (g, e) = compute(tpc, vec![5, 1, 0], vec![1, 0]);
+        assert_eq!(g, vec![Some(1), Some(6)]);
+        assert_eq!(e, vec![None, None]);
+    }
+
+    #[test]
+    fn test_input_errors() {
+        let (ch, _) = raw::local_channel_pair();
+        let err = mixed(1).garbler(&[7], &ch).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GarbleError>(),
+            Some(GarbleError::InputRange(0, 7))
+        ));
+        let err = mixed(1).evaluator(&[0; 6], &ch).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GarbleError>(),
+            Some(GarbleError::InputCount(6, 4))
+        ));
+    }
+
+    fn compute(
+        tpc: TwoPartyComputation,
+        x: Vec<u16>,
+        y: Vec<u16>,
+    ) -> (Vec<Option<u16>>, Vec<Option<u16>>) {
+        let (ch1, ch2) = raw::local_channel_pair();
+        let garbler = tpc.clone();
+        let h1 = thread::spawn(move || garbler.garbler(&x, &ch1).unwrap());
+        let h2 = thread::spawn(move || tpc.evaluator(&y, &ch2).unwrap());
+        (h1.join().unwrap(), h2.join().unwrap())
+    }
+
+    #[test]
+    fn test_comparison() {
+        // x >= y, computed as x - y mod 32 < 16.
+        let program = dsl::compile(
+            "
+            input y: mod 16 from evaluator;
+            input x: mod 16 from garbler;
+            output proj(x, 32) + proj(y, 32) * 31 < 16;
+        ",
+        )
+        .unwrap();
+        let tpc = TwoPartyComputation::from_program(&program).unwrap();
+        assert_eq!(tpc.wires(Party::Evaluator), vec![0]);
+        for (x, y) in [(3, 9), (9, 3), (7, 7), (0, 15), (15, 0)] {
+            let expected = Some((x >= y) as u16);
+            let (g, e) = compute(tpc.clone(), vec![x], vec![y]);
+            assert_eq!(g, vec![expected]);
+            assert_eq!(e, vec![expected]);
+        }
+    }
+
+    #[test]
+    fn test_equality_reveal() {
+        let program = dsl::compile(
+            "
+            input x: bit[4] from garbler;
+            input y: bit[4] from evaluator;
+            output sum i in 0..4 { proj(x[i] xor y[i], 5) } < 1;
+            output x[0] and y[0];
+        ",
+        )
+        .unwrap();
+        let tpc = TwoPartyComputation::from_program(&program)
+            .and_then(|tpc| tpc.reveal(0, Reveal::Garbler))
+            .and_then(|tpc| tpc.reveal(1, Reveal::Evaluator))
+            .unwrap();
+        let (g, e) = compute(tpc.clone(), vec![1, 0, 1, 1], vec![1, 0, 1, 1]);
+        assert_eq!(g, vec![Some(1), None]);
+        assert_eq!(e, vec![None, Some(1)]);
+        let (g, e) = compute(tpc, vec![1, 0, 1, 1], vec![0, 0, 1, 1]);
+        assert_eq!(g, vec![Some(0), None]);
+        assert_eq!(e, vec![None, Some(0)]);
+    }
+
+    #[test]
+    fn test_partition_errors() {
+        let circuit = mixed_circuit();
+        assert!(matches!(
+            TwoPartyComputation::new(circuit.clone(), vec![Party::Garbler; 4], vec![]),
+            Err(GarbleError::InputCount(4, 5))
+        ));
+        assert!(matches!(
+            TwoPartyComputation::new(circuit, vec![Party::Garbler; 5], vec![Reveal::Both]),
+            Err(GarbleError::OutputCount(1, 2))
+        ));
+        assert!(matches!(
+            mixed(1).reveal(2, Reveal::Garbler),
+            Err(GarbleError::OutputCount(3, 2))
+        ));
+        let program = dsl::compile("input x: bit from alice;\noutput x;").unwrap();
+        assert!(matches!(
+            TwoPartyComputation::from_program(&program),
+            Err(GarbleError::UnknownParty(p)) if p == "alice"
+        ));
+    }
+}
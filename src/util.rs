@@ -2,7 +2,17 @@ use num_traits::PrimInt;
 use rand::{Rng, RngCore};
 
 // Global Constants
+/// Length of wire labels in bits, 128 with the `label128` feature and 256 otherwise.
+///
+/// This is the entropy of a label only in the binary, mod 256 and mod 2^16 domains. Any other
+/// label mod m is [`LENGTH`] digits mod m, or `LENGTH / 2` if m > 256, and has `log2(m)` bits
+/// per digit, one digit of which is the public color. With `label128` a mod 3 label has about
+/// 25 bits and a mod 5 label about 37, and even with 256-bit labels a mod 3 label has about 51,
+/// so circuits relying on small moduli get less than `SECURITY_PARAM` bits of security.
+#[cfg(not(feature = "label128"))]
 pub const SECURITY_PARAM: usize = 256; // bits used total
+#[cfg(feature = "label128")]
+pub const SECURITY_PARAM: usize = 128; // bits used total
 pub const LENGTH: usize = SECURITY_PARAM / 8; // bytes used
 pub type WireBytes = [u8; LENGTH];
 
@@ -21,6 +31,8 @@ pub fn log2<N: PrimInt>(x: N) -> u32 {
     (std::mem::size_of::<N>() * 8) as u32 - (x - N::one()).leading_zeros()
 }
 
+/// Unpack bytes LSB-first, bit `j` being bit `j % 8` of byte `j / 8`. This is the order of
+/// password bits in the circuits and of choice bits in [`crate::ot::common::ObliviousReceiver`].
 pub fn u8_vec_to_bool_vec(str: &[u8]) -> Vec<bool> {
     let mut bits = Vec::with_capacity(8 * str.len());
     for s in str {
@@ -31,6 +43,8 @@ pub fn u8_vec_to_bool_vec(str: &[u8]) -> Vec<bool> {
     bits
 }
 
+/// Pack bits MSB-first, so this is *not* the inverse of [`u8_vec_to_bool_vec`], use
+/// [`pack_bits`] for that. Trailing bits that do not fill a byte are dropped.
 pub fn bool_vec_to_u8_vec(str: &[bool]) -> Vec<u8> {
     let mut bytes = Vec::with_capacity(str.len() / 8);
     let mut byte = 0;
@@ -46,6 +60,27 @@ pub fn bool_vec_to_u8_vec(str: &[bool]) -> Vec<u8> {
     bytes
 }
 
+/// Pack bits LSB-first, the inverse of [`u8_vec_to_bool_vec`]. The last byte is padded with
+/// zeros.
+pub fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; bits.len().div_ceil(8)];
+    for (i, &bit) in bits.iter().enumerate() {
+        bytes[i / 8] |= (bit as u8) << (i % 8);
+    }
+    bytes
+}
+
+/// The first `len` bits of `bytes` unpacked LSB-first.
+///
+/// # Panics
+/// If `bytes` holds fewer than `len` bits.
+pub fn unpack_bits(bytes: &[u8], len: usize) -> Vec<bool> {
+    assert!(len <= bytes.len() * 8, "Not enough bytes for {} bits", len);
+    (0..len)
+        .map(|i| (bytes[i / 8] >> (i % 8)) & 1 == 1)
+        .collect()
+}
+
 pub fn to_array(bytes: &[u8]) -> WireBytes {
     debug_assert!(bytes.len() == LENGTH, "Should be {} bytes", LENGTH);
     let mut array = [0u8; LENGTH];
@@ -87,6 +122,17 @@ macro_rules! hash {
 }
 pub use hash;
 
+/// Variadic hashing to a wire label.
+/// Same as `hash!` with the digest truncated to `LENGTH` bytes.
+#[macro_export]
+macro_rules! hash_label {
+    ($($ls:expr),+) => {{
+        let digest = $crate::util::hash!($($ls),+);
+        $crate::util::to_array(&digest[..$crate::util::LENGTH])
+    }};
+}
+pub use hash_label;
+
 pub fn xor(a: WireBytes, b: WireBytes) -> WireBytes {
     let mut result = [0u8; LENGTH];
     for i in 0..LENGTH {
@@ -158,8 +204,23 @@ mod tests {
         hasher.update("hello");
         hasher.update("world");
         let h2 = hasher.finalize();
-        let h2 = <WireBytes>::try_from(h2.as_ref()).expect("digest too long");
+        let h2 = <[u8; 32]>::try_from(h2.as_ref()).expect("digest too long");
         assert_eq!(h1, h2);
+
+        let l1: WireBytes = hash_label!("hello", "world");
+        assert_eq!(l1[..], h2[..LENGTH]);
+    }
+
+    #[test]
+    fn test_bit_order() {
+        let bits = u8_vec_to_bool_vec(&[0b0000_0110, 0b1000_0000]);
+        assert_eq!((0..16).filter(|&i| bits[i]).collect::<Vec<_>>(), [1, 2, 15]);
+        assert_eq!(pack_bits(&bits), [0b0000_0110, 0b1000_0000]);
+        assert_eq!(bool_vec_to_u8_vec(&bits), [0b0110_0000, 0b0000_0001]);
+
+        assert_eq!(pack_bits(&[true, false, true]), [0b101]);
+        assert_eq!(unpack_bits(&[0b101], 3), [true, false, true]);
+        assert!(pack_bits(&[]).is_empty());
     }
 
     #[test]
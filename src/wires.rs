@@ -32,7 +32,7 @@ impl Domain {
         }
     }
 
-    fn new(m: u16) -> Self {
+    pub(crate) fn new(m: u16) -> Self {
         const U8_MAX: u16 = u8::max_value() as u16;
         const U16_MAX: u16 = u16::max_value();
         if m == 0 {
@@ -274,6 +274,15 @@ impl Wire {
     pub const fn as_bytes(&self) -> WireBytes {
         self.values
     }
+
+    /// Size in bytes of a serialized wire in the given domain.
+    pub(crate) fn serialized_size(domain: u16) -> usize {
+        let wire = Self {
+            domain: Domain::new(domain),
+            values: [0; LENGTH],
+        };
+        bincode::serialized_size(&wire).unwrap() as usize
+    }
 }
 
 impl AsRef<[u8]> for Wire {
@@ -287,7 +296,7 @@ pub fn hash_wire(index: usize, wire: &Wire, target: &Wire) -> Wire {
     hasher.update(index.to_be_bytes());
     hasher.update(wire);
     let digest = hasher.finalize(); // TODO: use variable size hashing
-    let bytes = <WireBytes>::try_from(digest.as_ref()).expect("digest too long");
+    let bytes = to_array(&digest[..LENGTH]);
 
     // Makes values for the wire of target size from the output of the hash function, recall that
     // the hash function outputs 256 bits, which means that the number of values * the number of
@@ -296,5 +305,5 @@ pub fn hash_wire(index: usize, wire: &Wire, target: &Wire) -> Wire {
 }
 
 pub fn hash(index: usize, x: u16, wire: &Wire) -> WireBytes {
-    hash!(index.to_be_bytes(), x.to_be_bytes(), wire)
+    hash_label!(index.to_be_bytes(), x.to_be_bytes(), wire)
 }